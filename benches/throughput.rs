@@ -0,0 +1,58 @@
+//! Throughput of `query` against a local mock server.
+//!
+//! Compares clients sharing the default connection pool with clients that
+//! each bring their own `reqwest::Client`, as every `MoonDream` did before
+//! the pool was shared. Run with `cargo bench --bench throughput`.
+
+use futures::StreamExt;
+use moondream::MoonDream;
+use std::time::{Duration, Instant};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const REQUESTS: usize = 2000;
+const CONCURRENCY: usize = 32;
+const IMAGE: &str = "data:image/png;base64,iVBORw0KGgo=";
+
+async fn run(name: &str, client: impl Fn() -> MoonDream) {
+    let start = Instant::now();
+    let failures = futures::stream::iter(0..REQUESTS)
+        .map(|_| {
+            let md = client();
+            async move { md.query(IMAGE, "What is this?").await.is_err() }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .filter(|failed| std::future::ready(*failed))
+        .count()
+        .await;
+    let elapsed = start.elapsed();
+
+    println!(
+        "{name:<12} {:>8.0} req/s  {:>6.1} ms total  {failures} failures",
+        REQUESTS as f64 / elapsed.as_secs_f64(),
+        elapsed.as_secs_f64() * 1000.0,
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "answer": "a cat" }))
+                .set_delay(Duration::from_millis(2)),
+        )
+        .mount(&server)
+        .await;
+    let endpoint = server.uri();
+
+    let shared = MoonDream::local(&endpoint);
+    run("shared", || shared.clone()).await;
+    run("new client", || MoonDream::local(&endpoint)).await;
+    run("own pool", || {
+        MoonDream::local(&endpoint).with_client(reqwest::Client::new())
+    })
+    .await;
+}
@@ -0,0 +1,26 @@
+//! Query a local Moondream station from the browser.
+//!
+//! Build with `cargo build --example wasm --target wasm32-unknown-unknown`
+//! and load the output with `wasm-bindgen`. The station must allow the page
+//! origin through CORS.
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use moondream::MoonDream;
+    use web_sys::console;
+
+    wasm_bindgen_futures::spawn_local(async {
+        let md = MoonDream::local("http://localhost:2020/v1");
+        let image = "https://raw.githubusercontent.com/vikhyat/moondream/main/assets/demo-1.jpg";
+
+        match md.caption(image, None).await {
+            Ok(response) => console::log_1(&response.caption.into()),
+            Err(error) => console::error_1(&error.to_string().into()),
+        }
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eprintln!("build this example with `--target wasm32-unknown-unknown`");
+}
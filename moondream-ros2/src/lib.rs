@@ -0,0 +1,279 @@
+//! ROS 2 node running [Moondream](moondream) on image topics.
+//!
+//! A [`Ros2Bridge`] subscribes to a `sensor_msgs/Image` topic, runs a
+//! [`FrameOperation`] on the received images with any [`VisionClient`] and
+//! publishes the results on an output topic. Results are `std_msgs/String`
+//! messages holding a JSON object with the `stamp` and `frame_id` of the
+//! source image and either the `output` of the operation, serialized as a
+//! [`FrameOutput`], or an `error`.
+//!
+//! Images arriving while a request is in flight are dropped, so a slow
+//! network never builds up a backlog of stale frames. The supported
+//! encodings are `rgb8`, `bgr8`, `rgba8`, `bgra8` and `mono8`.
+//!
+//! The crate needs the `rclrs`, `sensor_msgs` and `std_msgs` crates of a
+//! sourced ROS 2 workspace, as set up by `colcon`. The message crates are not
+//! published on crates.io, so neither is this crate.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::video::FrameOperation;
+//! use moondream_ros2::Ros2Bridge;
+//!
+//! # fn run() -> Result<(), moondream_ros2::Error> {
+//! let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+//! let context = rclrs::Context::new(std::env::args())?;
+//! let node = Ros2Bridge::new(MoonDream::remote("token"), FrameOperation::Detect("person".into()))
+//!     .with_input_topic("/camera/image_raw")
+//!     .with_output_topic("/moondream/people")
+//!     .spawn(&context, runtime.handle().clone())?;
+//! node.spin()
+//! # }
+//! ```
+
+use derive_new::new;
+use derive_setters::Setters;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage, RgbaImage};
+use moondream::video::{FrameOperation, FrameOutput};
+use moondream::{ImageInput, VisionClient};
+use rclrs::{Context, Node, QOS_PROFILE_DEFAULT, QOS_PROFILE_SENSOR_DATA, Subscription};
+use sensor_msgs::msg::Image;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Error of a [`Ros2Bridge`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The node could not be set up or run.
+    #[error("ros2: {0}")]
+    Ros(#[from] rclrs::RclrsError),
+
+    /// Error of the client.
+    #[error(transparent)]
+    MoonDream(#[from] moondream::Error),
+}
+
+/// Runs a [`FrameOperation`] on the images of a ROS 2 topic, see the
+/// [crate documentation](crate).
+#[derive(Debug, Clone, new, Setters)]
+#[setters(prefix = "with_", into)]
+pub struct Ros2Bridge<C> {
+    #[setters(skip)]
+    client: C,
+
+    #[setters(skip)]
+    operation: FrameOperation,
+
+    /// Name of the node.
+    #[new(value = "String::from(\"moondream\")")]
+    node_name: String,
+
+    /// Topic of the `sensor_msgs/Image` messages to analyze.
+    #[new(value = "String::from(\"image\")")]
+    input_topic: String,
+
+    /// Topic the results are published on.
+    #[new(value = "String::from(\"moondream/result\")")]
+    output_topic: String,
+}
+
+/// A running [`Ros2Bridge`] node.
+pub struct Ros2Node {
+    node: Arc<Node>,
+    // The node only holds weak references to its subscriptions.
+    _subscription: Arc<Subscription<Image>>,
+}
+
+impl std::fmt::Debug for Ros2Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ros2Node")
+            .field("name", &self.node.name())
+            .finish()
+    }
+}
+
+impl Ros2Node {
+    /// The underlying node, for example to add it to an executor.
+    pub fn node(&self) -> &Arc<Node> {
+        &self.node
+    }
+
+    /// Process messages until the context is shut down.
+    pub fn spin(self) -> Result<(), Error> {
+        Ok(rclrs::spin(self.node.clone())?)
+    }
+}
+
+#[derive(Serialize)]
+struct ResultMessage<'a> {
+    stamp: Stamp,
+    frame_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<FrameOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Stamp {
+    sec: i32,
+    nanosec: u32,
+}
+
+impl<C: VisionClient + 'static> Ros2Bridge<C> {
+    /// Create the node, subscribe to the input topic and run the requests
+    /// on `runtime`.
+    pub fn spawn(
+        self,
+        context: &Context,
+        runtime: tokio::runtime::Handle,
+    ) -> Result<Ros2Node, Error> {
+        let node = rclrs::create_node(context, &self.node_name)?;
+        let publisher = node
+            .create_publisher::<std_msgs::msg::String>(&self.output_topic, QOS_PROFILE_DEFAULT)?;
+        let busy = Arc::new(AtomicBool::new(false));
+        let bridge = Arc::new(self);
+
+        let subscription = node.create_subscription::<Image, _>(
+            &bridge.input_topic,
+            QOS_PROFILE_SENSOR_DATA,
+            move |message: Image| {
+                if busy.swap(true, Ordering::AcqRel) {
+                    return;
+                }
+                let (bridge, publisher, busy) = (bridge.clone(), publisher.clone(), busy.clone());
+                runtime.spawn(async move {
+                    let result = bridge.process(&message).await;
+                    busy.store(false, Ordering::Release);
+                    let (output, error) = match result {
+                        Ok(output) => (Some(output), None),
+                        Err(error) => (None, Some(error.to_string())),
+                    };
+                    let result = ResultMessage {
+                        stamp: Stamp {
+                            sec: message.header.stamp.sec,
+                            nanosec: message.header.stamp.nanosec,
+                        },
+                        frame_id: &message.header.frame_id,
+                        output,
+                        error,
+                    };
+                    if let Ok(data) = serde_json::to_string(&result) {
+                        let _ = publisher.publish(std_msgs::msg::String { data });
+                    }
+                });
+            },
+        )?;
+
+        Ok(Ros2Node {
+            node,
+            _subscription: subscription,
+        })
+    }
+
+    async fn process(&self, message: &Image) -> Result<FrameOutput, moondream::Error> {
+        let png = encode_frame(
+            message.width,
+            message.height,
+            &message.encoding,
+            message.step,
+            &message.data,
+        )?;
+        let image = ImageInput::bytes(png, "image/png").into_url()?;
+        match &self.operation {
+            FrameOperation::Points(object) => self
+                .client
+                .points(image, object.clone())
+                .await
+                .map(FrameOutput::Points),
+            FrameOperation::Detect(object) => self
+                .client
+                .detect(image, object.clone())
+                .await
+                .map(FrameOutput::Detect),
+            FrameOperation::Caption(length) => self
+                .client
+                .caption(image, *length)
+                .await
+                .map(FrameOutput::Caption),
+            FrameOperation::Query(question) => self
+                .client
+                .query(image, question.clone())
+                .await
+                .map(FrameOutput::Query),
+        }
+    }
+}
+
+/// Encode the pixels of a `sensor_msgs/Image` as PNG.
+fn encode_frame(
+    width: u32,
+    height: u32,
+    encoding: &str,
+    step: u32,
+    data: &[u8],
+) -> Result<Vec<u8>, moondream::Error> {
+    let channels = match encoding {
+        "mono8" => 1,
+        "rgb8" | "bgr8" => 3,
+        "rgba8" | "bgra8" => 4,
+        _ => {
+            return Err(moondream::Error::InvalidImage(format!(
+                "unsupported image encoding {encoding:?}"
+            )));
+        }
+    };
+    let row = width as usize * channels;
+    let step = step as usize;
+    if step < row || data.len() < step * height.saturating_sub(1) as usize + row {
+        return Err(moondream::Error::InvalidImage(
+            "image data shorter than its size".into(),
+        ));
+    }
+    let mut pixels: Vec<u8> = (0..height as usize)
+        .flat_map(|y| &data[y * step..y * step + row])
+        .copied()
+        .collect();
+    if encoding.starts_with("bgr") {
+        pixels
+            .chunks_exact_mut(channels)
+            .for_each(|pixel| pixel.swap(0, 2));
+    }
+
+    let invalid = || moondream::Error::InvalidImage("image data does not match its size".into());
+    let image = match channels {
+        1 => DynamicImage::from(GrayImage::from_raw(width, height, pixels).ok_or_else(invalid)?),
+        3 => DynamicImage::from(RgbImage::from_raw(width, height, pixels).ok_or_else(invalid)?),
+        _ => DynamicImage::from(RgbaImage::from_raw(width, height, pixels).ok_or_else(invalid)?),
+    };
+    let mut png = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| moondream::Error::InvalidImage(e.to_string()))?;
+    Ok(png.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moondream::Error;
+
+    #[test]
+    fn test_encode_frame_with_padding() {
+        // 2x1 bgr8 image with 2 bytes of row padding.
+        let png = encode_frame(2, 1, "bgr8", 8, &[255, 0, 0, 0, 0, 255, 9, 9]).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 0, 0]);
+
+        assert!(matches!(
+            encode_frame(2, 1, "rgb16", 12, &[0; 12]),
+            Err(Error::InvalidImage(_))
+        ));
+        assert!(matches!(
+            encode_frame(2, 2, "mono8", 2, &[0; 3]),
+            Err(Error::InvalidImage(_))
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+//! How the client authenticates its requests.
+//!
+//! The hosted API expects the token in the `X-Moondream-Auth` header.
+//! Deployments behind a reverse proxy or API gateway often expect it
+//! elsewhere; [`MoonDream::with_auth`](crate::MoonDream::with_auth) selects
+//! where the token goes.
+//!
+//! ```
+//! use moondream::{Auth, MoonDream};
+//!
+//! // `Authorization: Bearer secret`
+//! let md = MoonDream::remote("secret")
+//!     .with_endpoint("https://vision.example.com/v1")
+//!     .with_auth(Auth::Bearer);
+//!
+//! // `https://vision.example.com/v1/query?sig=...`
+//! let md = MoonDream::remote("signed-value")
+//!     .with_endpoint("https://vision.example.com/v1")
+//!     .with_auth(Auth::Query("sig".into()));
+//! ```
+
+use crate::Error;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+
+/// Where the client sends its credentials.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Auth {
+    /// Send the token in the `X-Moondream-Auth` header (default).
+    #[default]
+    MoondreamHeader,
+    /// Send the token as `Authorization: Bearer <token>`.
+    Bearer,
+    /// Send the token as the query parameter with this name, for gateways
+    /// checking signed URLs.
+    Query(String),
+    /// Send this header name and value, ignoring the token.
+    Custom(String, String),
+    /// Send no credentials.
+    None,
+}
+
+impl Auth {
+    /// Add the credentials for `token` to `headers`, returning the query
+    /// parameter to add to every URL, if any.
+    pub(crate) fn apply(
+        &self,
+        token: &str,
+        headers: &mut HeaderMap,
+    ) -> Result<Option<(String, String)>, Error> {
+        let (name, value) = match self {
+            Auth::MoondreamHeader => (
+                HeaderName::from_static("x-moondream-auth"),
+                token.to_string(),
+            ),
+            Auth::Bearer => (AUTHORIZATION, format!("Bearer {token}")),
+            Auth::Custom(name, value) => {
+                let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                    Error::InvalidConfig(format!("authentication header {name:?}: {e}"))
+                })?;
+                (name, value.clone())
+            }
+            Auth::Query(name) => return Ok(Some((name.clone(), token.to_string()))),
+            Auth::None => return Ok(None),
+        };
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|e| Error::InvalidConfig(format!("authentication header value: {e}")))?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Auth::Bearer.apply("secret", &mut headers).unwrap(), None);
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+        assert!(headers[AUTHORIZATION].is_sensitive());
+
+        let query = Auth::Query("sig".into()).apply("signed", &mut headers);
+        assert_eq!(query.unwrap(), Some(("sig".into(), "signed".into())));
+
+        let custom = Auth::Custom("X-Api-Key".into(), "key".into());
+        custom.apply("ignored", &mut headers).unwrap();
+        assert_eq!(headers["x-api-key"], "key");
+
+        let invalid = Auth::Custom("X Api Key".into(), "key".into());
+        assert!(matches!(
+            invalid.apply("token", &mut headers),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! Results and aggregate metrics of a batch of calls.
+//!
+//! A [`BatchResult`] collects the outcome of many `*_with_meta` calls and
+//! summarizes them in [`BatchMetrics`]: retries, time spent backing off,
+//! cache hit rate and latency percentiles. Its `Display` form is a one-line
+//! summary for job logs.
+//!
+//! ```no_run
+//! use moondream::batch::BatchResult;
+//! use moondream::MoonDream;
+//!
+//! # async fn run(md: MoonDream, images: Vec<String>) {
+//! let calls = images.iter().map(|image| md.caption_with_meta(image.as_str(), None));
+//! let batch = BatchResult::new(futures::future::join_all(calls).await);
+//! // 120 calls, 2 failed, 14 retries, 3.2s backing off, 25% cache hits, p50 410ms, p95 2s
+//! println!("{}", batch.metrics);
+//! # }
+//! ```
+
+use crate::usage::EndpointUsage;
+use crate::{ApiResponse, Error, ResponseMeta};
+use std::fmt;
+use std::time::Duration;
+
+/// Outcome of every call of a batch, with their [`BatchMetrics`].
+#[derive(Debug)]
+pub struct BatchResult<T> {
+    /// Result of each call, in the order they were given.
+    pub results: Vec<Result<ApiResponse<T>, Error>>,
+    /// Metrics aggregated over `results`.
+    pub metrics: BatchMetrics,
+}
+
+impl<T> BatchResult<T> {
+    /// Collect `results` and compute their metrics.
+    pub fn new(results: impl IntoIterator<Item = Result<ApiResponse<T>, Error>>) -> Self {
+        let results: Vec<_> = results.into_iter().collect();
+        let mut metrics = BatchMetrics::default();
+        for result in &results {
+            metrics.record(result);
+        }
+        Self { results, metrics }
+    }
+
+    /// Iterate over the successful responses.
+    pub fn successes(&self) -> impl Iterator<Item = &ApiResponse<T>> {
+        self.results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+    }
+
+    /// Iterate over the errors.
+    pub fn failures(&self) -> impl Iterator<Item = &Error> {
+        self.results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+    }
+}
+
+/// Aggregate metrics of a batch of calls.
+///
+/// Retries, backoff, cache hits and latencies are taken from the
+/// [`ResponseMeta`] of the successful calls; failed calls are only counted.
+/// Their retries and backoff are recorded by the client instead, and added
+/// with [`BatchMetrics::record_failed_usage`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchMetrics {
+    calls: usize,
+    failures: usize,
+    retries: u64,
+    backoff: Duration,
+    cache_hits: usize,
+    latencies: Vec<Duration>,
+}
+
+impl BatchMetrics {
+    /// Add the outcome of one call.
+    pub fn record<T>(&mut self, result: &Result<ApiResponse<T>, Error>) {
+        match result {
+            Ok(response) => self.record_meta(&response.meta),
+            Err(_) => {
+                self.calls += 1;
+                self.failures += 1;
+            }
+        }
+    }
+
+    /// Add the metadata of one successful call.
+    pub fn record_meta(&mut self, meta: &ResponseMeta) {
+        self.calls += 1;
+        self.retries += u64::from(meta.attempt.saturating_sub(1));
+        self.backoff += meta.backoff;
+        if meta.cached {
+            self.cache_hits += 1;
+        }
+        let index = self
+            .latencies
+            .partition_point(|&latency| latency <= meta.latency);
+        self.latencies.insert(index, meta.latency);
+    }
+
+    /// Add the retries and backoff of the failed calls recorded in `usage`,
+    /// such as the [`UsageSnapshot::total`](crate::UsageSnapshot::total) of
+    /// [`MoonDream::reset_usage`](crate::MoonDream::reset_usage) called
+    /// before and after the batch.
+    pub fn record_failed_usage(&mut self, usage: &EndpointUsage) {
+        self.retries += usage.failed_retries;
+        self.backoff += Duration::from_millis(usage.failed_backoff_ms);
+    }
+
+    /// Number of calls recorded.
+    pub fn calls(&self) -> usize {
+        self.calls
+    }
+
+    /// Number of failed calls.
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    /// Retries performed by the successful calls, and by the failed calls
+    /// once [recorded](BatchMetrics::record_failed_usage).
+    pub fn retries(&self) -> u64 {
+        self.retries
+    }
+
+    /// Time the calls spent waiting between attempts, as for
+    /// [`BatchMetrics::retries`].
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Number of calls served from the [`Cache`](crate::Cache).
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
+
+    /// Share of the calls served from the cache, from 0 to 1.
+    pub fn cache_hit_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.cache_hits as f64 / self.calls as f64
+        }
+    }
+
+    /// Latency below which a `percentile` (0-100) of the successful calls
+    /// completed, or `None` without successful calls.
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.latencies.len() as f64).ceil();
+        let index = (rank as usize).clamp(1, self.latencies.len().max(1)) - 1;
+        self.latencies.get(index).copied()
+    }
+
+    /// Median latency of the successful calls.
+    pub fn p50(&self) -> Option<Duration> {
+        self.latency_percentile(50.0)
+    }
+
+    /// 95th percentile latency of the successful calls.
+    pub fn p95(&self) -> Option<Duration> {
+        self.latency_percentile(95.0)
+    }
+}
+
+impl fmt::Display for BatchMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} calls, {} failed, {} retries, {:.1?} backing off, {:.0}% cache hits",
+            self.calls,
+            self.failures,
+            self.retries,
+            self.backoff,
+            self.cache_hit_rate() * 100.0
+        )?;
+        if let (Some(p50), Some(p95)) = (self.p50(), self.p95()) {
+            write!(f, ", p50 {p50:.0?}, p95 {p95:.0?}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaptionResponse, MoonDream};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut metrics = BatchMetrics::default();
+        assert_eq!(metrics.p50(), None);
+        metrics.latencies = (1..=20).rev().map(Duration::from_millis).collect();
+        metrics.latencies.sort();
+        assert_eq!(metrics.p50(), Some(Duration::from_millis(10)));
+        assert_eq!(metrics.p95(), Some(Duration::from_millis(19)));
+        assert_eq!(
+            metrics.latency_percentile(0.0),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(
+            metrics.latency_percentile(100.0),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_metrics() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "a cat",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_retry(crate::RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(10)))
+            .with_cache(crate::Cache::memory(10));
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            results.push(
+                md.caption_with_meta("data:image/png;base64,AAA", None)
+                    .await,
+            );
+        }
+        results.push(Err::<ApiResponse<CaptionResponse>, _>(Error::Cancelled));
+
+        let down = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&down)
+            .await;
+        let failed = md
+            .clone()
+            .with_endpoint(down.uri())
+            .with_usage_tracker(crate::UsageTracker::new());
+        let result = failed.caption_with_meta("img", None).await;
+        assert!(matches!(&result, Err(Error::Status { .. })));
+        results.push(result);
+
+        let mut batch = BatchResult::new(results);
+        assert_eq!((batch.metrics.calls(), batch.metrics.failures()), (4, 2));
+        assert_eq!(batch.metrics.retries(), 1);
+        batch
+            .metrics
+            .record_failed_usage(&failed.reset_usage().total());
+        let metrics = &batch.metrics;
+        assert_eq!(metrics.retries(), 3);
+        assert!(metrics.backoff() >= Duration::from_millis(30));
+        assert_eq!(metrics.cache_hits(), 1);
+        assert_eq!(batch.successes().count(), 2);
+        assert!(
+            metrics
+                .to_string()
+                .starts_with("4 calls, 2 failed, 3 retries")
+        );
+    }
+}
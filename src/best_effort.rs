@@ -0,0 +1,234 @@
+//! Time-boxed calls for real-time loops.
+//!
+//! [`MoonDream::detect_within`] waits at most `budget` for a detection and
+//! never fails: when the API is slow or returns an error, it falls back on
+//! the last detection of the same object by this client (or one of its
+//! clones), marked [`BestEffort::stale`], or on `None` if there is none yet.
+//! A request still running when the budget expires keeps going in the
+//! background and its result becomes the fallback of the next call. Calls
+//! made meanwhile for the same object return the fallback at once instead of
+//! sending another request, so a render loop can call it once per frame
+//! without piling up requests:
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use std::time::Duration;
+//!
+//! # async fn run(md: MoonDream, frames: Vec<Vec<u8>>) {
+//! for frame in frames {
+//!     let image = moondream::ImageInput::bytes(frame, "image/jpeg");
+//!     match md.detect_within(image, "person", Duration::from_millis(30)).await {
+//!         Some(detected) if detected.stale => { /* draw the previous boxes, dimmed */ }
+//!         Some(detected) => { /* draw detected.data.objects */ }
+//!         None => { /* nothing detected yet */ }
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! Responses served by the [`Cache`](crate::Cache) come back fresh and fast.
+
+use crate::{DetectResponse, ImageInput, MoonDream, rt};
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Result of a time-boxed call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BestEffort<T> {
+    /// Response of the call, or an earlier one when `stale`.
+    pub data: T,
+    /// `true` when `data` answers an earlier call, because this one did not
+    /// complete within its budget.
+    pub stale: bool,
+}
+
+impl<T> BestEffort<T> {
+    /// Return the response, fresh or not.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+/// Last detection of each object, and whether a request for it is running,
+/// shared between clones of a client.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecentDetections(Arc<Mutex<HashMap<String, Detection>>>);
+
+#[derive(Debug, Default)]
+struct Detection {
+    last: Option<DetectResponse>,
+    pending: bool,
+}
+
+impl RecentDetections {
+    fn get(&self, object: &str) -> Option<DetectResponse> {
+        self.lock()
+            .get(object)
+            .and_then(|detection| detection.last.clone())
+    }
+
+    /// Mark a request for `object` as running, or return `None` if one
+    /// already is.
+    fn start(&self, object: &str) -> Option<Pending> {
+        let mut detections = self.lock();
+        let detection = detections.entry(object.to_string()).or_default();
+        if detection.pending {
+            return None;
+        }
+        detection.pending = true;
+        Some(Pending {
+            detections: self.clone(),
+            object: object.to_string(),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Detection>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Running request for an object, cleared when dropped.
+struct Pending {
+    detections: RecentDetections,
+    object: String,
+}
+
+impl Pending {
+    fn finish(self, response: DetectResponse) {
+        if let Some(detection) = self.detections.lock().get_mut(&self.object) {
+            detection.last = Some(response);
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(detection) = self.detections.lock().get_mut(&self.object) {
+            detection.pending = false;
+        }
+    }
+}
+
+impl MoonDream {
+    /// Detect `object`, waiting at most `budget`, see the
+    /// [module documentation](crate::best_effort).
+    pub async fn detect_within(
+        &self,
+        image: impl Into<ImageInput>,
+        object: impl Into<String>,
+        budget: Duration,
+    ) -> Option<BestEffort<DetectResponse>> {
+        let object = object.into();
+        let stale = || {
+            self.recent_detections
+                .get(&object)
+                .map(|data| BestEffort { data, stale: true })
+        };
+        let Some(pending) = self.recent_detections.start(&object) else {
+            return stale();
+        };
+        let Ok(image) = self.prepare_image(image) else {
+            return stale();
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        let (md, label) = (self.clone(), object.clone());
+        rt::spawn(async move {
+            let body = md.object_request(image, label);
+            if let Ok(response) = md.send::<DetectResponse>("detect", body).await {
+                let response = response.into_inner();
+                pending.finish(response.clone());
+                let _ = sender.send(response);
+            }
+        });
+
+        let deadline = std::pin::pin!(rt::sleep(budget));
+        match future::select(receiver, deadline).await {
+            Either::Left((Ok(data), _)) => Some(BestEffort { data, stale: false }),
+            _ => stale(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn objects(x_min: f64) -> serde_json::Value {
+        serde_json::json!({
+            "objects": [{"x_min": x_min, "y_min": 0.0, "x_max": 1.0, "y_max": 1.0}],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_detect_within_falls_back_on_last_result() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(objects(0.1)))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(objects(0.2))
+                    .set_delay(Duration::from_millis(300)),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let budget = Duration::from_secs(5);
+        let fresh = md.detect_within("img", "person", budget).await.unwrap();
+        assert!(!fresh.stale);
+        assert_eq!(fresh.data.objects[0].x_min, 0.1);
+
+        // The API is now slower than the budget.
+        let budget = Duration::from_millis(20);
+        let stale = md.detect_within("img", "person", budget).await.unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.data.objects[0].x_min, 0.1);
+        assert!(md.detect_within("img", "car", budget).await.is_none());
+
+        // The late response replaces the fallback.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let stale = md.detect_within("img", "person", budget).await.unwrap();
+        assert_eq!(stale.data.objects[0].x_min, 0.2);
+    }
+
+    #[tokio::test]
+    async fn test_detect_within_sends_one_request_per_object() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(objects(0.1))
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let budget = Duration::from_millis(5);
+        for _ in 0..20 {
+            assert!(md.detect_within("img", "person", budget).await.is_none());
+        }
+        assert!(md.detect_within("img", "car", budget).await.is_none());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+
+        // Once the request completes, the next call sends a new one.
+        let stale = md.detect_within("img", "person", budget).await.unwrap();
+        assert!(stale.stale);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,701 @@
+//! Command line client for one-off calls (feature `cli`).
+//!
+//! ```text
+//! MOONDREAM_API_KEY=... moondream-cli caption photo.jpg --length short
+//! moondream-cli --endpoint http://localhost:2020/v1 detect photo.jpg person --output json
+//! moondream-cli detect photo.jpg car --filter "confidence > 0.5 && area < 0.1"
+//! ```
+//!
+//! # Configuration
+//!
+//! `moondream-cli init` asks for the API key, checks it with
+//! [`MoonDream::verify_token`] and saves it with the endpoint to
+//! `$XDG_CONFIG_HOME/moondream/config.json` (`~/.config` on Unix,
+//! `%APPDATA%` on Windows), or to `--config`. Flags and environment
+//! variables take precedence over the file.
+//!
+//! # Watch mode
+//!
+//! `moondream-cli watch <dir> --op detect --object person` processes every
+//! image added to `dir` and appends one JSON line per image to
+//! `--results` (`results.jsonl` by default):
+//! `{"schema_version": 1, "command": "detect", "file": ..., "result": ...}`,
+//! or `"error"` instead of `"result"` when the call failed.
+//!
+//! `moondream-cli completions <shell>` prints a completion script, for
+//! example `moondream-cli completions bash > /etc/bash_completion.d/moondream-cli`.
+//!
+//! # Output
+//!
+//! `--output` selects the format, `text` by default:
+//!
+//! - `json`: one document `{"schema_version": 1, "command": ..., "result": ...}`
+//!   where `result` is the serialized API response, or `null` for a caption
+//!   or answer rejected by `--filter`.
+//! - `jsonl`: one line per row, each with `schema_version`, `command` and the
+//!   row fields.
+//! - `csv`: a header line followed by one line per row.
+//! - `table`: aligned columns for terminals.
+//!
+//! Rows are the detected objects (`x_min`, `y_min`, `x_max`, `y_max`,
+//! `confidence`), the points (`x`, `y`, `confidence`), or a single caption
+//! (`request_id`, `caption`) or answer (`request_id`, `answer`).
+//! [`SCHEMA_VERSION`] is bumped whenever a field is renamed or removed.
+//!
+//! # Exit codes
+//!
+//! - `0`: at least one result.
+//! - `1`: the call failed.
+//! - `2`: invalid arguments.
+//! - `3`: no result, i.e. nothing was detected or `--filter` rejected
+//!   everything. The (empty) output is still printed.
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use futures::StreamExt;
+use moondream::export::{ExportFormat, ExportWriter};
+use moondream::{CaptionLength, ImageInput, MoonDream, Predicate};
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Version of the `json`, `jsonl` and `csv` output schema.
+const SCHEMA_VERSION: u32 = 1;
+
+/// Exit code when the call succeeded without any result.
+const EXIT_NO_RESULTS: u8 = 3;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "moondream-cli",
+    version,
+    about = "Call the Moondream API from the command line"
+)]
+struct Cli {
+    /// API endpoint, for example a local deployment. Defaults to the hosted API.
+    #[arg(long, env = "MOONDREAM_ENDPOINT", global = true)]
+    endpoint: Option<String>,
+
+    /// API key. Required for the hosted API.
+    #[arg(long, env = "MOONDREAM_API_KEY", hide_env_values = true, global = true)]
+    api_key: Option<String>,
+
+    /// Configuration file. Defaults to `moondream/config.json` in the user
+    /// configuration directory.
+    #[arg(long, env = "MOONDREAM_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = Output::Text, global = true)]
+    output: Output,
+
+    /// Shorthand for `--output json`.
+    #[arg(long, global = true, conflicts_with = "output")]
+    json: bool,
+
+    /// Keep only results matching the expression, for example
+    /// "confidence > 0.5 && area < 0.1" or "answer contains 'yes'".
+    #[arg(long, global = true)]
+    filter: Option<Predicate>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Describe an image.
+    Caption {
+        /// Image path or URL.
+        image: String,
+        /// Length of the caption.
+        #[arg(long, value_enum, default_value_t = Length::Normal)]
+        length: Length,
+    },
+    /// Ask a question about an image.
+    Query {
+        /// Image path or URL.
+        image: String,
+        /// Question to ask.
+        question: String,
+    },
+    /// Detect bounding boxes of an object.
+    Detect {
+        /// Image path or URL.
+        image: String,
+        /// Object to detect.
+        object: String,
+    },
+    /// Locate the centre points of an object.
+    Point {
+        /// Image path or URL.
+        image: String,
+        /// Object to locate.
+        object: String,
+    },
+    /// Process images added to a directory.
+    Watch {
+        /// Directory to watch.
+        dir: PathBuf,
+        /// Operation run on each new image.
+        #[arg(long, value_enum)]
+        op: WatchOp,
+        /// Object to detect or locate.
+        #[arg(long, required_if_eq_any([("op", "detect"), ("op", "point")]))]
+        object: Option<String>,
+        /// Question to ask.
+        #[arg(long, required_if_eq("op", "query"))]
+        question: Option<String>,
+        /// JSON Lines file the results are appended to.
+        #[arg(long, default_value = "results.jsonl")]
+        results: PathBuf,
+        /// Also process the images already in the directory.
+        #[arg(long)]
+        existing: bool,
+    },
+    /// Save the endpoint and API key to the configuration file.
+    Init {
+        /// Do not check the API key with the server.
+        #[arg(long)]
+        no_verify: bool,
+    },
+    /// Print a shell completion script.
+    Completions {
+        /// Target shell.
+        shell: Shell,
+    },
+}
+
+/// Content of the configuration file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Config {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    endpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Length {
+    Short,
+    Normal,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum WatchOp {
+    Caption,
+    Query,
+    Detect,
+    Point,
+}
+
+impl WatchOp {
+    fn name(&self) -> &'static str {
+        match self {
+            WatchOp::Caption => "caption",
+            WatchOp::Query => "query",
+            WatchOp::Detect => "detect",
+            WatchOp::Point => "point",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// Human readable text.
+    Text,
+    /// A single JSON document.
+    Json,
+    /// One JSON object per line.
+    Jsonl,
+    /// Comma-separated values with a header.
+    Csv,
+    /// Aligned columns.
+    Table,
+}
+
+/// Result of a command, ready to be printed.
+struct Report {
+    command: &'static str,
+    result: Value,
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Value>>,
+    text: String,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut cli = Cli::parse();
+    if cli.json {
+        cli.output = Output::Json;
+    }
+    match run(cli).await {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let config_path = cli.config.or_else(default_config_path);
+    let command = match cli.command {
+        Command::Completions { shell } => {
+            let mut command = Cli::command();
+            clap_complete::generate(shell, &mut command, "moondream-cli", &mut std::io::stdout());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Init { no_verify } => {
+            let path =
+                config_path.ok_or("cannot locate the configuration directory, use --config")?;
+            init(&path, cli.endpoint, cli.api_key, no_verify).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        command => command,
+    };
+
+    let config = match &config_path {
+        Some(path) if path.exists() => serde_json::from_slice(&std::fs::read(path)?)?,
+        _ => Config::default(),
+    };
+    let md = client(
+        cli.endpoint.or(config.endpoint),
+        cli.api_key.or(config.api_key),
+    )?;
+
+    let report = match command {
+        Command::Caption { image, length } => {
+            let length = match length {
+                Length::Short => CaptionLength::Short,
+                Length::Normal => CaptionLength::Normal,
+            };
+            let response = md.caption(image_input(&image)?, Some(length)).await?;
+            let result = serde_json::to_value(&response)?;
+            let (result, rows) = if matches_filter(cli.filter.as_ref(), &result) {
+                let row = vec![json!(response.request_id), json!(response.caption)];
+                (result, vec![row])
+            } else {
+                (Value::Null, Vec::new())
+            };
+            Report {
+                command: "caption",
+                result,
+                columns: &["request_id", "caption"],
+                rows,
+                text: response.caption,
+            }
+        }
+        Command::Query { image, question } => {
+            let response = md.query(image_input(&image)?, question).await?;
+            let result = serde_json::to_value(&response)?;
+            let (result, rows) = if matches_filter(cli.filter.as_ref(), &result) {
+                let row = vec![json!(response.request_id), json!(response.answer)];
+                (result, vec![row])
+            } else {
+                (Value::Null, Vec::new())
+            };
+            Report {
+                command: "query",
+                result,
+                columns: &["request_id", "answer"],
+                rows,
+                text: response.answer,
+            }
+        }
+        Command::Detect { image, object } => {
+            let mut response = md.detect(image_input(&image)?, object).await?;
+            if let Some(filter) = &cli.filter {
+                response = filter.filter_detections(response);
+            }
+            let rows = response
+                .objects
+                .iter()
+                .map(|o| {
+                    vec![
+                        json!(o.x_min),
+                        json!(o.y_min),
+                        json!(o.x_max),
+                        json!(o.y_max),
+                        json!(o.confidence),
+                    ]
+                })
+                .collect();
+            let text = response
+                .objects
+                .iter()
+                .map(|o| {
+                    format!(
+                        "({:.3}, {:.3}) - ({:.3}, {:.3})",
+                        o.x_min, o.y_min, o.x_max, o.y_max
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Report {
+                command: "detect",
+                result: serde_json::to_value(&response)?,
+                columns: &["x_min", "y_min", "x_max", "y_max", "confidence"],
+                rows,
+                text,
+            }
+        }
+        Command::Point { image, object } => {
+            let mut response = md.points(image_input(&image)?, object).await?;
+            if let Some(filter) = &cli.filter {
+                response = filter.filter_points(response);
+            }
+            let rows = response
+                .points
+                .iter()
+                .map(|p| vec![json!(p.x), json!(p.y), json!(p.confidence)])
+                .collect();
+            let text = response
+                .points
+                .iter()
+                .map(|p| format!("({:.3}, {:.3})", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Report {
+                command: "point",
+                result: serde_json::to_value(&response)?,
+                columns: &["x", "y", "confidence"],
+                rows,
+                text,
+            }
+        }
+        Command::Watch {
+            dir,
+            op,
+            object,
+            question,
+            results,
+            existing,
+        } => {
+            let writer = ExportWriter::new(results, ExportFormat::Jsonl)
+                .with_append(true)
+                .with_flush_every(1);
+            let task = WatchTask {
+                op,
+                object: object.unwrap_or_default(),
+                question: question.unwrap_or_default(),
+                filter: cli.filter,
+            };
+            watch(&md, &dir, &task, writer, existing).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Init { .. } | Command::Completions { .. } => unreachable!("handled above"),
+    };
+
+    print_report(&report, cli.output)?;
+    if report.rows.is_empty() {
+        Ok(ExitCode::from(EXIT_NO_RESULTS))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn client(endpoint: Option<String>, api_key: Option<String>) -> Result<MoonDream, &'static str> {
+    match (endpoint, api_key) {
+        (Some(endpoint), key) => {
+            Ok(MoonDream::new(key.unwrap_or_default()).with_endpoint(endpoint))
+        }
+        (None, Some(key)) => Ok(MoonDream::remote(key)),
+        (None, None) => Err("set MOONDREAM_API_KEY or --endpoint, or run `moondream-cli init`"),
+    }
+}
+
+/// Ask for missing settings, check the API key and write the configuration.
+async fn init(
+    path: &Path,
+    endpoint: Option<String>,
+    api_key: Option<String>,
+    no_verify: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let api_key = match api_key {
+        Some(key) => Some(key),
+        // Local deployments usually do not need a key.
+        None if endpoint.is_some() => None,
+        None => Some(prompt("API key: ")?),
+    };
+
+    if !no_verify {
+        let md = client(endpoint.clone(), api_key.clone())?;
+        if !md.verify_token().await? {
+            return Err("the API key was rejected by the server".into());
+        }
+    }
+
+    let config = Config { endpoint, api_key };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(&serde_json::to_vec_pretty(&config)?)?;
+    eprintln!("wrote {}", path.display());
+    Ok(())
+}
+
+/// Read one trimmed line from stdin after printing `label` to stderr.
+fn prompt(label: &str) -> std::io::Result<String> {
+    eprint!("{label}");
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// `moondream/config.json` in the user configuration directory.
+fn default_config_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("moondream").join("config.json"))
+}
+
+fn print_report(report: &Report, output: Output) -> Result<(), serde_json::Error> {
+    match output {
+        Output::Text => {
+            if !report.rows.is_empty() && !report.text.is_empty() {
+                println!("{}", report.text);
+            }
+        }
+        Output::Json => {
+            let document = json!({
+                "schema_version": SCHEMA_VERSION,
+                "command": report.command,
+                "result": report.result,
+            });
+            println!("{}", serde_json::to_string_pretty(&document)?);
+        }
+        Output::Jsonl => {
+            for row in &report.rows {
+                let mut line = serde_json::Map::new();
+                line.insert("schema_version".into(), json!(SCHEMA_VERSION));
+                line.insert("command".into(), json!(report.command));
+                for (column, value) in report.columns.iter().zip(row) {
+                    line.insert(column.to_string(), value.clone());
+                }
+                println!("{}", Value::Object(line));
+            }
+        }
+        Output::Csv => {
+            println!("{}", report.columns.join(","));
+            for row in &report.rows {
+                let cells: Vec<String> = row.iter().map(|value| csv_cell(&cell(value))).collect();
+                println!("{}", cells.join(","));
+            }
+        }
+        Output::Table => {
+            let cells: Vec<Vec<String>> = report
+                .rows
+                .iter()
+                .map(|row| row.iter().map(cell).collect())
+                .collect();
+            let widths: Vec<usize> = report
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    cells
+                        .iter()
+                        .map(|row| row[i].chars().count())
+                        .fold(column.len(), usize::max)
+                })
+                .collect();
+            let line = |row: Vec<String>| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(value, width)| format!("{value:<width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            };
+            println!(
+                "{}",
+                line(report.columns.iter().map(|c| c.to_string()).collect())
+            );
+            for row in cells {
+                println!("{}", line(row));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Return `true` without a filter or when `result` matches it.
+fn matches_filter(filter: Option<&Predicate>, result: &Value) -> bool {
+    filter.is_none_or(|filter| filter.matches(result))
+}
+
+/// Render a value without JSON quoting; `null` becomes an empty cell.
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Quote a CSV cell when needed.
+fn csv_cell(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// What `watch` does with each image.
+struct WatchTask {
+    op: WatchOp,
+    object: String,
+    question: String,
+    filter: Option<Predicate>,
+}
+
+impl WatchTask {
+    async fn run(&self, md: &MoonDream, path: &Path) -> Result<Value, moondream::Error> {
+        let image = ImageInput::from_path(path)?;
+        let result = match self.op {
+            WatchOp::Caption => serde_json::to_value(md.caption(image, None).await?),
+            WatchOp::Query => serde_json::to_value(md.query(image, &self.question).await?),
+            WatchOp::Detect => {
+                let mut response = md.detect(image, &self.object).await?;
+                if let Some(filter) = &self.filter {
+                    response = filter.filter_detections(response);
+                }
+                serde_json::to_value(response)
+            }
+            WatchOp::Point => {
+                let mut response = md.points(image, &self.object).await?;
+                if let Some(filter) = &self.filter {
+                    response = filter.filter_points(response);
+                }
+                serde_json::to_value(response)
+            }
+        };
+        Ok(result?)
+    }
+}
+
+/// Process the images added to `dir` until the watcher fails.
+async fn watch(
+    md: &MoonDream,
+    dir: &Path,
+    task: &WatchTask,
+    mut writer: ExportWriter,
+    existing: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, mut events) = futures::channel::mpsc::unbounded();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let _ = tx.unbounded_send(event);
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    eprintln!("watching {}", dir.display());
+
+    let mut seen = HashSet::new();
+    if existing {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        for path in paths {
+            if is_image(&path) && seen.insert(path.clone()) {
+                process(md, &path, task, &mut writer).await?;
+            }
+        }
+    }
+
+    while let Some(event) = events.next().await {
+        let event = event?;
+        let added = matches!(
+            event.kind,
+            EventKind::Create(_)
+                | EventKind::Modify(ModifyKind::Name(_))
+                | EventKind::Access(AccessKind::Close(AccessMode::Write))
+        );
+        if !added {
+            continue;
+        }
+        for path in event.paths {
+            if is_image(&path) && path.is_file() && seen.insert(path.clone()) {
+                wait_until_written(&path).await;
+                process(md, &path, task, &mut writer).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `task` on `path` and append the result or the error to `writer`.
+async fn process(
+    md: &MoonDream,
+    path: &Path,
+    task: &WatchTask,
+    writer: &mut ExportWriter,
+) -> Result<(), moondream::Error> {
+    let mut record = json!({
+        "schema_version": SCHEMA_VERSION,
+        "command": task.op.name(),
+        "file": path.display().to_string(),
+    });
+    match task.run(md, path).await {
+        Ok(result) => record["result"] = result,
+        Err(error) => {
+            eprintln!("{}: {error}", path.display());
+            record["error"] = json!(error.to_string());
+        }
+    }
+    writer.write(&record)
+}
+
+/// Wait, up to 10 s, until the size of `path` stops changing, so files
+/// still being copied into the directory are not read half-written.
+async fn wait_until_written(path: &Path) {
+    let size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).ok();
+    let mut last = size(path);
+    for _ in 0..40 {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let current = size(path);
+        if current.is_none() || (current == last && current != Some(0)) {
+            return;
+        }
+        last = current;
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            matches!(
+                extension.to_ascii_lowercase().as_str(),
+                "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp" | "tif" | "tiff"
+            )
+        })
+}
+
+/// Treat URLs and data URIs as such and anything else as a file path.
+fn image_input(image: &str) -> Result<ImageInput, moondream::Error> {
+    if ["http://", "https://", "data:"]
+        .iter()
+        .any(|prefix| image.starts_with(prefix))
+    {
+        Ok(ImageInput::url(image))
+    } else {
+        ImageInput::from_path(image)
+    }
+}
@@ -0,0 +1,771 @@
+//! Response caching.
+//!
+//! A [`Cache`] installed with [`MoonDream::with_cache`](crate::MoonDream::with_cache)
+//! stores the raw JSON body of every successful response, keyed by a SHA-256
+//! hash of the endpoint URL and the request payload (image and prompt).
+//! Identical calls are then answered without contacting the API.
+//!
+//! [`Cache::memory`] keeps entries in process memory and [`Cache::disk`] in
+//! files that survive restarts. Other backends (redis, ...) can be plugged in
+//! by implementing [`CacheStore`].
+//!
+//! With [`Cache::with_stale_while_revalidate`], an entry close to its expiry
+//! is still served, and a single background request refreshes it, so
+//! popular entries never expire for every caller at once. The window can be
+//! set per operation with [`Cache::with_stale_while_revalidate_for`]:
+//!
+//! ```
+//! use moondream::{Cache, MoonDream};
+//! use std::time::Duration;
+//!
+//! let cache = Cache::memory(1000)
+//!     .with_ttl(Duration::from_secs(3600))
+//!     .with_stale_while_revalidate(Duration::from_secs(300))
+//!     // Detections are cheap to recompute: let them expire.
+//!     .with_stale_while_revalidate_for("detect", Duration::ZERO);
+//! let md = MoonDream::remote("token").with_cache(cache);
+//! ```
+//!
+//! [`Cache::with_compression`] compresses entries before they reach the
+//! store and decompresses them on read, which keeps stores such as
+//! [`DiskStore`] small when annotating large datasets. `Zstd` (feature
+//! `zstd`) is provided; other codecs implement [`Compression`]. Entries that
+//! do not decompress, such as those written before compression was enabled,
+//! are treated as misses.
+
+use crate::rt::Instant;
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Storage backend used by [`Cache`].
+///
+/// Keys are lowercase hex SHA-256 digests and values are raw JSON response
+/// bodies.
+#[async_trait]
+pub trait CacheStore: std::fmt::Debug + Send + Sync {
+    /// Return the value stored under `key`, if present and not expired.
+    async fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Store `value` under `key`, expiring after `ttl` when set.
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>);
+
+    /// Same as [`CacheStore::get`], also returning the time left before the
+    /// entry expires, if known. Stores that do not know it never trigger
+    /// [`Cache::with_stale_while_revalidate`] refreshes.
+    async fn get_with_ttl(&self, key: &str) -> Option<(Vec<u8>, Option<Duration>)> {
+        self.get(key).await.map(|value| (value, None))
+    }
+}
+
+/// Share a store between caches, or keep a handle to inspect it.
+#[async_trait]
+impl<T: CacheStore + ?Sized> CacheStore for Arc<T> {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        (**self).get(key).await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        (**self).put(key, value, ttl).await
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> Option<(Vec<u8>, Option<Duration>)> {
+        (**self).get_with_ttl(key).await
+    }
+}
+
+/// Codec applied to the entries of a [`Cache`].
+pub trait Compression: std::fmt::Debug + Send + Sync {
+    /// Compress a response body before it is stored.
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
+
+    /// Restore a body compressed by [`Compression::compress`].
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
+}
+
+/// Zstandard [`Compression`] (feature `zstd`).
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zstd {
+    level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Zstd {
+    /// Compress at `level`, from 1 (fastest) to 22 (smallest).
+    pub fn new(level: i32) -> Self {
+        Self { level }
+    }
+
+    pub(crate) fn level(&self) -> i32 {
+        self.level
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    /// The default zstd level, 3.
+    fn default() -> Self {
+        Self::new(zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Compression for Zstd {
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::encode_all(data, self.level)
+    }
+
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::decode_all(data)
+    }
+}
+
+/// Response cache attached to a client.
+///
+/// ```
+/// use moondream::{Cache, MoonDream};
+/// use std::time::Duration;
+///
+/// let md = MoonDream::remote("token")
+///     .with_cache(Cache::memory(1000).with_ttl(Duration::from_secs(3600)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cache {
+    store: Arc<dyn CacheStore>,
+    ttl: Option<Duration>,
+    stale: Option<Duration>,
+    stale_for: HashMap<String, Duration>,
+    compression: Option<Arc<dyn Compression>>,
+    /// Keys being refreshed in the background.
+    refreshing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Cache {
+    /// Use a custom [`CacheStore`].
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: None,
+            stale: None,
+            stale_for: HashMap::new(),
+            compression: None,
+            refreshing: Arc::default(),
+        }
+    }
+
+    /// Keep up to `capacity` responses in memory, evicting the least
+    /// recently used entry when full.
+    ///
+    /// `capacity` counts entries, whatever their size. To bound the bytes
+    /// used instead, see [`DiskStore::with_max_bytes`].
+    pub fn memory(capacity: usize) -> Self {
+        Self::new(MemoryStore::new(capacity))
+    }
+
+    /// Keep responses in files under `dir`, see [`DiskStore`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn disk(dir: impl Into<PathBuf>) -> Self {
+        Self::new(DiskStore::new(dir))
+    }
+
+    /// Expire entries `ttl` after they were stored.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Compress entries with `compression`, see the
+    /// [module documentation](crate::cache).
+    pub fn with_compression(mut self, compression: impl Compression + 'static) -> Self {
+        self.compression = Some(Arc::new(compression));
+        self
+    }
+
+    /// Serve entries expiring within `window` while refreshing them in the
+    /// background, see the [module documentation](crate::cache).
+    pub fn with_stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.stale = Some(window);
+        self
+    }
+
+    /// Same as [`Cache::with_stale_while_revalidate`] for the calls to the
+    /// endpoint `path` only, such as `caption`. A zero `window` disables
+    /// refreshes for it.
+    pub fn with_stale_while_revalidate_for(
+        mut self,
+        path: impl Into<String>,
+        window: Duration,
+    ) -> Self {
+        self.stale_for.insert(path.into(), window);
+        self
+    }
+
+    /// Return the entry stored under `key` and whether the caller should
+    /// refresh it. Only one caller at a time is asked to refresh a key,
+    /// until [`Cache::refreshed`] is called.
+    pub(crate) async fn get(&self, key: &str, path: &str) -> Option<(Vec<u8>, bool)> {
+        let window = self
+            .stale_for
+            .get(path)
+            .copied()
+            .or(self.stale)
+            .filter(|window| !window.is_zero());
+        let Some(window) = window else {
+            let value = self.store.get(key).await?;
+            return Some((self.decompress(value)?, false));
+        };
+        let (value, expires_in) = self.store.get_with_ttl(key).await?;
+        let value = self.decompress(value)?;
+        let stale = expires_in.is_some_and(|expires_in| expires_in < window);
+        let refresh = stale && self.refreshing().insert(key.to_string());
+        Some((value, refresh))
+    }
+
+    fn decompress(&self, value: Vec<u8>) -> Option<Vec<u8>> {
+        match &self.compression {
+            Some(compression) => compression.decompress(&value).ok(),
+            None => Some(value),
+        }
+    }
+
+    /// Mark the background refresh of `key` as finished.
+    pub(crate) fn refreshed(&self, key: &str) {
+        self.refreshing().remove(key);
+    }
+
+    fn refreshing(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.refreshing.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) async fn put(&self, key: &str, value: Vec<u8>) {
+        let value = match &self.compression {
+            Some(compression) => match compression.compress(&value) {
+                Ok(compressed) => compressed,
+                Err(_) => return,
+            },
+            None => value,
+        };
+        self.store.put(key, value, self.ttl).await
+    }
+
+    /// Compute the cache key of a request to `url` with the given serialized
+    /// JSON payload.
+    pub fn key(url: &str, payload: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(url.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(payload);
+
+        hasher
+            .finalize()
+            .iter()
+            .fold(String::with_capacity(64), |mut key, byte| {
+                let _ = write!(key, "{byte:02x}");
+                key
+            })
+    }
+}
+
+/// In-memory [`CacheStore`] with a bounded number of entries.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    entries: HashMap<String, MemoryEntry>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+    last_used: u64,
+}
+
+impl MemoryStore {
+    /// Create a store holding at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(MemoryState::default()),
+        }
+    }
+
+    /// Number of entries currently stored, including expired ones not yet
+    /// evicted.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Return `true` if the store holds no entry.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.get_with_ttl(key).await.map(|(value, _)| value)
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> Option<(Vec<u8>, Option<Duration>)> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let now = Instant::now();
+
+        let expired = state
+            .entries
+            .get(key)?
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now);
+        if expired {
+            state.entries.remove(key);
+            return None;
+        }
+
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = tick;
+        let expires_in = entry.expires_at.map(|expires_at| expires_at - now);
+        Some((entry.value.clone(), expires_in))
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let now = Instant::now();
+
+        if !state.entries.contains_key(key) && state.entries.len() >= self.capacity {
+            state
+                .entries
+                .retain(|_, entry| entry.expires_at.is_none_or(|expires_at| expires_at > now));
+        }
+        if !state.entries.contains_key(key) && state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+
+        state.entries.insert(
+            key.to_string(),
+            MemoryEntry {
+                value,
+                expires_at: ttl.map(|ttl| now + ttl),
+                last_used: tick,
+            },
+        );
+    }
+}
+
+/// [`CacheStore`] keeping one file per entry in a directory.
+///
+/// Entries survive restarts. Expired entries are deleted when they are read
+/// or by [`DiskStore::sweep`]. With [`DiskStore::with_max_bytes`] or
+/// [`DiskStore::with_max_entries`], writes that exceed the limit sweep the
+/// directory and delete the least recently written entries. Combine with
+/// [`Cache::with_compression`] to store them compressed:
+///
+/// ```
+/// use moondream::cache::DiskStore;
+/// use moondream::{Cache, MoonDream};
+///
+/// let store = DiskStore::new("/var/cache/moondream").with_max_bytes(1 << 30);
+/// // With the `zstd` feature:
+/// // .with_compression(moondream::cache::Zstd::default())
+/// let md = MoonDream::remote("token").with_cache(Cache::new(store));
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    dir: PathBuf,
+    max_bytes: Option<u64>,
+    max_entries: Option<usize>,
+    /// Bytes and entries in `dir`, counted by the first sweep.
+    size: Arc<Mutex<Option<(u64, usize)>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiskStore {
+    /// Store entries in `dir`, created on the first write.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: None,
+            max_entries: None,
+            size: Arc::default(),
+        }
+    }
+
+    /// Keep the files of the entries under `max_bytes` in total.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Keep at most `max_entries` entries.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Directory holding the entries.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Delete the expired entries, then the least recently written ones
+    /// while the limits are exceeded. Returns the number of entries deleted.
+    pub async fn sweep(&self) -> std::io::Result<usize> {
+        let (dir, max_bytes, max_entries) = (self.dir.clone(), self.max_bytes, self.max_entries);
+        let (deleted, size) =
+            tokio::task::spawn_blocking(move || sweep(&dir, max_bytes, max_entries))
+                .await
+                .map_err(std::io::Error::other)??;
+        *self.size() = Some(size);
+        Ok(deleted)
+    }
+
+    /// Count an entry of `bytes` written over one of `replaced` bytes, if
+    /// any, and sweep when a limit is exceeded.
+    async fn written(&self, bytes: u64, replaced: Option<u64>) {
+        if self.max_bytes.is_none() && self.max_entries.is_none() {
+            return;
+        }
+        let exceeded = match &mut *self.size() {
+            // The directory may hold entries of an earlier run.
+            None => true,
+            Some((total, entries)) => {
+                *total = (*total + bytes).saturating_sub(replaced.unwrap_or(0));
+                *entries += usize::from(replaced.is_none());
+                self.max_bytes.is_some_and(|max| *total > max)
+                    || self.max_entries.is_some_and(|max| *entries > max)
+            }
+        };
+        if exceeded {
+            let _ = self.sweep().await;
+        }
+    }
+
+    fn size(&self) -> std::sync::MutexGuard<'_, Option<(u64, usize)>> {
+        self.size.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// A file holds the expiry of the entry, in milliseconds since the Unix epoch
+// or 0 if it never expires, as 8 big-endian bytes, followed by the value.
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl CacheStore for DiskStore {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.get_with_ttl(key).await.map(|(value, _)| value)
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> Option<(Vec<u8>, Option<Duration>)> {
+        let path = self.dir.join(key);
+        let mut data = tokio::fs::read(&path).await.ok()?;
+        let expires_at = u64::from_be_bytes(data.get(..8)?.try_into().ok()?);
+        let expires_in = match expires_at {
+            0 => None,
+            expires_at => {
+                let now = unix_millis(std::time::SystemTime::now());
+                if expires_at <= now {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return None;
+                }
+                Some(Duration::from_millis(expires_at - now))
+            }
+        };
+        data.drain(..8);
+        Some((data, expires_in))
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        let expires_at = ttl.map_or(0, |ttl| {
+            unix_millis(std::time::SystemTime::now() + ttl).max(1)
+        });
+        let mut data = Vec::with_capacity(8 + value.len());
+        data.extend_from_slice(&expires_at.to_be_bytes());
+        data.extend_from_slice(&value);
+
+        if tokio::fs::create_dir_all(&self.dir).await.is_err() {
+            return;
+        }
+        let path = self.dir.join(key);
+        let bytes = data.len() as u64;
+        let replaced = tokio::fs::metadata(&path).await.ok().map(|m| m.len());
+        // Readers never see a partially written entry.
+        let temp = self
+            .dir
+            .join(format!("{key}.{}.{TEMP_SUFFIX}", uuid::Uuid::new_v4()));
+        if tokio::fs::write(&temp, data).await.is_err()
+            || tokio::fs::rename(&temp, &path).await.is_err()
+        {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return;
+        }
+        self.written(bytes, replaced).await;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const TEMP_SUFFIX: &str = "tmp";
+
+/// Delete the expired entries of `dir`, then the least recently written
+/// ones while over the limits. Returns the number of entries deleted and
+/// the bytes and entries left.
+#[cfg(not(target_arch = "wasm32"))]
+fn sweep(
+    dir: &Path,
+    max_bytes: Option<u64>,
+    max_entries: Option<usize>,
+) -> std::io::Result<(usize, (u64, usize))> {
+    use std::io::Read;
+
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok((0, (0, 0))),
+        Err(error) => return Err(error),
+    };
+    let now = unix_millis(std::time::SystemTime::now());
+    let mut deleted = 0;
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == TEMP_SUFFIX)
+        {
+            continue;
+        }
+        let mut header = [0; 8];
+        let Ok(metadata) = std::fs::File::open(&path)
+            .and_then(|mut file| file.read_exact(&mut header).and_then(|()| file.metadata()))
+        else {
+            continue;
+        };
+        let expires_at = u64::from_be_bytes(header);
+        if expires_at != 0 && expires_at <= now {
+            if std::fs::remove_file(&path).is_ok() {
+                deleted += 1;
+            }
+            continue;
+        }
+        let written_at = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+        entries.push((written_at, metadata.len(), path));
+    }
+
+    entries.sort_unstable();
+    let mut bytes: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    let mut count = entries.len();
+    for (_, len, path) in entries {
+        let exceeded =
+            max_bytes.is_some_and(|max| bytes > max) || max_entries.is_some_and(|max| count > max);
+        if !exceeded {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            bytes -= len;
+            count -= 1;
+            deleted += 1;
+        }
+    }
+    Ok((deleted, (bytes, count)))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_millis(time: std::time::SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_depends_on_url_and_body() {
+        let body = json!({"image_url": "data:image/png;base64,AAA", "object": "cat"}).to_string();
+        let key = Cache::key("http://localhost/detect", body.as_bytes());
+
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, Cache::key("http://localhost/detect", body.as_bytes()));
+        assert_ne!(key, Cache::key("http://localhost/point", body.as_bytes()));
+        assert_ne!(
+            key,
+            Cache::key(
+                "http://localhost/detect",
+                json!({"image_url": "data:image/png;base64,AAA", "object": "dog"})
+                    .to_string()
+                    .as_bytes()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_evicts_least_recently_used() {
+        let store = MemoryStore::new(2);
+        store.put("a", b"1".to_vec(), None).await;
+        store.put("b", b"2".to_vec(), None).await;
+        assert_eq!(store.get("a").await, Some(b"1".to_vec()));
+
+        store.put("c", b"3".to_vec(), None).await;
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("b").await, None);
+        assert_eq!(store.get("a").await, Some(b"1".to_vec()));
+        assert_eq!(store.get("c").await, Some(b"3".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_expires_entries() {
+        let store = MemoryStore::new(10);
+        store.put("a", b"1".to_vec(), Some(Duration::ZERO)).await;
+        store
+            .put("b", b"2".to_vec(), Some(Duration::from_secs(60)))
+            .await;
+
+        assert_eq!(store.get("a").await, None);
+        assert_eq!(store.get("b").await, Some(b"2".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_stale_entries_are_refreshed_once() {
+        let cache = Cache::memory(10)
+            .with_ttl(Duration::from_secs(60))
+            .with_stale_while_revalidate(Duration::from_secs(120))
+            .with_stale_while_revalidate_for("detect", Duration::ZERO);
+        cache.put("a", b"1".to_vec()).await;
+
+        assert_eq!(cache.get("a", "caption").await, Some((b"1".to_vec(), true)));
+        // Already being refreshed.
+        assert_eq!(
+            cache.get("a", "caption").await,
+            Some((b"1".to_vec(), false))
+        );
+        assert_eq!(cache.get("a", "detect").await, Some((b"1".to_vec(), false)));
+        cache.refreshed("a");
+        assert_eq!(cache.get("a", "query").await, Some((b"1".to_vec(), true)));
+    }
+
+    /// Reverses the bytes, to check that entries go through the codec.
+    #[derive(Debug)]
+    struct Reverse;
+
+    impl Compression for Reverse {
+        fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            Ok(data.iter().rev().copied().collect())
+        }
+
+        fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            if data.first() == Some(&b'{') {
+                return Err(std::io::ErrorKind::InvalidData.into());
+            }
+            Ok(data.iter().rev().copied().collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compressed_entries() {
+        let store = Arc::new(MemoryStore::new(10));
+        let cache = Cache::new(store.clone()).with_compression(Reverse);
+        cache.put("a", br#"{"caption":"cat"}"#.to_vec()).await;
+        assert_eq!(store.get("a").await, Some(br#"}"tac":"noitpac"{"#.to_vec()));
+        assert_eq!(
+            cache.get("a", "caption").await,
+            Some((br#"{"caption":"cat"}"#.to_vec(), false))
+        );
+
+        // Entries written without compression are misses.
+        store.put("b", br#"{"caption":"dog"}"#.to_vec(), None).await;
+        assert_eq!(cache.get("b", "caption").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_compressed() {
+        let dir = std::env::temp_dir().join(format!("moondream-cache-{}", uuid::Uuid::new_v4()));
+        let cache = Cache::disk(&dir).with_compression(Reverse);
+        cache.put("a", br#"{"caption":"cat"}"#.to_vec()).await;
+        assert_eq!(
+            std::fs::read(dir.join("a")).unwrap()[8..],
+            br#"}"tac":"noitpac"{"#[..]
+        );
+        assert_eq!(
+            Cache::disk(&dir)
+                .with_compression(Reverse)
+                .get("a", "caption")
+                .await,
+            Some((br#"{"caption":"cat"}"#.to_vec(), false))
+        );
+
+        let store = DiskStore::new(&dir);
+        store.put("b", b"1".to_vec(), Some(Duration::ZERO)).await;
+        store
+            .put("c", b"2".to_vec(), Some(Duration::from_secs(60)))
+            .await;
+        assert_eq!(store.get("b").await, None);
+        assert!(!dir.join("b").exists());
+        let (value, expires_in) = store.get_with_ttl("c").await.unwrap();
+        assert_eq!(value, b"2");
+        assert!(expires_in.unwrap() <= Duration::from_secs(60));
+        assert_eq!(store.get("d").await, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_limits() {
+        let dir = std::env::temp_dir().join(format!("moondream-cache-{}", uuid::Uuid::new_v4()));
+        let store = DiskStore::new(&dir).with_max_entries(2);
+        for key in ["a", "b", "c"] {
+            store.put(key, b"1".to_vec(), None).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!dir.join("a").exists());
+        assert!(dir.join("b").exists() && dir.join("c").exists());
+
+        // Files hold the 8 bytes of the expiry followed by the value.
+        let store = DiskStore::new(&dir).with_max_bytes(30);
+        store.put("d", vec![b'0'; 10], None).await;
+        assert!(!dir.join("b").exists());
+        assert!(dir.join("c").exists() && dir.join("d").exists());
+
+        DiskStore::new(&dir)
+            .put("e", b"1".to_vec(), Some(Duration::ZERO))
+            .await;
+        assert_eq!(store.sweep().await.unwrap(), 1);
+        assert!(!dir.join("e").exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(store.sweep().await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let body = br#"{"caption":"a cat on a mat"}"#.repeat(100);
+        let compressed = Zstd::default().compress(&body).unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(Zstd::new(19).decompress(&compressed).unwrap(), body);
+    }
+}
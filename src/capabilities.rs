@@ -0,0 +1,175 @@
+//! Discovery of the features supported by a server.
+//!
+//! Servers may publish a manifest at `GET {endpoint}/capabilities`:
+//!
+//! ```json
+//! {"endpoints": ["caption", "query", "detect", "point", "segment"], "streaming": true, "max_payload_size": 10485760}
+//! ```
+//!
+//! When no manifest is available, [`MoonDream::capabilities`] probes each
+//! known endpoint with an empty request: anything but `404 Not Found` or
+//! `405 Method Not Allowed` means the endpoint exists.
+
+use crate::{Error, MoonDream};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Endpoints probed when the server has no manifest.
+const KNOWN_ENDPOINTS: [&str; 6] = [
+    "caption",
+    "query",
+    "detect",
+    "point",
+    "segment",
+    "detect_gaze",
+];
+
+/// How a [`Capabilities`] value was obtained.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CapabilitySource {
+    /// Read from the manifest published by the server.
+    Manifest,
+    /// Inferred by probing the known endpoints.
+    #[default]
+    Probe,
+}
+
+/// Features supported by a server, as returned by
+/// [`MoonDream::capabilities`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Names of the supported endpoints, such as `caption` or `segment`.
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+    /// Whether captions and answers can be streamed; `None` if unknown.
+    #[serde(default)]
+    pub streaming: Option<bool>,
+    /// Maximum request size accepted, in bytes; `None` if unknown.
+    #[serde(default)]
+    pub max_payload_size: Option<usize>,
+    /// How these capabilities were obtained.
+    #[serde(default)]
+    pub source: CapabilitySource,
+}
+
+impl Capabilities {
+    /// Return `true` if the server exposes `endpoint`.
+    pub fn supports(&self, endpoint: &str) -> bool {
+        self.endpoints.iter().any(|supported| supported == endpoint)
+    }
+
+    /// Return `true` if the server supports segmentation.
+    pub fn segmentation(&self) -> bool {
+        self.supports("segment")
+    }
+
+    /// Return `true` if the server supports gaze detection.
+    pub fn gaze(&self) -> bool {
+        self.supports("detect_gaze")
+    }
+}
+
+impl MoonDream {
+    /// Discover the endpoints and options supported by the server.
+    ///
+    /// Reads the server manifest when available and otherwise probes the
+    /// known endpoints, see the [module documentation](crate::capabilities).
+    /// Fails with [`Error::Unavailable`] if the server cannot be reached.
+    pub async fn capabilities(&self) -> Result<Capabilities, Error> {
+        let template = self.template()?;
+        let request = template.authorize(self.client.get(template.url("capabilities")?));
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.timeout(self.timeout);
+        let response = request.send().await.map_err(|e| self.unavailable(e))?;
+        if response.status().is_success() {
+            let body = response.bytes().await?;
+            return Ok(Capabilities {
+                source: CapabilitySource::Manifest,
+                ..serde_json::from_slice::<Capabilities>(&body)?
+            });
+        }
+
+        let mut endpoints = Vec::new();
+        for endpoint in KNOWN_ENDPOINTS {
+            if self.probe(endpoint).await? {
+                endpoints.push(endpoint.to_string());
+            }
+        }
+        Ok(Capabilities {
+            endpoints,
+            source: CapabilitySource::Probe,
+            ..Capabilities::default()
+        })
+    }
+
+    /// Send an empty request to `endpoint` and report whether it exists.
+    async fn probe(&self, endpoint: &str) -> Result<bool, Error> {
+        let template = self.template()?;
+        let request = template
+            .authorize(self.client.post(template.url(endpoint)?))
+            .json(&serde_json::json!({}));
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.timeout(self.timeout);
+        let status = request
+            .send()
+            .await
+            .map_err(|e| self.unavailable(e))?
+            .status();
+        Ok(status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_capabilities_from_manifest() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/capabilities"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "endpoints": ["caption", "segment"],
+                "streaming": true,
+                "max_payload_size": 1024,
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let capabilities = md.capabilities().await.unwrap();
+
+        assert_eq!(capabilities.source, CapabilitySource::Manifest);
+        assert!(capabilities.segmentation());
+        assert!(!capabilities.gaze());
+        assert_eq!(capabilities.streaming, Some(true));
+        assert_eq!(capabilities.max_payload_size, Some(1024));
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_probe_fallback() {
+        let server = MockServer::start().await;
+
+        for endpoint in ["/caption", "/query", "/detect", "/point"] {
+            Mock::given(method("POST"))
+                .and(path(endpoint))
+                .respond_with(ResponseTemplate::new(422))
+                .mount(&server)
+                .await;
+        }
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let capabilities = md.capabilities().await.unwrap();
+
+        assert_eq!(capabilities.source, CapabilitySource::Probe);
+        assert_eq!(
+            capabilities.endpoints,
+            ["caption", "query", "detect", "point"]
+        );
+        assert!(!capabilities.segmentation());
+        assert_eq!(capabilities.streaming, None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Continuation of truncated answers.
+//!
+//! Long answers, for example transcriptions of document pages, can be cut
+//! off by the token limit of the model. [`MoonDream::query_full`] detects
+//! truncated answers with a [`Continuation`] and asks the model to continue
+//! them, stitching the parts into a single [`QueryResponse`].
+
+use crate::{Error, ImageInput, MoonDream, QueryResponse};
+use std::fmt;
+use std::sync::Arc;
+
+/// Detects truncated answers and controls the follow-up queries.
+///
+/// By default an answer is considered truncated when it does not end with
+/// closing punctuation, and up to 4 follow-up queries are sent.
+///
+/// ```
+/// use moondream::Continuation;
+///
+/// let continuation = Continuation::new()
+///     .with_max_rounds(8)
+///     .with_detector(|answer| !answer.trim_end().ends_with("END"));
+/// ```
+#[derive(Clone)]
+pub struct Continuation {
+    max_rounds: u32,
+    prompt: String,
+    detector: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl Default for Continuation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Continuation")
+            .field("max_rounds", &self.max_rounds)
+            .field("prompt", &self.prompt)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Continuation {
+    /// Send up to 4 follow-up queries for answers that look truncated.
+    pub fn new() -> Self {
+        Self {
+            max_rounds: 4,
+            prompt:
+                "Continue exactly where it stopped, without repeating what was already written."
+                    .to_string(),
+            detector: Arc::new(looks_truncated),
+        }
+    }
+
+    /// Set the maximum number of follow-up queries.
+    pub fn with_max_rounds(mut self, max_rounds: u32) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Set the instruction sent after the question and the partial answer.
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Decide whether an answer, stitched so far, is truncated.
+    pub fn with_detector(
+        mut self,
+        detector: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.detector = Arc::new(detector);
+        self
+    }
+
+    /// Maximum number of follow-up queries.
+    pub fn max_rounds(&self) -> u32 {
+        self.max_rounds
+    }
+
+    /// Return `true` if `answer` is considered truncated.
+    pub fn is_truncated(&self, answer: &str) -> bool {
+        (self.detector)(answer)
+    }
+
+    fn follow_up(&self, question: &str, answer: &str) -> String {
+        format!(
+            "{question}\n\nYour previous answer was cut off:\n{answer}\n\n{}",
+            self.prompt
+        )
+    }
+}
+
+/// Default detector: the answer does not end with closing punctuation.
+fn looks_truncated(answer: &str) -> bool {
+    let answer = answer.trim_end();
+    !answer.is_empty()
+        && !answer.ends_with([
+            '.', '!', '?', '"', '\'', ')', ']', '}', '`', '…', '。', '！', '？',
+        ])
+}
+
+/// Result of [`MoonDream::query_full`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryFullResponse {
+    /// The stitched response. `request_id` is the one of the first query.
+    pub response: QueryResponse,
+    /// Number of queries sent, the first one included.
+    pub round_trips: u32,
+    /// `true` if the answer still looked truncated after the last follow-up
+    /// query.
+    pub truncated: bool,
+}
+
+impl MoonDream {
+    /// Ask `question` and continue the answer while it looks truncated, see
+    /// the [module documentation](crate::continuation).
+    pub async fn query_full(
+        &self,
+        image: impl Into<ImageInput>,
+        question: impl Into<String>,
+        continuation: &Continuation,
+    ) -> Result<QueryFullResponse, Error> {
+        let image = self.prepare_image(image)?;
+        let question = question.into();
+
+        let mut response = self
+            .query_prepared(&image, question.clone())
+            .await?
+            .into_inner();
+        let mut round_trips = 1;
+        while continuation.is_truncated(&response.answer) {
+            if round_trips > continuation.max_rounds {
+                return Ok(QueryFullResponse {
+                    response,
+                    round_trips,
+                    truncated: true,
+                });
+            }
+            let part = self
+                .query_prepared(&image, continuation.follow_up(&question, &response.answer))
+                .await?
+                .into_inner();
+            round_trips += 1;
+            response.flags |= part.flags;
+            if part.answer.trim().is_empty() {
+                break;
+            }
+            stitch(&mut response.answer, &part.answer);
+        }
+
+        Ok(QueryFullResponse {
+            truncated: continuation.is_truncated(&response.answer),
+            response,
+            round_trips,
+        })
+    }
+}
+
+/// Append `part` to `answer`, dropping text repeated from the end of
+/// `answer` and separating words cut at the boundary with a space.
+fn stitch(answer: &mut String, part: &str) {
+    let part = part.trim_start();
+    let overlap = part
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .rev()
+        .find(|&end| end >= 8 && answer.ends_with(&part[..end]))
+        .unwrap_or(0);
+    let part = &part[overlap..];
+    if part.is_empty() {
+        return;
+    }
+    let joined = answer.ends_with(char::is_whitespace)
+        || part.starts_with(|c: char| c.is_ascii_punctuation() && !"([{\"'".contains(c));
+    if !joined {
+        answer.push(' ');
+    }
+    answer.push_str(part);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_stitch() {
+        let mut answer = "The text reads: lorem ipsum dolor".to_string();
+        stitch(&mut answer, "sit amet");
+        assert_eq!(answer, "The text reads: lorem ipsum dolor sit amet");
+
+        stitch(&mut answer, "ipsum dolor sit amet, consectetur.");
+        assert_eq!(
+            answer,
+            "The text reads: lorem ipsum dolor sit amet, consectetur."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_full_continues_truncated_answers() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_string_contains("was cut off"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "second",
+                "answer": "sit amet.",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "first",
+                "answer": "lorem ipsum dolor",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let full = md
+            .query_full(
+                "https://example.com/a.jpg",
+                "Transcribe the page.",
+                &Continuation::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(full.response.answer, "lorem ipsum dolor sit amet.");
+        assert_eq!(full.response.request_id.as_deref(), Some("first"));
+        assert_eq!(full.round_trips, 2);
+        assert!(!full.truncated);
+
+        let full = md
+            .query_full(
+                "https://example.com/a.jpg",
+                "Transcribe the page.",
+                &Continuation::new()
+                    .with_detector(|_| true)
+                    .with_max_rounds(2),
+            )
+            .await
+            .unwrap();
+        assert_eq!(full.round_trips, 3);
+        assert!(full.truncated);
+    }
+}
@@ -0,0 +1,195 @@
+//! Sampled logging of complete requests and responses.
+//!
+//! [`MoonDream::with_debug_sampling`] picks a fraction of the calls and
+//! records their full request and response bodies, so production issues
+//! can be diagnosed without logging every payload. Inline images are
+//! replaced by the start of their SHA-256 hash and their size, as with
+//! [`MoonDream::with_log_bodies`](crate::MoonDream::with_log_bodies).
+//!
+//! Records go to the [`DebugSink`] set with [`MoonDream::with_debug_sink`],
+//! or else are emitted as `DEBUG` events of the `moondream.debug` target
+//! (feature `tracing`). A call is sampled once, its retries included, and
+//! the decision is derived from its client request ID, so it can be
+//! correlated with server logs.
+//!
+//! ```
+//! use moondream::MoonDream;
+//!
+//! // Record one call in a hundred.
+//! let md = MoonDream::remote("token").with_debug_sampling(0.01);
+//! ```
+
+use crate::telemetry::redact_body;
+use crate::{ApiResponse, Error, MoonDream};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A sampled call, with its redacted bodies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugRecord {
+    /// Client request ID of the call, sent as `X-Client-Request-Id`.
+    pub client_request_id: String,
+    /// Endpoint path, such as `query`.
+    pub path: String,
+    /// Request body.
+    pub request: String,
+    /// HTTP status of the response, when one was received.
+    pub status: Option<u16>,
+    /// Response body, when one was received.
+    pub response: Option<String>,
+    /// Error returned by the call.
+    pub error: Option<String>,
+    /// Whether the response came from the cache.
+    pub cached: bool,
+    /// Duration of the call, retries included.
+    pub latency: Duration,
+}
+
+/// Destination of sampled [`DebugRecord`]s.
+#[async_trait]
+pub trait DebugSink: std::fmt::Debug + Send + Sync {
+    /// Store or print `record`.
+    async fn record(&self, record: &DebugRecord);
+}
+
+/// Return `true` if the call with `id` is sampled at `rate`.
+pub(crate) fn sampled(id: &Uuid, rate: f64) -> bool {
+    // The first bytes of a v4 UUID are random.
+    let fraction = (id.as_u64_pair().0 >> 11) as f64 / (1u64 << 53) as f64;
+    fraction < rate
+}
+
+impl MoonDream {
+    /// Record the complete request and response of a `rate` fraction of the
+    /// calls, from 0 (default) to 1, see the [module documentation](crate::debug).
+    pub fn with_debug_sampling(mut self, rate: f64) -> Self {
+        self.debug_sampling = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Send sampled records to `sink` instead of `tracing`.
+    pub fn with_debug_sink(mut self, sink: impl DebugSink + 'static) -> Self {
+        self.debug_sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Deliver the record of a sampled call.
+    pub(crate) async fn record_debug<T>(
+        &self,
+        client_request_id: &Uuid,
+        path: &str,
+        request: &[u8],
+        result: &Result<ApiResponse<T>, Error>,
+        latency: Duration,
+    ) {
+        let (status, response, error, cached) = match result {
+            Ok(response) => (
+                response.meta.status.map(|status| status.as_u16()),
+                Some(redact_body(&response.meta.raw_body)),
+                None,
+                response.meta.cached,
+            ),
+            Err(error) => {
+                let status = match error {
+                    Error::PointError(error) => error.status().map(|status| status.as_u16()),
+                    Error::Status { status, .. } | Error::ModelLoading { status, .. } => {
+                        Some(status.as_u16())
+                    }
+                    _ => None,
+                };
+                (status, None, Some(error.to_string()), false)
+            }
+        };
+        let record = DebugRecord {
+            client_request_id: client_request_id.to_string(),
+            path: path.to_string(),
+            request: redact_body(request),
+            status,
+            response,
+            error,
+            cached,
+            latency,
+        };
+
+        match &self.debug_sink {
+            Some(sink) => sink.record(&record).await,
+            #[cfg(feature = "tracing")]
+            None => tracing::debug!(
+                target: "moondream.debug",
+                client_request_id = %record.client_request_id,
+                path = %record.path,
+                request = %record.request,
+                status = record.status,
+                response = record.response.as_deref(),
+                error = record.error.as_deref(),
+                cached = record.cached,
+                latency_ms = record.latency.as_millis() as u64,
+                "sampled request"
+            ),
+            #[cfg(not(feature = "tracing"))]
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Debug, Default)]
+    struct Collect(Mutex<Vec<DebugRecord>>);
+
+    #[async_trait]
+    impl DebugSink for Arc<Collect> {
+        async fn record(&self, record: &DebugRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[test]
+    fn test_sampled_rate() {
+        let ids: Vec<Uuid> = (0..2000).map(|_| Uuid::new_v4()).collect();
+        assert!(ids.iter().all(|id| sampled(id, 1.0)));
+        assert!(!ids.iter().any(|id| sampled(id, 0.0)));
+        let count = ids.iter().filter(|id| sampled(id, 0.25)).count();
+        assert!((300..700).contains(&count), "{count}");
+    }
+
+    #[tokio::test]
+    async fn test_debug_sampling_records_bodies() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "A cat",
+            })))
+            .mount(&server)
+            .await;
+
+        let sink = Arc::new(Collect::default());
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_debug_sink(sink.clone());
+        md.query("data:image/png;base64,AAAA", "What?")
+            .await
+            .unwrap();
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        let md = md.with_debug_sampling(1.0);
+        md.query("data:image/png;base64,AAAA", "What?")
+            .await
+            .unwrap();
+        let records = sink.0.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].path, "query");
+        assert_eq!(records[0].status, Some(200));
+        assert!(records[0].request.contains("<image sha256="));
+        assert!(!records[0].request.contains("AAAA"));
+        assert!(records[0].response.as_deref().unwrap().contains("A cat"));
+    }
+}
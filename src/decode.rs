@@ -0,0 +1,187 @@
+//! Response decoding with an optional lenient fallback.
+//!
+//! Responses are always decoded strictly first. When that fails and lenient
+//! decoding is enabled with
+//! [`MoonDream::with_lenient_decode`](crate::MoonDream::with_lenient_decode),
+//! the body is repaired field by field: each field the target type rejects
+//! is fixed and the decoding retried. A number sent as a string (`"0.5"`) is
+//! turned into a number, only where the type expects one, so strings such
+//! as a `request_id` of `"123"` are kept. An unknown enum value is dropped:
+//! an optional field becomes `None` and a list loses the item. A response
+//! decoded this way carries [`ResultFlags::LENIENT_PARSE`] in its
+//! [`ResponseMeta`](crate::ResponseMeta).
+//!
+//! Unknown fields are ignored in both modes, unless the `strict` feature is
+//! enabled: both modes then reject them, except the token `usage` read by
+//! the [usage tracker](crate::usage).
+
+use crate::ResultFlags;
+use serde::de::DeserializeOwned;
+use serde_json::{Number, Value};
+use serde_path_to_error::{Path, Segment};
+
+/// Decode `bytes` as `T`, falling back to a lenient parse if `lenient` is set.
+///
+/// The error of the strict parse is returned when both attempts fail.
+pub(crate) fn decode<T: DeserializeOwned>(
+    bytes: &[u8],
+    lenient: bool,
+) -> Result<(T, ResultFlags), serde_json::Error> {
+    #[cfg(feature = "strict")]
+    let stripped = strip_usage(bytes);
+    #[cfg(feature = "strict")]
+    let bytes = stripped.as_deref().unwrap_or(bytes);
+
+    let error = match serde_json::from_slice(bytes) {
+        Ok(data) => return Ok((data, ResultFlags::empty())),
+        Err(error) if !lenient => return Err(error),
+        Err(error) => error,
+    };
+
+    let Ok(mut value) = serde_json::from_slice::<Value>(bytes) else {
+        return Err(error);
+    };
+    loop {
+        match serde_path_to_error::deserialize::<_, T>(&value) {
+            Ok(data) => return Ok((data, ResultFlags::LENIENT_PARSE)),
+            Err(rejected) => {
+                let message = rejected.inner().to_string();
+                if !repair(&mut value, rejected.path(), &message) {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+/// `bytes` without their top-level `usage` field, or `None` if they have
+/// none.
+#[cfg(feature = "strict")]
+fn strip_usage(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut value = serde_json::from_slice::<Value>(bytes).ok()?;
+    value.as_object_mut()?.remove("usage")?;
+    serde_json::to_vec(&value).ok()
+}
+
+/// Fix the value at `path` rejected with `message`, returning `false` if it
+/// cannot be fixed.
+fn repair(root: &mut Value, path: &Path, message: &str) -> bool {
+    let segments: Vec<&Segment> = path.iter().collect();
+    if message.starts_with("invalid type: string") {
+        let Some(value) = lookup(root, &segments) else {
+            return false;
+        };
+        return match value.as_str().and_then(parse_number) {
+            Some(number) => {
+                *value = Value::Number(number);
+                true
+            }
+            None => false,
+        };
+    }
+    if message.starts_with("unknown variant") {
+        let Some((last, parent)) = segments.split_last() else {
+            return false;
+        };
+        return match (lookup(root, parent), last) {
+            (Some(Value::Array(items)), Segment::Seq { index }) if *index < items.len() => {
+                items.remove(*index);
+                true
+            }
+            (Some(Value::Object(fields)), Segment::Map { key }) => {
+                fields.insert(key.clone(), Value::Null);
+                true
+            }
+            _ => false,
+        };
+    }
+    false
+}
+
+fn lookup<'a>(mut value: &'a mut Value, segments: &[&Segment]) -> Option<&'a mut Value> {
+    for segment in segments {
+        value = match (value, segment) {
+            (Value::Array(items), Segment::Seq { index }) => items.get_mut(*index)?,
+            (Value::Object(fields), Segment::Map { key }) => fields.get_mut(key)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Parse `text` as a finite number.
+fn parse_number(text: &str) -> Option<Number> {
+    let text = text.trim();
+    match text.parse::<u64>() {
+        Ok(number) => Some(Number::from(number)),
+        Err(_) => text.parse::<f64>().ok().and_then(Number::from_f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Point, PointsResponse};
+
+    const BODY: &[u8] = br#"{"points": [{"x": "0.5", "y": 0.25}], "count": "1"}"#;
+
+    #[test]
+    fn test_strict_decode_rejects_numbers_as_strings() {
+        assert!(decode::<PointsResponse>(BODY, false).is_err());
+    }
+
+    #[test]
+    fn test_lenient_decode_coerces_numbers() {
+        let (resp, flags) = decode::<PointsResponse>(BODY, true).unwrap();
+
+        assert_eq!(
+            resp.points,
+            vec![Point {
+                x: 0.5,
+                y: 0.25,
+                confidence: None
+            }]
+        );
+        assert_eq!(resp.count, Some(1));
+        assert_eq!(flags, ResultFlags::LENIENT_PARSE);
+    }
+
+    #[test]
+    fn test_lenient_decode_keeps_numeric_strings() {
+        let body = br#"{"request_id": "123", "points": [{"x": "0.5", "y": 0.25}]}"#;
+        let (resp, _) = decode::<PointsResponse>(body, true).unwrap();
+        assert_eq!(resp.request_id.as_deref(), Some("123"));
+        assert_eq!(resp.points[0].x, 0.5);
+    }
+
+    #[test]
+    fn test_lenient_decode_drops_unknown_enum_values() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Kind {
+            Car,
+            Bus,
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Body {
+            kind: Option<Kind>,
+            kinds: Vec<Kind>,
+        }
+
+        let body = br#"{"kind": "tram", "kinds": ["car", "tram", "bus"]}"#;
+        assert!(decode::<Body>(body, false).is_err());
+        let (body, flags) = decode::<Body>(body, true).unwrap();
+        assert_eq!(body.kind, None);
+        assert_eq!(body.kinds, vec![Kind::Car, Kind::Bus]);
+        assert_eq!(flags, ResultFlags::LENIENT_PARSE);
+    }
+
+    #[test]
+    fn test_valid_body_is_not_flagged() {
+        let body = br#"{"points": [], "count": 0}"#;
+        let (_, flags) = decode::<PointsResponse>(body, true).unwrap();
+
+        assert!(flags.is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+//! Custom decoders for image formats the API does not accept.
+//!
+//! A [`DecoderRegistry`] maps MIME types, and the file extensions that carry
+//! them, to [`ImageDecoder`]s converting the data into a standard
+//! [`ImageInput`], typically a PNG or JPEG. Registered with
+//! [`MoonDream::with_decoders`](crate::MoonDream::with_decoders), it runs
+//! before preprocessing on every image.
+
+use crate::input::{decode_data_uri, mime_from_path};
+use crate::{Error, ImageInput};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Converts images of an exotic format into an [`ImageInput`].
+pub trait ImageDecoder: std::fmt::Debug + Send + Sync {
+    /// Decode `data`, encoded in the MIME type the decoder was registered for.
+    fn decode(&self, data: &[u8]) -> Result<ImageInput, Error>;
+}
+
+/// Decoders keyed by MIME type.
+///
+/// ```
+/// use moondream::decoder::{DecoderRegistry, ImageDecoder};
+/// use moondream::{Error, ImageInput};
+///
+/// #[derive(Debug)]
+/// struct RawDecoder;
+///
+/// impl ImageDecoder for RawDecoder {
+///     fn decode(&self, data: &[u8]) -> Result<ImageInput, Error> {
+///         let png = convert_raw_to_png(data)?;
+///         Ok(ImageInput::bytes(png, "image/png"))
+///     }
+/// }
+/// # fn convert_raw_to_png(data: &[u8]) -> Result<Vec<u8>, Error> { Ok(data.to_vec()) }
+///
+/// let registry = DecoderRegistry::new().register("image/x-canon-cr2", &["cr2"], RawDecoder);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<String, Arc<dyn ImageDecoder>>,
+    extensions: HashMap<String, String>,
+}
+
+impl DecoderRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode `mime` images, and files with one of `extensions`, with
+    /// `decoder`. Replaces any decoder registered for `mime`.
+    pub fn register(
+        mut self,
+        mime: impl Into<String>,
+        extensions: &[&str],
+        decoder: impl ImageDecoder + 'static,
+    ) -> Self {
+        let mime = mime.into().to_ascii_lowercase();
+        for extension in extensions {
+            self.extensions
+                .insert(extension.to_ascii_lowercase(), mime.clone());
+        }
+        self.decoders.insert(mime, Arc::new(decoder));
+        self
+    }
+
+    /// Return `true` if a decoder is registered for `mime`.
+    pub fn supports(&self, mime: &str) -> bool {
+        self.decoders.contains_key(&mime.to_ascii_lowercase())
+    }
+
+    /// Decode `image` if its MIME type has a registered decoder, otherwise
+    /// return it unchanged.
+    ///
+    /// Raw bytes, files, mapped files and base64 `data:` URIs are decoded;
+    /// remote URLs are left untouched.
+    pub fn decode(&self, image: ImageInput) -> Result<ImageInput, Error> {
+        match &image {
+            ImageInput::Bytes { data, mime } => match self.decoder(mime) {
+                Some(decoder) => decoder.decode(data),
+                None => Ok(image),
+            },
+            ImageInput::Url(url) => match decode_data_uri(url) {
+                Some(decoded) => {
+                    let (mime, data) = decoded?;
+                    match self.decoder(mime) {
+                        Some(decoder) => decoder.decode(&data),
+                        None => Ok(image),
+                    }
+                }
+                None => Ok(image),
+            },
+            ImageInput::File { path, mime } => match self.decoder(mime) {
+                Some(decoder) => decoder.decode(&std::fs::read(path)?),
+                None => Ok(image),
+            },
+            #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+            ImageInput::Mapped { file, mime } => match self.decoder(mime) {
+                Some(decoder) => decoder.decode(file),
+                None => Ok(image),
+            },
+        }
+    }
+
+    /// Read the image at `path` and decode it, guessing its MIME type from
+    /// the registered extensions first.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<ImageInput, Error> {
+        let path = path.as_ref();
+        let mime = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.extensions.get(&extension.to_ascii_lowercase()))
+            .map(String::as_str)
+            .unwrap_or_else(|| mime_from_path(path));
+        let data = std::fs::read(path)?;
+        self.decode(ImageInput::bytes(data, mime))
+    }
+
+    fn decoder(&self, mime: &str) -> Option<&Arc<dyn ImageDecoder>> {
+        self.decoders.get(&mime.to_ascii_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretends to convert by uppercasing the data.
+    #[derive(Debug)]
+    struct Upper;
+
+    impl ImageDecoder for Upper {
+        fn decode(&self, data: &[u8]) -> Result<ImageInput, Error> {
+            Ok(ImageInput::bytes(data.to_ascii_uppercase(), "image/png"))
+        }
+    }
+
+    #[test]
+    fn test_registered_mime_is_decoded() {
+        let registry = DecoderRegistry::new().register("application/dicom", &["dcm"], Upper);
+
+        assert_eq!(
+            registry
+                .decode(ImageInput::bytes(b"scan".to_vec(), "application/DICOM"))
+                .unwrap(),
+            ImageInput::bytes(b"SCAN".to_vec(), "image/png")
+        );
+        assert_eq!(
+            registry
+                .decode(ImageInput::url("data:application/dicom;base64,c2Nhbg=="))
+                .unwrap(),
+            ImageInput::bytes(b"SCAN".to_vec(), "image/png")
+        );
+    }
+
+    #[test]
+    fn test_other_inputs_are_unchanged() {
+        let registry = DecoderRegistry::new().register("application/dicom", &["dcm"], Upper);
+
+        for image in [
+            ImageInput::url("https://example.com/a.png"),
+            ImageInput::bytes(b"png".to_vec(), "image/png"),
+        ] {
+            assert_eq!(registry.decode(image.clone()).unwrap(), image);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Default options shared by clients.
+//!
+//! [`MoonDreamDefaults`] groups options that are usually identical across a
+//! codebase. They can be installed process-wide with
+//! [`MoonDreamDefaults::set_global`] or per client with
+//! [`MoonDream::with_defaults`](crate::MoonDream::with_defaults). Options set
+//! directly on a client or passed to a request always take precedence, then
+//! the client defaults, then the global defaults.
+
+use crate::{CaptionLength, ImagePreprocessor, RetryPolicy};
+use std::sync::{Arc, RwLock};
+
+static GLOBAL: RwLock<Option<MoonDreamDefaults>> = RwLock::new(None);
+
+/// Options inherited by clients and requests unless overridden.
+///
+/// ```
+/// use moondream::{CaptionLength, MoonDreamDefaults, RetryPolicy};
+///
+/// MoonDreamDefaults::new()
+///     .with_caption_length(CaptionLength::Short)
+///     .with_retry(RetryPolicy::new(3))
+///     .with_max_image_size(8 * 1024 * 1024)
+///     .set_global();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MoonDreamDefaults {
+    caption_length: Option<CaptionLength>,
+    retry: Option<RetryPolicy>,
+    preprocess: Option<Arc<dyn ImagePreprocessor>>,
+    max_image_size: Option<usize>,
+}
+
+impl MoonDreamDefaults {
+    /// Create an empty set of defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caption length used when a request does not specify one.
+    pub fn with_caption_length(mut self, length: CaptionLength) -> Self {
+        self.caption_length = Some(length);
+        self
+    }
+
+    /// Retry policy used by clients without their own.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Preprocessor used by clients without their own.
+    pub fn with_preprocess(mut self, preprocess: impl ImagePreprocessor + 'static) -> Self {
+        self.preprocess = Some(Arc::new(preprocess));
+        self
+    }
+
+    /// Maximum size, in bytes, of the encoded image sent to the API.
+    pub fn with_max_image_size(mut self, max_image_size: usize) -> Self {
+        self.max_image_size = Some(max_image_size);
+        self
+    }
+
+    /// Default caption length, if set.
+    pub fn caption_length(&self) -> Option<CaptionLength> {
+        self.caption_length
+    }
+
+    /// Default retry policy, if set.
+    pub fn retry(&self) -> Option<RetryPolicy> {
+        self.retry
+    }
+
+    /// Default preprocessor, if set.
+    pub fn preprocess(&self) -> Option<&Arc<dyn ImagePreprocessor>> {
+        self.preprocess.as_ref()
+    }
+
+    /// Default maximum image size, if set.
+    pub fn max_image_size(&self) -> Option<usize> {
+        self.max_image_size
+    }
+
+    /// Install these defaults for every client of the process.
+    ///
+    /// Clients read the global defaults on every request, so the change also
+    /// applies to clients created earlier.
+    pub fn set_global(self) {
+        *GLOBAL.write().unwrap_or_else(|e| e.into_inner()) = Some(self);
+    }
+
+    /// Remove the global defaults.
+    pub fn clear_global() {
+        *GLOBAL.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Return a copy of the global defaults.
+    pub fn global() -> Self {
+        GLOBAL
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// Fill every option not set in `self` from `fallback`.
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            caption_length: self.caption_length.or(fallback.caption_length),
+            retry: self.retry.or(fallback.retry),
+            preprocess: self.preprocess.or(fallback.preprocess),
+            max_image_size: self.max_image_size.or(fallback.max_image_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_or_prefers_own_values() {
+        let client = MoonDreamDefaults::new().with_caption_length(CaptionLength::Short);
+        let global = MoonDreamDefaults::new()
+            .with_caption_length(CaptionLength::Normal)
+            .with_retry(RetryPolicy::new(2))
+            .with_max_image_size(1024);
+
+        let merged = client.or(global);
+
+        assert_eq!(merged.caption_length(), Some(CaptionLength::Short));
+        assert_eq!(merged.retry(), Some(RetryPolicy::new(2)));
+        assert_eq!(merged.max_image_size(), Some(1024));
+        assert!(merged.preprocess().is_none());
+    }
+}
@@ -0,0 +1,135 @@
+//! DICOM medical images (feature `dicom`).
+//!
+//! DICOM files are converted to 8-bit PNG [`ImageInput`]s: one frame is
+//! selected and its values are mapped with a window/level (VOI LUT). Without
+//! an explicit window the one stored in the file is used, falling back to the
+//! full range of the pixel values.
+//!
+//! ```no_run
+//! use moondream::dicom::{DicomDecoder, DicomOptions};
+//! use moondream::{DecoderRegistry, MoonDream};
+//!
+//! # async fn run() -> Result<(), moondream::Error> {
+//! let options = DicomOptions::new().with_window(40.0, 400.0);
+//! let md = MoonDream::local("http://localhost:2020/v1").with_decoders(
+//!     DecoderRegistry::new().register("application/dicom", &["dcm"], DicomDecoder::new(options)),
+//! );
+//! let image = moondream::ImageInput::from_path("ct-slice.dcm")?;
+//! let report = md.query(image, "Describe any abnormality.").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::decoder::ImageDecoder;
+use crate::{Error, ImageInput};
+use dicom_object::file::ReadPreamble;
+use dicom_object::{DefaultDicomObject, OpenFileOptions};
+use dicom_pixeldata::{ConvertOptions, PixelDecoder, VoiLutOption, WindowLevel};
+use image::ImageFormat;
+use std::io::Cursor;
+use std::path::Path;
+
+/// How a DICOM image is converted.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DicomOptions {
+    frame: u32,
+    window: Option<WindowLevel>,
+}
+
+impl DicomOptions {
+    /// Convert the first frame with the window stored in the file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Convert frame `frame` (starting at 0) of multi-frame images.
+    pub fn with_frame(mut self, frame: u32) -> Self {
+        self.frame = frame;
+        self
+    }
+
+    /// Map values with the window of the given `center` (level) and `width`,
+    /// for example `40` / `400` for a CT soft-tissue window.
+    pub fn with_window(mut self, center: f64, width: f64) -> Self {
+        self.window = Some(WindowLevel { center, width });
+        self
+    }
+
+    fn convert_options(&self) -> ConvertOptions {
+        let voi_lut = match self.window {
+            Some(window) => VoiLutOption::Custom(window),
+            None => VoiLutOption::First,
+        };
+        ConvertOptions::new().with_voi_lut(voi_lut).force_8bit()
+    }
+}
+
+/// Read the DICOM file at `path` and convert it to a PNG image.
+pub fn load(path: impl AsRef<Path>, options: &DicomOptions) -> Result<ImageInput, Error> {
+    let object = OpenFileOptions::new().open_file(path).map_err(invalid)?;
+    convert(&object, options)
+}
+
+/// Convert the DICOM file `data`, with or without its 128-byte preamble, to
+/// a PNG image.
+pub fn decode(data: &[u8], options: &DicomOptions) -> Result<ImageInput, Error> {
+    let object = OpenFileOptions::new()
+        .read_preamble(ReadPreamble::Auto)
+        .from_reader(Cursor::new(data))
+        .map_err(invalid)?;
+    convert(&object, options)
+}
+
+fn convert(object: &DefaultDicomObject, options: &DicomOptions) -> Result<ImageInput, Error> {
+    let pixels = object.decode_pixel_data().map_err(invalid)?;
+    if options.frame >= pixels.number_of_frames() {
+        return Err(Error::InvalidImage(format!(
+            "frame {} out of range, the image has {} frames",
+            options.frame,
+            pixels.number_of_frames()
+        )));
+    }
+    let image = pixels
+        .to_dynamic_image_with_options(options.frame, &options.convert_options())
+        .map_err(invalid)?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(invalid)?;
+    Ok(ImageInput::bytes(png, "image/png"))
+}
+
+fn invalid(error: impl std::fmt::Display) -> Error {
+    Error::InvalidImage(error.to_string())
+}
+
+/// [`ImageDecoder`] converting `application/dicom` images.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DicomDecoder {
+    options: DicomOptions,
+}
+
+impl DicomDecoder {
+    /// Create a decoder converting images with `options`.
+    pub fn new(options: DicomOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl ImageDecoder for DicomDecoder {
+    fn decode(&self, data: &[u8]) -> Result<ImageInput, Error> {
+        decode(data, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_data_is_rejected() {
+        let err = decode(b"not a dicom file", &DicomOptions::new()).unwrap_err();
+        assert!(matches!(err, Error::InvalidImage(_)));
+    }
+}
@@ -0,0 +1,506 @@
+//! Streaming export of results to CSV or JSON Lines files.
+//!
+//! [`ExportWriter`] writes records one at a time through a bounded buffer, so
+//! long-running pipelines never hold their results in memory. Files can be
+//! rotated by size or record count and are flushed periodically. Parquet is
+//! not supported; JSON Lines files convert losslessly with external tools.
+//! With the `zstd` feature, files can be compressed as they are written with
+//! `ExportWriter::with_compression`.
+//!
+//! Geo-referenced detections are exported as GeoJSON with [`geojson`].
+
+use crate::{DetectResponse, Error, GeoTransform};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "zstd")]
+use crate::cache::Zstd;
+
+/// File format written by an [`ExportWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header row.
+    ///
+    /// Records must serialize to objects. The header is taken from the first
+    /// record of each file; nested values are written as JSON.
+    Csv,
+    /// One JSON document per line.
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// When an [`ExportWriter`] starts a new file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    max_bytes: Option<u64>,
+    max_records: Option<u64>,
+}
+
+impl Rotation {
+    /// Never rotate.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Start a new file once the current one holds `max_bytes` bytes.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Start a new file once the current one holds `max_records` records.
+    pub fn with_max_records(mut self, max_records: u64) -> Self {
+        self.max_records = Some(max_records);
+        self
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.max_records.is_some()
+    }
+}
+
+/// Open export file.
+enum Sink {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(file) => file,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder,
+        }
+    }
+
+    /// Write the buffered data and, for compressed files, the end of the
+    /// frame.
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Sink::Plain(mut file) => file.flush(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sink::Plain(_) => f.write_str("Plain"),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(_) => f.write_str("Zstd"),
+        }
+    }
+}
+
+/// Writes serializable records to CSV or JSON Lines files.
+///
+/// Without rotation every record goes to `path`. With a [`Rotation`], files
+/// are numbered after the stem of `path`: `results-0000.jsonl`,
+/// `results-0001.jsonl`, ...
+///
+/// ```no_run
+/// use moondream::export::{ExportFormat, ExportWriter, Rotation};
+///
+/// # fn run(results: Vec<serde_json::Value>) -> Result<(), moondream::Error> {
+/// let mut writer = ExportWriter::new("results.jsonl", ExportFormat::Jsonl)
+///     .with_rotation(Rotation::none().with_max_bytes(64 * 1024 * 1024))
+///     .with_flush_every(100);
+/// for result in &results {
+///     writer.write(result)?;
+/// }
+/// let files = writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ExportWriter {
+    path: PathBuf,
+    format: ExportFormat,
+    rotation: Rotation,
+    flush_every: u64,
+    append: bool,
+    #[cfg(feature = "zstd")]
+    compression: Option<Zstd>,
+    file: Option<Sink>,
+    files: Vec<PathBuf>,
+    columns: Vec<String>,
+    file_bytes: u64,
+    file_records: u64,
+    unflushed: u64,
+}
+
+impl ExportWriter {
+    /// Create a writer producing `format` files at `path`.
+    ///
+    /// The first file is created on the first record.
+    pub fn new(path: impl Into<PathBuf>, format: ExportFormat) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            rotation: Rotation::none(),
+            flush_every: 1000,
+            append: false,
+            #[cfg(feature = "zstd")]
+            compression: None,
+            file: None,
+            files: Vec::new(),
+            columns: Vec::new(),
+            file_bytes: 0,
+            file_records: 0,
+            unflushed: 0,
+        }
+    }
+
+    /// Rotate files according to `rotation`.
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Flush the buffer to disk every `records` records (default 1000).
+    pub fn with_flush_every(mut self, records: u64) -> Self {
+        self.flush_every = records.max(1);
+        self
+    }
+
+    /// Append to existing files instead of truncating them, for example to
+    /// keep the results of earlier runs. CSV headers are only written to
+    /// empty files.
+    pub fn with_append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Compress the files with `compression` as they are written (feature
+    /// `zstd`), for example to `results.jsonl.zst`.
+    ///
+    /// Rotated files keep the `.zst` extension after their number, and
+    /// [`Rotation::with_max_bytes`] counts uncompressed bytes. Appending adds
+    /// a new zstd frame, which decoders read as one stream. Files are only
+    /// complete once the writer is finished or dropped.
+    #[cfg(feature = "zstd")]
+    pub fn with_compression(mut self, compression: Zstd) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Append one record.
+    pub fn write<T: Serialize>(&mut self, record: &T) -> Result<(), Error> {
+        let value = serde_json::to_value(record).map_err(|e| Error::Export(e.to_string()))?;
+        if self.file.is_none() || self.should_rotate() {
+            self.open_next(&value)?;
+        }
+
+        let line = match self.format {
+            ExportFormat::Jsonl => value.to_string(),
+            ExportFormat::Csv => csv_row(self.columns.iter().map(|column| {
+                let field = value.get(column).unwrap_or(&Value::Null);
+                csv_field(field)
+            })),
+        };
+        self.write_line(&line)?;
+        self.file_records += 1;
+        self.unflushed += 1;
+
+        if self.unflushed >= self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write every record of `stream`, returning the number of records.
+    ///
+    /// Records are written as they arrive; at most one record is held in
+    /// memory besides the write buffer.
+    pub async fn write_stream<T: Serialize>(
+        &mut self,
+        stream: impl Stream<Item = T>,
+    ) -> Result<u64, Error> {
+        let mut stream = std::pin::pin!(stream);
+        let mut written = 0;
+        while let Some(record) = stream.next().await {
+            self.write(&record)?;
+            written += 1;
+        }
+        self.flush()?;
+        Ok(written)
+    }
+
+    /// Flush buffered records to disk.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(file) = &mut self.file {
+            file.writer().flush()?;
+        }
+        self.unflushed = 0;
+        Ok(())
+    }
+
+    /// Flush and close the writer, returning the files written.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>, Error> {
+        self.close()?;
+        Ok(std::mem::take(&mut self.files))
+    }
+
+    /// Flush and close the current file.
+    fn close(&mut self) -> Result<(), Error> {
+        self.flush()?;
+        if let Some(file) = self.file.take() {
+            file.finish()?;
+        }
+        Ok(())
+    }
+
+    fn should_rotate(&self) -> bool {
+        let Rotation {
+            max_bytes,
+            max_records,
+        } = self.rotation;
+        max_bytes.is_some_and(|max| self.file_bytes >= max)
+            || max_records.is_some_and(|max| self.file_records >= max)
+    }
+
+    fn open_next(&mut self, first: &Value) -> Result<(), Error> {
+        self.close()?;
+        let path = if self.rotation.is_enabled() {
+            rotated_path(&self.path, self.files.len(), self.format)
+        } else {
+            self.path.clone()
+        };
+        let file = if self.append {
+            OpenOptions::new().create(true).append(true).open(&path)?
+        } else {
+            File::create(&path)?
+        };
+        self.file_bytes = file.metadata()?.len();
+        let file = BufWriter::new(file);
+        #[cfg(feature = "zstd")]
+        let file = match self.compression {
+            Some(compression) => Sink::Zstd(zstd::stream::write::Encoder::new(
+                file,
+                compression.level(),
+            )?),
+            None => Sink::Plain(file),
+        };
+        #[cfg(not(feature = "zstd"))]
+        let file = Sink::Plain(file);
+        self.file = Some(file);
+        self.files.push(path);
+        self.file_records = 0;
+
+        if self.format == ExportFormat::Csv {
+            let Value::Object(fields) = first else {
+                return Err(Error::Export("CSV records must be objects".to_string()));
+            };
+            self.columns = fields.keys().cloned().collect();
+            if self.file_bytes == 0 {
+                let header = csv_row(self.columns.iter().map(|column| csv_escape(column)));
+                self.write_line(&header)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let file = self.file.as_mut().expect("export file is open").writer();
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")?;
+        self.file_bytes += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+impl Drop for ExportWriter {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Build a GeoJSON `FeatureCollection` with one polygon per detected object.
+///
+/// Each feature carries the `request_id` of the response and the
+/// `confidence` of the object, when known, as properties, so the output can
+/// be loaded directly in GIS tools such as QGIS.
+pub fn geojson(response: &DetectResponse, transform: &GeoTransform) -> Value {
+    let features: Vec<Value> = response
+        .objects
+        .iter()
+        .map(|object| {
+            json!({
+                "type": "Feature",
+                "geometry": object.to_geo(transform).to_geojson(),
+                "properties": {
+                    "request_id": response.request_id,
+                    "confidence": object.confidence,
+                },
+            })
+        })
+        .collect();
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+/// Path of the `index`-th rotated file derived from `path`.
+fn rotated_path(path: &Path, index: usize, format: ExportFormat) -> PathBuf {
+    if path.extension().is_some_and(|extension| extension == "zst") {
+        let rotated = rotated_path(&path.with_extension(""), index, format);
+        let mut name = rotated.into_os_string();
+        name.push(".zst");
+        return name.into();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "export".to_string());
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| format.extension().to_string());
+    path.with_file_name(format!("{stem}-{index:04}.{extension}"))
+}
+
+fn csv_row(fields: impl Iterator<Item = String>) -> String {
+    fields.collect::<Vec<_>>().join(",")
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => csv_escape(text),
+        Value::Bool(_) | Value::Number(_) => value.to_string(),
+        Value::Array(_) | Value::Object(_) => csv_escape(&value.to_string()),
+    }
+}
+
+fn csv_escape(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DetectionObject;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("moondream-export-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let path = temp_path("results.csv");
+        let mut writer = ExportWriter::new(&path, ExportFormat::Csv);
+        writer
+            .write(&json!({"frame": 1, "caption": "a \"red\", car", "boxes": [1, 2]}))
+            .unwrap();
+        writer.write(&json!({"frame": 2, "caption": null})).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "boxes,caption,frame\n\"[1,2]\",\"a \"\"red\"\", car\",1\n,,2\n"
+        );
+    }
+
+    #[test]
+    fn test_append_keeps_existing_records() {
+        let path = temp_path("results.csv");
+        for frame in [1, 2] {
+            let mut writer = ExportWriter::new(&path, ExportFormat::Csv).with_append(true);
+            writer.write(&json!({ "frame": frame })).unwrap();
+            writer.finish().unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "frame\n1\n2\n");
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_stream_rotates_by_records() {
+        let path = temp_path("results.jsonl");
+        let mut writer = ExportWriter::new(&path, ExportFormat::Jsonl)
+            .with_rotation(Rotation::none().with_max_records(2));
+
+        let records = futures::stream::iter((0..5).map(|frame| json!({ "frame": frame })));
+        assert_eq!(writer.write_stream(records).await.unwrap(), 5);
+
+        let files = writer.finish().unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files[1].ends_with("results-0001.jsonl"));
+        assert_eq!(
+            std::fs::read_to_string(&files[2]).unwrap(),
+            "{\"frame\":4}\n"
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_compressed_rotated_files() {
+        let path = temp_path("results.jsonl.zst");
+        let mut writer = ExportWriter::new(&path, ExportFormat::Jsonl)
+            .with_compression(Zstd::default())
+            .with_rotation(Rotation::none().with_max_records(2));
+        for frame in 0..3 {
+            writer.write(&json!({ "frame": frame })).unwrap();
+        }
+        let files = writer.finish().unwrap();
+        assert!(files[0].ends_with("results-0000.jsonl.zst"));
+
+        let read = |path: &Path| zstd::decode_all(File::open(path).unwrap()).unwrap();
+        assert_eq!(read(&files[0]), b"{\"frame\":0}\n{\"frame\":1}\n");
+
+        // Appending adds a frame to the last file.
+        let mut writer = ExportWriter::new(&files[1], ExportFormat::Jsonl)
+            .with_compression(Zstd::default())
+            .with_append(true);
+        writer.write(&json!({ "frame": 3 })).unwrap();
+        drop(writer);
+        assert_eq!(read(&files[1]), b"{\"frame\":2}\n{\"frame\":3}\n");
+    }
+
+    #[test]
+    fn test_geojson_feature_collection() {
+        let response = DetectResponse {
+            request_id: Some("r1".to_string()),
+            objects: vec![DetectionObject {
+                x_min: 0.0,
+                y_min: 0.0,
+                x_max: 1.0,
+                y_max: 1.0,
+                confidence: Some(0.75),
+            }],
+        };
+        let transform = GeoTransform::north_up(10.0, 50.0, 0.25, 0.25, 4, 4);
+
+        let collection = geojson(&response, &transform);
+        assert_eq!(collection["type"], "FeatureCollection");
+        let feature = &collection["features"][0];
+        assert_eq!(feature["geometry"]["type"], "Polygon");
+        assert_eq!(
+            feature["geometry"]["coordinates"][0][2],
+            json!([11.0, 49.0])
+        );
+        assert_eq!(feature["properties"]["confidence"], 0.75);
+    }
+}
@@ -0,0 +1,138 @@
+//! Schema-guided extraction of structured data (feature `schemars`).
+//!
+//! [`MoonDream::extract`] derives a JSON schema from the target type, asks
+//! the model to fill it and deserializes the answer. An answer that does not
+//! fit the type is sent back once together with the error, which fixes most
+//! missing fields and wrong value types.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use schemars::JsonSchema;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, JsonSchema)]
+//! struct Receipt {
+//!     /// Name of the shop.
+//!     merchant: String,
+//!     /// Total amount paid.
+//!     total: f64,
+//!     /// Purchase date, as YYYY-MM-DD.
+//!     date: Option<String>,
+//! }
+//!
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let receipt: Receipt = md
+//!     .extract("https://example.com/receipt.jpg", "Read the receipt.")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, ImageInput, MoonDream, strip_code_fence};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+impl MoonDream {
+    /// Extract a `T` from the image following `instructions`, see the
+    /// [module documentation](crate::extract).
+    ///
+    /// Doc comments on the fields of `T` end up in the schema and guide the
+    /// model. Returns [`Error::InvalidModelOutput`] with the last answer if
+    /// the retry does not fit `T` either.
+    pub async fn extract<T: JsonSchema + DeserializeOwned>(
+        &self,
+        image: impl Into<ImageInput>,
+        instructions: impl Into<String>,
+    ) -> Result<T, Error> {
+        let image = self.prepare_image(image)?;
+        let prompt = prompt::<T>(&instructions.into())?;
+
+        let answer = self
+            .query_prepared(&image, prompt.clone())
+            .await?
+            .data
+            .answer;
+        match parse(answer) {
+            Err(Error::InvalidModelOutput { raw, source }) => {
+                let retry = format!(
+                    "{prompt}\n\nYour previous answer was:\n{raw}\n\nIt is invalid: {source}. \
+                     Answer again with corrected JSON."
+                );
+                parse(self.query_prepared(&image, retry).await?.data.answer)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Prompt asking for a JSON value matching the schema of `T`.
+fn prompt<T: JsonSchema>(instructions: &str) -> Result<String, Error> {
+    let schema = serde_json::to_string_pretty(&schemars::schema_for!(T))?;
+    Ok(format!(
+        "{}\n\nAnswer only with a JSON value matching this JSON schema, without any other \
+         text:\n{schema}",
+        instructions.trim()
+    ))
+}
+
+fn parse<T: DeserializeOwned>(answer: String) -> Result<T, Error> {
+    serde_json::from_str(strip_code_fence(&answer)).map_err(|source| Error::InvalidModelOutput {
+        raw: answer,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Car {
+        /// Main color of the car.
+        color: String,
+        doors: u32,
+    }
+
+    #[test]
+    fn test_prompt_embeds_schema() {
+        let prompt = prompt::<Car>("Describe the car. ").unwrap();
+        assert!(prompt.starts_with("Describe the car.\n\nAnswer only with a JSON value"));
+        assert!(prompt.contains("\"doors\""));
+        assert!(prompt.contains("Main color of the car."));
+    }
+
+    #[tokio::test]
+    async fn test_extract_retries_with_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_string_contains("It is invalid"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "```json\n{\"color\": \"red\", \"doors\": 4}\n```",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "{\"color\": \"red\", \"doors\": \"four\"}",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let car: Car = md.extract("img", "Describe the car.").await.unwrap();
+        assert_eq!(
+            car,
+            Car {
+                color: "red".into(),
+                doors: 4
+            }
+        );
+    }
+}
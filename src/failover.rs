@@ -0,0 +1,210 @@
+//! Failover between regional endpoints.
+//!
+//! [`MoonDream::with_endpoints`] configures a primary endpoint followed by
+//! fallbacks, for example an EU and a US deployment. Every attempt of a call
+//! tries the endpoints in turn until one answers: connection errors,
+//! timeouts and `5xx` responses move on to the next endpoint, other errors
+//! are returned right away. The [`RetryPolicy`](crate::RetryPolicy) applies
+//! on top, a retry going through the endpoints again.
+//!
+//! [`ResponseMeta::endpoint`](crate::ResponseMeta::endpoint) tells which
+//! endpoint served a response and
+//! [`ResponseMeta::failovers`](crate::ResponseMeta::failovers) how many
+//! failed before it. Endpoints that fail, or fail the health check of
+//! [`MoonDream::check_endpoints`], are tried last during a cooldown.
+//!
+//! ```
+//! use moondream::{FailoverPolicy, MoonDream};
+//! use std::time::Duration;
+//!
+//! let md = MoonDream::remote("token")
+//!     .with_endpoints(["https://eu.example.com/v1", "https://us.example.com/v1"])
+//!     .with_failover_policy(FailoverPolicy::new().with_cooldown(Duration::from_secs(60)));
+//! ```
+
+use crate::rt::Instant;
+use crate::{Error, MoonDream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Controls the order in which endpoints are tried.
+///
+/// By default every call starts with the primary endpoint, and endpoints
+/// are tried last for 30 s after they fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailoverPolicy {
+    sticky_primary: bool,
+    cooldown: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FailoverPolicy {
+    /// Prefer the primary endpoint, with a 30 s cooldown.
+    pub fn new() -> Self {
+        Self {
+            sticky_primary: true,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    /// Start every call with the primary endpoint once its cooldown is over
+    /// (default). When `false`, calls stay on the endpoint that served the
+    /// last response until it fails.
+    pub fn with_sticky_primary(mut self, sticky_primary: bool) -> Self {
+        self.sticky_primary = sticky_primary;
+        self
+    }
+
+    /// Try failed endpoints last for `cooldown`; zero keeps the configured
+    /// order.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+/// Fallback endpoints of a client and their state, shared between clones.
+#[derive(Debug, Clone)]
+pub(crate) struct Failover {
+    fallbacks: Vec<String>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Endpoint that served the last response.
+    current: usize,
+    /// End of the cooldown of each endpoint.
+    down_until: Vec<Option<Instant>>,
+}
+
+impl Failover {
+    pub(crate) fn new(fallbacks: Vec<String>) -> Self {
+        let state = State {
+            current: 0,
+            down_until: vec![None; fallbacks.len() + 1],
+        };
+        Self {
+            fallbacks,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Endpoints after the primary one.
+    pub(crate) fn fallbacks(&self) -> &[String] {
+        &self.fallbacks
+    }
+
+    /// Indices of the endpoints to try, the primary one being 0.
+    pub(crate) fn order(&self, policy: &FailoverPolicy) -> Vec<usize> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let count = state.down_until.len();
+        let start = if policy.sticky_primary {
+            0
+        } else {
+            state.current
+        };
+        let now = Instant::now();
+        let (up, down): (Vec<usize>, Vec<usize>) = (0..count)
+            .map(|offset| (start + offset) % count)
+            .partition(|&index| state.down_until[index].is_none_or(|until| until <= now));
+        up.into_iter().chain(down).collect()
+    }
+
+    /// Record that endpoint `index` served a response.
+    pub(crate) fn succeeded(&self, index: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.current = index;
+        state.down_until[index] = None;
+    }
+
+    /// Clear the cooldown of an endpoint that passed its health check.
+    fn recovered(&self, index: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.down_until[index] = None;
+    }
+
+    /// Start the cooldown of endpoint `index`.
+    pub(crate) fn failed(&self, index: usize, policy: &FailoverPolicy) {
+        if policy.cooldown.is_zero() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.down_until[index] = Some(Instant::now() + policy.cooldown);
+    }
+
+    /// Return `true` if another endpoint may succeed where this one failed.
+    pub(crate) fn should_fail_over(error: &Error) -> bool {
+        match error {
+            Error::PointError(error) => {
+                error.is_connect()
+                    || error.is_timeout()
+                    || error
+                        .status()
+                        .is_some_and(|status| status.is_server_error())
+            }
+            Error::Status { status, .. } | Error::ModelLoading { status, .. } => {
+                status.is_server_error()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl MoonDream {
+    /// Check the health of every endpoint, see the
+    /// [module documentation](crate::failover).
+    ///
+    /// Endpoints that are down start a cooldown. Returns each endpoint with
+    /// `true` if it is up.
+    pub async fn check_endpoints(&self) -> Vec<(String, bool)> {
+        let mut endpoints = vec![self.endpoint.clone()];
+        if let Some(failover) = &self.failover {
+            endpoints.extend(failover.fallbacks().iter().cloned());
+        }
+
+        let checks = endpoints.iter().map(|endpoint| async move {
+            let client = self.clone().with_endpoint(endpoint);
+            client.health().await.is_ok_and(|health| health.is_up())
+        });
+        let up = futures::future::join_all(checks).await;
+
+        if let Some(failover) = &self.failover {
+            for (index, up) in up.iter().enumerate() {
+                if *up {
+                    failover.recovered(index);
+                } else {
+                    failover.failed(index, &self.failover_policy);
+                }
+            }
+        }
+        endpoints.into_iter().zip(up).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_prefers_available_endpoints() {
+        let failover = Failover::new(vec!["b".into(), "c".into()]);
+        let policy = FailoverPolicy::new();
+        assert_eq!(failover.order(&policy), [0, 1, 2]);
+
+        failover.failed(0, &policy);
+        assert_eq!(failover.order(&policy), [1, 2, 0]);
+
+        failover.succeeded(2);
+        let roaming = policy.with_sticky_primary(false);
+        assert_eq!(failover.order(&roaming), [2, 1, 0]);
+
+        failover.failed(1, &policy.with_cooldown(Duration::ZERO));
+        assert_eq!(failover.order(&policy), [1, 2, 0]);
+    }
+}
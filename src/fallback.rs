@@ -0,0 +1,318 @@
+//! Graceful degradation of questions through a fallback chain.
+//!
+//! A [`FallbackChain`] lists steps from the most to the least demanding,
+//! for example a structured extraction, then a simpler question, then a
+//! caption. [`MoonDream::query_with_fallback`] runs them in order on the
+//! same image until one succeeds and reports which [`Fallback::level`] did,
+//! so a user interface always has something useful to show.
+//!
+//! A step fails when its request fails or its answer does not validate: a
+//! structured answer must deserialize into `T`, and a plain answer must not
+//! be empty and pass the validator of the step, if any. Cancellations and
+//! expired deadlines stop the chain.
+//!
+//! ```no_run
+//! use moondream::fallback::{FallbackAnswer, FallbackChain};
+//! use moondream::MoonDream;
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Car {
+//!     color: String,
+//!     doors: u32,
+//! }
+//!
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let chain = FallbackChain::<Car>::new()
+//!     .then_structured(r#"Describe the car as {"color": string, "doors": number}."#)
+//!     .then_question("What color is the car?")
+//!     .then_caption(None);
+//! let result = md.query_with_fallback("https://example.com/car.jpg", &chain).await?;
+//! match result.answer {
+//!     FallbackAnswer::Structured(car) => println!("{} car, {} doors", car.color, car.doors),
+//!     FallbackAnswer::Answer(text) | FallbackAnswer::Caption(text) => println!("{text}"),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{CaptionLength, Error, ImageInput, MoonDream, strip_code_fence};
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+type Validator = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+enum Step {
+    Structured(String),
+    Question(String, Option<Validator>),
+    Caption(Option<CaptionLength>),
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Structured(question) => f.debug_tuple("Structured").field(question).finish(),
+            Step::Question(question, validator) => f
+                .debug_struct("Question")
+                .field("question", question)
+                .field("validated", &validator.is_some())
+                .finish(),
+            Step::Caption(length) => f.debug_tuple("Caption").field(length).finish(),
+        }
+    }
+}
+
+/// Steps of a [`MoonDream::query_with_fallback`] call, tried in order.
+#[derive(Debug, Clone)]
+pub struct FallbackChain<T> {
+    steps: Vec<Step>,
+    target: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for FallbackChain<T> {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            target: PhantomData,
+        }
+    }
+}
+
+impl<T> FallbackChain<T> {
+    /// Create an empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask `question` for a JSON answer, deserialized into `T` as with
+    /// [`MoonDream::query_json`].
+    pub fn then_structured(mut self, question: impl Into<String>) -> Self {
+        self.steps.push(Step::Structured(question.into()));
+        self
+    }
+
+    /// Ask `question`, accepting any non-empty answer.
+    pub fn then_question(mut self, question: impl Into<String>) -> Self {
+        self.steps.push(Step::Question(question.into(), None));
+        self
+    }
+
+    /// Ask `question`, accepting non-empty answers for which `validate`
+    /// returns `true`.
+    pub fn then_question_validated(
+        mut self,
+        question: impl Into<String>,
+        validate: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.steps
+            .push(Step::Question(question.into(), Some(Arc::new(validate))));
+        self
+    }
+
+    /// Caption the image.
+    pub fn then_caption(mut self, length: Option<CaptionLength>) -> Self {
+        self.steps.push(Step::Caption(length));
+        self
+    }
+
+    /// Number of steps.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Return `true` if the chain has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// Answer of the step that succeeded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FallbackAnswer<T> {
+    /// Deserialized answer of a structured step.
+    Structured(T),
+    /// Answer of a question step.
+    Answer(String),
+    /// Caption of a caption step.
+    Caption(String),
+}
+
+/// Result of a [`MoonDream::query_with_fallback`] call.
+#[derive(Debug)]
+pub struct Fallback<T> {
+    /// Answer of the first step that succeeded.
+    pub answer: FallbackAnswer<T>,
+    /// Index of that step in the chain, 0 when the first step succeeded.
+    pub level: usize,
+    /// Errors of the steps tried before it.
+    pub failures: Vec<Error>,
+}
+
+impl<T> Fallback<T> {
+    /// Return `true` if an earlier step failed.
+    pub fn is_degraded(&self) -> bool {
+        self.level > 0
+    }
+}
+
+impl MoonDream {
+    /// Run the steps of `chain` on `image` until one succeeds, see the
+    /// [module documentation](crate::fallback).
+    ///
+    /// Returns the error of the last step when all of them fail, and
+    /// [`Error::InvalidConfig`] for an empty chain.
+    pub async fn query_with_fallback<T: DeserializeOwned>(
+        &self,
+        image: impl Into<ImageInput>,
+        chain: &FallbackChain<T>,
+    ) -> Result<Fallback<T>, Error> {
+        if chain.is_empty() {
+            return Err(Error::InvalidConfig("empty fallback chain".into()));
+        }
+        let image = self.prepare_image(image)?;
+        let mut failures = Vec::new();
+        for (level, step) in chain.steps.iter().enumerate() {
+            match self.fallback_step(&image, step).await {
+                Ok(answer) => {
+                    return Ok(Fallback {
+                        answer,
+                        level,
+                        failures,
+                    });
+                }
+                Err(error @ (Error::Cancelled | Error::DeadlineExceeded)) => return Err(error),
+                Err(error) => failures.push(error),
+            }
+        }
+        Err(failures.pop().unwrap_or(Error::Cancelled))
+    }
+
+    async fn fallback_step<T: DeserializeOwned>(
+        &self,
+        image: &str,
+        step: &Step,
+    ) -> Result<FallbackAnswer<T>, Error> {
+        match step {
+            Step::Structured(question) => {
+                let question =
+                    format!("{question}\n\nAnswer only with valid JSON, without any other text.");
+                let answer = self.query_prepared(image, question).await?.data.answer;
+                serde_json::from_str(strip_code_fence(&answer))
+                    .map(FallbackAnswer::Structured)
+                    .map_err(|source| Error::InvalidModelOutput {
+                        raw: answer,
+                        source,
+                    })
+            }
+            Step::Question(question, validate) => {
+                let answer = self
+                    .query_prepared(image, question.clone())
+                    .await?
+                    .data
+                    .answer;
+                let valid = !answer.trim().is_empty()
+                    && validate.as_ref().is_none_or(|validate| validate(&answer));
+                if valid {
+                    Ok(FallbackAnswer::Answer(answer))
+                } else {
+                    Err(Error::UnexpectedAnswer {
+                        task: "query",
+                        answer,
+                    })
+                }
+            }
+            Step::Caption(length) => {
+                let caption = self.caption_prepared(image, *length).await?.data.caption;
+                Ok(FallbackAnswer::Caption(caption))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Car {
+        doors: u32,
+    }
+
+    #[tokio::test]
+    async fn test_fallback_to_caption() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_string_contains("valid JSON"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "It has four doors.",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_string_contains("What color"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "purple",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "A car parked on a street.",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let chain = FallbackChain::<Car>::new()
+            .then_structured("Describe the car.")
+            .then_question_validated("What color is the car?", |answer| {
+                ["red", "blue", "black", "white"].contains(&answer)
+            })
+            .then_caption(None);
+        let result = md.query_with_fallback("img", &chain).await.unwrap();
+        assert_eq!(
+            result.answer,
+            FallbackAnswer::Caption("A car parked on a street.".into())
+        );
+        assert_eq!(result.level, 2);
+        assert!(matches!(
+            result.failures.as_slice(),
+            [
+                Error::InvalidModelOutput { .. },
+                Error::UnexpectedAnswer { .. }
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_structured_first() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "```json\n{\"doors\": 4}\n```",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let chain = FallbackChain::new()
+            .then_structured("Describe the car.")
+            .then_caption(None);
+        let result = md.query_with_fallback("img", &chain).await.unwrap();
+        assert_eq!(result.answer, FallbackAnswer::Structured(Car { doors: 4 }));
+        assert!(!result.is_degraded());
+        assert!(matches!(
+            md.query_with_fallback("img", &FallbackChain::<Car>::new())
+                .await,
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
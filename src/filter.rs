@@ -0,0 +1,155 @@
+//! Content-safety post-filter for generated text.
+//!
+//! A [`ContentFilter`] installed with
+//! [`MoonDream::with_content_filter`](crate::MoonDream::with_content_filter)
+//! scans every caption and answer against a denylist of words and regular
+//! expressions. Matches are either redacted or left in place, and in both
+//! cases the response carries [`ResultFlags::CONTENT_FILTERED`].
+
+use crate::ResultFlags;
+use regex::{NoExpand, Regex, RegexBuilder};
+
+/// What to do with text that matches a [`ContentFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterAction {
+    /// Replace every match with the filter's replacement text.
+    #[default]
+    Redact,
+    /// Keep the text unchanged and only flag the result.
+    Flag,
+}
+
+/// Denylist of words and patterns applied to captions and answers.
+///
+/// ```
+/// use moondream::{ContentFilter, MoonDream};
+///
+/// let filter = ContentFilter::new()
+///     .deny_words(["gore", "nudity"])
+///     .with_replacement("***");
+/// let md = MoonDream::remote("token").with_content_filter(filter);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContentFilter {
+    patterns: Vec<Regex>,
+    action: FilterAction,
+    replacement: String,
+}
+
+impl Default for ContentFilter {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            action: FilterAction::default(),
+            replacement: String::from("[redacted]"),
+        }
+    }
+}
+
+impl ContentFilter {
+    /// Create an empty filter that redacts matches with `[redacted]`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deny a whole word, matched case-insensitively.
+    pub fn deny_word(mut self, word: impl AsRef<str>) -> Self {
+        let pattern = format!(r"\b{}\b", regex::escape(word.as_ref()));
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .expect("escaped word is a valid regex");
+        self.patterns.push(regex);
+        self
+    }
+
+    /// Deny every word in `words`.
+    pub fn deny_words<I>(self, words: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        words.into_iter().fold(self, Self::deny_word)
+    }
+
+    /// Deny every match of `regex`.
+    pub fn deny_regex(mut self, regex: Regex) -> Self {
+        self.patterns.push(regex);
+        self
+    }
+
+    /// Set the [`FilterAction`] applied to matches.
+    pub fn with_action(mut self, action: FilterAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Set the text substituted for matches when redacting.
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Return `true` if `text` matches any denied word or pattern.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.patterns.iter().any(|regex| regex.is_match(text))
+    }
+
+    /// Filter `text` in place and return the resulting flags.
+    pub fn apply(&self, text: &mut String) -> ResultFlags {
+        if !self.is_match(text) {
+            return ResultFlags::empty();
+        }
+
+        if self.action == FilterAction::Redact {
+            for regex in &self.patterns {
+                let redacted = regex
+                    .replace_all(text, NoExpand(&self.replacement))
+                    .into_owned();
+                *text = redacted;
+            }
+        }
+        ResultFlags::CONTENT_FILTERED
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_filter_redacts_words() {
+        let filter = ContentFilter::new().deny_words(["cat", "dog"]);
+        let mut text = String::from("A Cat chases a dog near a category sign");
+
+        let flags = filter.apply(&mut text);
+
+        assert!(flags.contains(ResultFlags::CONTENT_FILTERED));
+        assert_eq!(
+            text,
+            "A [redacted] chases a [redacted] near a category sign"
+        );
+    }
+
+    #[test]
+    fn test_content_filter_flags_only() {
+        let filter = ContentFilter::new()
+            .deny_regex(Regex::new(r"\d{3}-\d{4}").unwrap())
+            .with_action(FilterAction::Flag);
+        let mut text = String::from("Call 555-1234");
+
+        let flags = filter.apply(&mut text);
+
+        assert_eq!(flags, ResultFlags::CONTENT_FILTERED);
+        assert_eq!(text, "Call 555-1234");
+    }
+
+    #[test]
+    fn test_content_filter_no_match() {
+        let filter = ContentFilter::new().deny_word("gore");
+        let mut text = String::from("A sunny beach");
+
+        assert!(filter.apply(&mut text).is_empty());
+        assert_eq!(text, "A sunny beach");
+    }
+}
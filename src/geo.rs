@@ -0,0 +1,184 @@
+//! Geo-referenced detections for aerial and satellite imagery.
+//!
+//! A [`GeoTransform`] maps pixels of one image to geographic coordinates
+//! with the same six coefficients as GDAL. Attach it to an image with
+//! [`ImageInput::with_geo_transform`] and use [`MoonDream::detect_geo`], or
+//! convert results yourself with [`DetectionObject::to_geo`] and
+//! [`Point::to_geo`].
+
+use crate::{DetectionObject, Error, ImageInput, MoonDream, Point};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// Affine transform from the pixels of an image to longitude / latitude.
+///
+/// For a pixel `(col, row)`:
+///
+/// ```text
+/// lon = c[0] + col * c[1] + row * c[2]
+/// lat = c[3] + col * c[4] + row * c[5]
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoTransform {
+    coefficients: [f64; 6],
+    width: u32,
+    height: u32,
+}
+
+impl GeoTransform {
+    /// Create a transform from GDAL coefficients for an image of `width` x
+    /// `height` pixels.
+    pub fn new(coefficients: [f64; 6], width: u32, height: u32) -> Self {
+        Self {
+            coefficients,
+            width,
+            height,
+        }
+    }
+
+    /// Create a north-up transform from the coordinates of the top-left
+    /// corner and the size of a pixel in degrees.
+    pub fn north_up(
+        west: f64,
+        north: f64,
+        pixel_width: f64,
+        pixel_height: f64,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        Self::new(
+            [west, pixel_width, 0.0, north, 0.0, -pixel_height],
+            width,
+            height,
+        )
+    }
+
+    /// GDAL coefficients of the transform.
+    pub fn coefficients(&self) -> [f64; 6] {
+        self.coefficients
+    }
+
+    /// Convert normalized image coordinates (0-1) to `(lon, lat)`.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let [c0, c1, c2, c3, c4, c5] = self.coefficients;
+        let col = x * f64::from(self.width);
+        let row = y * f64::from(self.height);
+        (c0 + col * c1 + row * c2, c3 + col * c4 + row * c5)
+    }
+}
+
+/// A closed polygon in geographic coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoPolygon {
+    /// `(lon, lat)` vertices; the first vertex is repeated at the end.
+    pub ring: Vec<(f64, f64)>,
+}
+
+impl GeoPolygon {
+    /// GeoJSON `Polygon` geometry of the polygon.
+    pub fn to_geojson(&self) -> Value {
+        let ring: Vec<[f64; 2]> = self.ring.iter().map(|&(lon, lat)| [lon, lat]).collect();
+        json!({
+            "type": "Polygon",
+            "coordinates": [ring],
+        })
+    }
+}
+
+impl DetectionObject {
+    /// Project the bounding box to geographic coordinates.
+    ///
+    /// The four corners are transformed, so rotated transforms produce
+    /// rotated polygons.
+    pub fn to_geo(&self, transform: &GeoTransform) -> GeoPolygon {
+        let corners = [
+            (self.x_min, self.y_min),
+            (self.x_max, self.y_min),
+            (self.x_max, self.y_max),
+            (self.x_min, self.y_max),
+            (self.x_min, self.y_min),
+        ];
+        GeoPolygon {
+            ring: corners
+                .iter()
+                .map(|&(x, y)| transform.apply(x, y))
+                .collect(),
+        }
+    }
+}
+
+impl Point {
+    /// Project the point to `(lon, lat)`.
+    pub fn to_geo(&self, transform: &GeoTransform) -> (f64, f64) {
+        transform.apply(self.x, self.y)
+    }
+}
+
+/// An image together with its [`GeoTransform`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoImage {
+    /// The image.
+    pub image: ImageInput,
+    /// Transform of the image pixels.
+    pub transform: GeoTransform,
+}
+
+impl ImageInput {
+    /// Attach a geographic transform to the image.
+    pub fn with_geo_transform(self, transform: GeoTransform) -> GeoImage {
+        GeoImage {
+            image: self,
+            transform,
+        }
+    }
+}
+
+impl From<GeoImage> for ImageInput {
+    fn from(image: GeoImage) -> Self {
+        image.image
+    }
+}
+
+impl MoonDream {
+    /// Detect `object` in a geo-referenced image and return the bounding
+    /// boxes as geographic polygons.
+    pub async fn detect_geo(
+        &self,
+        image: GeoImage,
+        object: impl Into<String>,
+    ) -> Result<Vec<GeoPolygon>, Error> {
+        let transform = image.transform;
+        let response = self.detect(image, object).await?;
+        Ok(response
+            .objects
+            .iter()
+            .map(|object| object.to_geo(&transform))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detection_to_geo() {
+        let transform = GeoTransform::north_up(10.0, 50.0, 0.25, 0.25, 4, 2);
+        let object = DetectionObject {
+            x_min: 0.0,
+            y_min: 0.0,
+            x_max: 0.5,
+            y_max: 1.0,
+            confidence: None,
+        };
+
+        let polygon = object.to_geo(&transform);
+        assert_eq!(polygon.ring.len(), 5);
+        assert_eq!(polygon.ring[0], (10.0, 50.0));
+        assert_eq!(polygon.ring[2], (10.5, 49.5));
+        assert_eq!(
+            polygon.to_geojson()["coordinates"][0][1],
+            serde_json::json!([10.5, 50.0])
+        );
+    }
+}
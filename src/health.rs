@@ -0,0 +1,183 @@
+//! Health checks, mainly for local deployments.
+//!
+//! [`MoonDream::health`] calls `GET {endpoint}/health`. Servers without a
+//! health endpoint still prove they are up by answering, which is reported
+//! as [`HealthStatus::Unknown`]. Unreachable servers fail with
+//! [`Error::Unavailable`]. [`MoonDream::verify_token`] checks the API key
+//! without running any inference.
+
+use crate::rt::Instant;
+use crate::{Error, MoonDream};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// State reported by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HealthStatus {
+    /// The server reported itself ready.
+    Healthy,
+    /// The server answered the health check with an error.
+    Unhealthy,
+    /// The server is up but has no health endpoint.
+    Unknown,
+}
+
+/// Result of [`MoonDream::health`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Health {
+    /// State reported by the server.
+    pub status: HealthStatus,
+    /// Server version, when reported.
+    pub version: Option<String>,
+    /// Loaded model, when reported.
+    pub model: Option<String>,
+    /// Round-trip time of the health check.
+    pub latency: Duration,
+}
+
+impl Health {
+    /// Return `true` unless the server reported itself unhealthy.
+    pub fn is_up(&self) -> bool {
+        self.status != HealthStatus::Unhealthy
+    }
+}
+
+impl MoonDream {
+    /// Check that the server is reachable and ready, see the
+    /// [module documentation](crate::health).
+    pub async fn health(&self) -> Result<Health, Error> {
+        #[derive(Deserialize, Default)]
+        struct Body {
+            status: Option<String>,
+            version: Option<String>,
+            model: Option<String>,
+        }
+
+        let start = Instant::now();
+        let template = self.template()?;
+        let request = template.authorize(self.client.get(template.url("health")?));
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.timeout(self.timeout);
+        let response = request.send().await.map_err(|e| self.unavailable(e))?;
+        let latency = start.elapsed();
+
+        let status = response.status();
+        let body: Body = match response.bytes().await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(_) => Body::default(),
+        };
+        let reported_ok = body
+            .status
+            .as_deref()
+            .is_none_or(|status| matches!(status, "ok" | "healthy" | "ready" | "up"));
+        let status = match status {
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => HealthStatus::Unknown,
+            status if status.is_success() && reported_ok => HealthStatus::Healthy,
+            _ => HealthStatus::Unhealthy,
+        };
+
+        Ok(Health {
+            status,
+            version: body.version,
+            model: body.model,
+            latency,
+        })
+    }
+
+    /// Check that the server accepts the token of the client.
+    ///
+    /// Sends an empty `query` request, which is rejected after
+    /// authentication, so no inference is run. Returns `false` on
+    /// `401 Unauthorized` and `403 Forbidden`.
+    pub async fn verify_token(&self) -> Result<bool, Error> {
+        let template = self.template()?;
+        let request = template
+            .authorize(self.client.post(template.url("query")?))
+            .json(&serde_json::json!({}));
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.timeout(self.timeout);
+        let response = request.send().await.map_err(|e| self.unavailable(e))?;
+
+        let status = response.status();
+        if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            return Ok(false);
+        }
+        if status.is_server_error() {
+            response.error_for_status()?;
+        }
+        Ok(true)
+    }
+
+    /// Wrap a connection failure into [`Error::Unavailable`].
+    pub(crate) fn unavailable(&self, source: reqwest::Error) -> Error {
+        Error::Unavailable {
+            endpoint: self.endpoint.clone(),
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_health() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": "ok",
+                "version": "0.1.2",
+                "model": "moondream-2b",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let health = md.health().await.unwrap();
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert_eq!(health.model.as_deref(), Some("moondream-2b"));
+    }
+
+    #[tokio::test]
+    async fn test_health_fallbacks() {
+        let server = MockServer::start().await;
+        let md = MoonDream::local(server.uri());
+        let health = md.health().await.unwrap();
+        assert_eq!(health.status, HealthStatus::Unknown);
+        assert!(health.is_up());
+
+        let md = MoonDream::local("http://127.0.0.1:1");
+        assert!(matches!(
+            md.health().await.unwrap_err(),
+            Error::Unavailable { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_token() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header("X-Moondream-Auth", "good"))
+            .respond_with(ResponseTemplate::new(422))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::new("good".to_string()).with_endpoint(server.uri());
+        assert!(md.verify_token().await.unwrap());
+        let md = MoonDream::new("bad".to_string()).with_endpoint(server.uri());
+        assert!(!md.verify_token().await.unwrap());
+    }
+}
@@ -0,0 +1,296 @@
+//! Image inputs accepted by the client.
+
+use crate::Error;
+use base64::{Engine as _, engine::general_purpose};
+use std::path::{Path, PathBuf};
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+use std::sync::Arc;
+
+/// An image to analyze.
+///
+/// Strings convert into [`ImageInput::Url`], so existing code passing remote
+/// URLs or base64 `data:` URIs keeps working. Raw bytes are encoded into a
+/// data URI when the request is sent. Files are read when the request is
+/// sent, or streamed from disk with
+/// [`ImageTransport::Multipart`](crate::ImageTransport::Multipart).
+///
+/// The `Mapped` variant only exists with the `mmap` feature, so matches need
+/// a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageInput {
+    /// A remote URL or an already encoded base64 `data:` URI.
+    Url(String),
+    /// Encoded image bytes (PNG, JPEG, ...) and their MIME type.
+    Bytes {
+        /// Encoded image data.
+        data: Vec<u8>,
+        /// MIME type of `data`, for example `image/png`.
+        mime: String,
+    },
+    /// An image file, read when the request is sent.
+    File {
+        /// Path of the file.
+        path: PathBuf,
+        /// MIME type of the file, for example `image/jpeg`.
+        mime: String,
+    },
+    /// A memory-mapped image file (feature `mmap`).
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    Mapped {
+        /// Mapped contents of the file.
+        file: MappedFile,
+        /// MIME type of the file, for example `image/tiff`.
+        mime: String,
+    },
+}
+
+impl ImageInput {
+    /// Create an input from a remote URL or a base64 `data:` URI.
+    pub fn url(url: impl Into<String>) -> Self {
+        ImageInput::Url(url.into())
+    }
+
+    /// Create an input from encoded image bytes.
+    pub fn bytes(data: impl Into<Vec<u8>>, mime: impl Into<String>) -> Self {
+        ImageInput::Bytes {
+            data: data.into(),
+            mime: mime.into(),
+        }
+    }
+
+    /// Read an image file, guessing its MIME type from the extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        Ok(ImageInput::bytes(data, mime_from_path(path)))
+    }
+
+    /// Refer to an image file without reading it, guessing its MIME type
+    /// from the extension.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mime = mime_from_path(&path).to_string();
+        ImageInput::File { path, mime }
+    }
+
+    /// Map an image file into memory, guessing its MIME type from the
+    /// extension (feature `mmap`).
+    ///
+    /// Pages are loaded by the operating system as they are read, so
+    /// decoders and tile readers can work on multi-GB scans without copying
+    /// the file to the heap. See [`MappedFile::open`] for the caveats.
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    pub fn from_mmap(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        Ok(ImageInput::Mapped {
+            file: MappedFile::open(path)?,
+            mime: mime_from_path(path).to_string(),
+        })
+    }
+
+    /// Path and MIME type of file inputs, which can be streamed from disk.
+    pub(crate) fn local_file(&self) -> Option<(&Path, &str)> {
+        match self {
+            ImageInput::File { path, mime } => Some((path, mime)),
+            #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+            ImageInput::Mapped { file, mime } => Some((file.path(), mime)),
+            _ => None,
+        }
+    }
+
+    /// Return the value sent as `image_url`: the URL itself, or the bytes
+    /// encoded as a base64 `data:` URI. Files are read first.
+    pub fn into_url(self) -> Result<String, Error> {
+        Ok(match self {
+            ImageInput::Url(url) => url,
+            ImageInput::Bytes { data, mime } => encode_data_uri(&mime, &data),
+            ImageInput::File { path, mime } => encode_data_uri(&mime, &std::fs::read(path)?),
+            #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+            ImageInput::Mapped { file, mime } => encode_data_uri(&mime, &file),
+        })
+    }
+}
+
+/// A read-only memory map of a file, cheap to clone (feature `mmap`).
+///
+/// Dereferences to the bytes of the file. Two maps are equal when they have
+/// the same contents.
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+#[derive(Clone)]
+pub struct MappedFile {
+    path: PathBuf,
+    map: Arc<memmap2::Mmap>,
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl MappedFile {
+    /// Map the file at `path`.
+    ///
+    /// The file must not be truncated or modified while it is mapped:
+    /// reading a truncated map crashes the process, and modifications show
+    /// through.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let file = std::fs::File::open(&path)?;
+        // SAFETY: the file is opened read-only, and the caller is told not
+        // to modify it while it is mapped.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Self {
+            path,
+            map: Arc::new(map),
+        })
+    }
+
+    /// Path of the mapped file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl std::ops::Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl std::fmt::Debug for MappedFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MappedFile")
+            .field("path", &self.path)
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl PartialEq for MappedFile {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.map, &other.map) || self.map[..] == other.map[..]
+    }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl Eq for MappedFile {}
+
+impl From<String> for ImageInput {
+    fn from(url: String) -> Self {
+        ImageInput::Url(url)
+    }
+}
+
+impl From<&str> for ImageInput {
+    fn from(url: &str) -> Self {
+        ImageInput::Url(url.to_string())
+    }
+}
+
+impl From<&String> for ImageInput {
+    fn from(url: &String) -> Self {
+        ImageInput::Url(url.clone())
+    }
+}
+
+/// Guess the MIME type of an image from its file extension.
+pub(crate) fn mime_from_path(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        Some("bmp") => "image/bmp",
+        Some("tif" | "tiff") => "image/tiff",
+        Some("dcm") => "application/dicom",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Split a base64 `data:` URI into its MIME type and decoded bytes.
+///
+/// Returns `None` when `image` is not a base64 data URI.
+pub(crate) fn decode_data_uri(image: &str) -> Option<Result<(&str, Vec<u8>), Error>> {
+    let (mime, payload) = image.strip_prefix("data:")?.split_once(";base64,")?;
+    Some(
+        general_purpose::STANDARD
+            .decode(payload)
+            .map(|bytes| (mime, bytes))
+            .map_err(|e| Error::InvalidImage(e.to_string())),
+    )
+}
+
+/// Encode `bytes` as a base64 `data:` URI.
+pub(crate) fn encode_data_uri(mime: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime,
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_input_into_url() {
+        assert_eq!(
+            ImageInput::from("https://example.com/cat.png")
+                .into_url()
+                .unwrap(),
+            "https://example.com/cat.png"
+        );
+        assert_eq!(
+            ImageInput::bytes(vec![0, 1], "image/png")
+                .into_url()
+                .unwrap(),
+            "data:image/png;base64,AAE="
+        );
+        assert!(ImageInput::file("missing.png").into_url().is_err());
+    }
+
+    #[test]
+    fn test_data_uri_round_trip() {
+        let uri = encode_data_uri("image/jpeg", b"jpeg");
+        let (mime, bytes) = decode_data_uri(&uri).unwrap().unwrap();
+
+        assert_eq!(mime, "image/jpeg");
+        assert_eq!(bytes, b"jpeg");
+        assert!(decode_data_uri("https://example.com/cat.png").is_none());
+    }
+
+    #[test]
+    fn test_mime_from_path() {
+        assert_eq!(mime_from_path(Path::new("photo.JPG")), "image/jpeg");
+        assert_eq!(mime_from_path(Path::new("scan.tiff")), "image/tiff");
+        assert_eq!(
+            mime_from_path(Path::new("blob")),
+            "application/octet-stream"
+        );
+    }
+
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    #[test]
+    fn test_from_mmap() {
+        let path = std::env::temp_dir().join(format!("moondream-{}.png", uuid::Uuid::new_v4()));
+        std::fs::write(&path, [0, 1]).unwrap();
+        let input = ImageInput::from_mmap(&path).unwrap();
+        assert_eq!(input.local_file(), Some((path.as_path(), "image/png")));
+        assert_eq!(input.into_url().unwrap(), "data:image/png;base64,AAE=");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
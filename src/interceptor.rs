@@ -0,0 +1,47 @@
+//! Request and response interceptors.
+//!
+//! Interceptors registered with
+//! [`MoonDream::with_request_interceptor`](crate::MoonDream::with_request_interceptor)
+//! can mutate every outgoing HTTP request (dynamic auth headers, signatures,
+//! ...). Those registered with
+//! [`MoonDream::with_response_interceptor`](crate::MoonDream::with_response_interceptor)
+//! observe every HTTP response, including error statuses, before it is
+//! decoded. Interceptors run in registration order, once per attempt.
+
+use crate::Error;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{Request, StatusCode};
+use std::time::Duration;
+
+/// Hook run on every outgoing request.
+#[async_trait]
+pub trait RequestInterceptor: std::fmt::Debug + Send + Sync {
+    /// Inspect or mutate `request` before it is sent.
+    ///
+    /// Returning an error aborts the call; wrap custom errors in
+    /// [`Error::Interceptor`].
+    async fn intercept(&self, request: &mut Request) -> Result<(), Error>;
+}
+
+/// Hook run on every HTTP response.
+#[async_trait]
+pub trait ResponseInterceptor: std::fmt::Debug + Send + Sync {
+    /// Observe `response` before its body is read.
+    async fn observe(&self, response: &ResponseContext<'_>);
+}
+
+/// View of an HTTP response passed to a [`ResponseInterceptor`].
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseContext<'a> {
+    /// URL the request was sent to.
+    pub url: &'a str,
+    /// HTTP status of the response.
+    pub status: StatusCode,
+    /// Response headers.
+    pub headers: &'a HeaderMap,
+    /// Time between sending the request and receiving the headers.
+    pub elapsed: Duration,
+    /// Number of the attempt, starting at 1.
+    pub attempt: u32,
+}
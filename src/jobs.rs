@@ -0,0 +1,368 @@
+//! Asynchronous jobs for long-running requests.
+//!
+//! Self-hosted servers can accept a request, answer right away with a job
+//! identifier and run the inference in the background, so slow inferences
+//! do not hold a connection open until the HTTP timeout.
+//! [`MoonDream::submit_query`] posts to `{endpoint}/jobs/query` and returns a
+//! [`JobId`]; [`MoonDream::poll`] reads `GET {endpoint}/jobs/{id}` once and
+//! [`MoonDream::await_result`] polls until the job is done, backing off
+//! according to a [`PollPolicy`]. The deadline and cancellation token of the
+//! [`RequestOptions`](crate::RequestOptions) bound the wait.
+//!
+//! The server answers the submission with `{"job_id": "..."}` and polls with
+//! `{"status": "running"}`, then `{"status": "completed", "result": {...}}`
+//! or `{"status": "failed", "error": "..."}`.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::jobs::PollPolicy;
+//!
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let job = md
+//!     .submit_query("https://example.com/page.png", "Transcribe the page.")
+//!     .await?;
+//! let response = md.await_result(&job, &PollPolicy::new()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::rt::{self, Instant};
+use crate::{Error, ImageInput, MoonDream, QueryResponse};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Identifier of a job producing a `T`.
+pub struct JobId<T = QueryResponse> {
+    id: String,
+    output: PhantomData<fn() -> T>,
+}
+
+impl<T> JobId<T> {
+    /// Refer to an existing job, for example one submitted by another
+    /// process.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            output: PhantomData,
+        }
+    }
+
+    /// Identifier assigned by the server.
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+}
+
+impl<T> Clone for JobId<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.id.clone())
+    }
+}
+
+impl<T> fmt::Debug for JobId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("JobId").field(&self.id).finish()
+    }
+}
+
+impl<T> fmt::Display for JobId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+impl<T> PartialEq for JobId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for JobId<T> {}
+
+/// State of a job, returned by [`MoonDream::poll`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus<T> {
+    /// Waiting to be scheduled.
+    Pending,
+    /// Being processed.
+    Running,
+    /// Finished with a result.
+    Completed(T),
+    /// Finished with the given error message.
+    Failed(String),
+}
+
+impl<T> JobStatus<T> {
+    /// Return `true` once the job completed or failed.
+    pub fn is_done(&self) -> bool {
+        matches!(self, JobStatus::Completed(_) | JobStatus::Failed(_))
+    }
+}
+
+/// How often [`MoonDream::await_result`] polls a job.
+///
+/// The interval starts at 1 s and grows by half after each poll, up to 30 s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollPolicy {
+    interval: Duration,
+    max_interval: Duration,
+    multiplier: f64,
+}
+
+impl Default for PollPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PollPolicy {
+    /// Poll after 1 s, then 1.5 times later each time, up to every 30 s.
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+            multiplier: 1.5,
+        }
+    }
+
+    /// Set the delay before the second poll.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the upper bound of the delay between polls.
+    pub fn with_max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Set the factor applied to the delay after each poll; 1 polls at a
+    /// fixed interval.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Delay after the given poll, starting at 1.
+    pub fn delay(&self, poll: u32) -> Duration {
+        let factor = self.multiplier.powi(poll.saturating_sub(1) as i32);
+        let secs = self.interval.as_secs_f64() * factor;
+        if secs >= self.max_interval.as_secs_f64() {
+            self.max_interval
+        } else {
+            Duration::from_secs_f64(secs)
+        }
+    }
+}
+
+impl MoonDream {
+    /// Submit a `/query` request as a job, see the
+    /// [module documentation](crate::jobs).
+    ///
+    /// The submission is not retried, since a retry could start a second
+    /// job.
+    pub async fn submit_query(
+        &self,
+        image: impl Into<ImageInput>,
+        question: impl Into<String>,
+    ) -> Result<JobId<QueryResponse>, Error> {
+        let image = self.prepare_image(image)?;
+        self.submit(
+            "query",
+            json!({
+                "image_url": image,
+                "question": question.into(),
+            }),
+        )
+        .await
+    }
+
+    /// Read the state of `job` once.
+    pub async fn poll<T: DeserializeOwned>(&self, job: &JobId<T>) -> Result<JobStatus<T>, Error> {
+        #[derive(Deserialize)]
+        struct Polled {
+            status: String,
+            #[serde(default)]
+            result: Option<Value>,
+            #[serde(default)]
+            error: Option<String>,
+        }
+
+        let template = self.template()?;
+        let mut url = template.url("jobs")?;
+        url.path_segments_mut()
+            .map_err(|()| Error::InvalidConfig(format!("endpoint {:?}", self.endpoint)))?
+            .push(job.as_str());
+        let request = template.authorize(self.client.get(url));
+        #[cfg(not(target_arch = "wasm32"))]
+        let request = request.timeout(self.attempt_timeout()?);
+        let fetch = async {
+            let response = request.send().await?.error_for_status()?;
+            Ok::<_, Error>(response.bytes().await?)
+        };
+        let body = self
+            .cancellable(fetch)
+            .await
+            .map_err(|error| self.deadline_error(error))?;
+
+        let polled: Polled = serde_json::from_slice(&body)?;
+        match polled.status.to_ascii_lowercase().as_str() {
+            "queued" | "pending" | "submitted" => Ok(JobStatus::Pending),
+            "running" | "processing" | "in_progress" => Ok(JobStatus::Running),
+            "completed" | "succeeded" | "success" | "done" => match polled.result {
+                Some(result) => Ok(JobStatus::Completed(serde_json::from_value(result)?)),
+                None => Err(Error::JobFailed {
+                    job: job.to_string(),
+                    message: "completed without a result".to_string(),
+                }),
+            },
+            "failed" | "error" | "cancelled" | "canceled" => Ok(JobStatus::Failed(
+                polled.error.unwrap_or_else(|| polled.status.clone()),
+            )),
+            status => Err(Error::JobFailed {
+                job: job.to_string(),
+                message: format!("unknown job status {status:?}"),
+            }),
+        }
+    }
+
+    /// Poll `job` until it completes, returning its result, or fails with
+    /// [`Error::JobFailed`].
+    ///
+    /// Polls failing with a retryable error, for example while the server
+    /// restarts, are tried again at the next interval.
+    pub async fn await_result<T: DeserializeOwned>(
+        &self,
+        job: &JobId<T>,
+        policy: &PollPolicy,
+    ) -> Result<T, Error> {
+        let mut polls = 0;
+        loop {
+            match self.poll(job).await {
+                Ok(JobStatus::Completed(result)) => return Ok(result),
+                Ok(JobStatus::Failed(message)) => {
+                    return Err(Error::JobFailed {
+                        job: job.to_string(),
+                        message,
+                    });
+                }
+                Ok(JobStatus::Pending | JobStatus::Running) => {}
+                Err(error) if error.is_retryable() => {}
+                Err(error) => return Err(error),
+            }
+
+            polls += 1;
+            let delay = policy.delay(polls);
+            if let Some(deadline) = self.options.deadline()
+                && Instant::now() + delay >= deadline
+            {
+                return Err(Error::DeadlineExceeded);
+            }
+            let sleep = async {
+                rt::sleep(delay).await;
+                Ok(())
+            };
+            self.cancellable(sleep).await?;
+        }
+    }
+
+    /// POST `body` to `{endpoint}/jobs/{path}` and return the job created.
+    async fn submit<T>(&self, path: &str, body: Value) -> Result<JobId<T>, Error> {
+        #[derive(Deserialize)]
+        struct Submitted {
+            #[serde(alias = "id")]
+            job_id: String,
+        }
+
+        let url = self.template()?.url(&format!("jobs/{path}"))?;
+        let payload = self.payload(body)?;
+        let timeout = self.attempt_timeout()?;
+        let client_request_id = Uuid::new_v4().to_string();
+        let execute = self.execute(&url, &payload, &client_request_id, 1, timeout);
+        let response = self
+            .cancellable(execute)
+            .await
+            .map_err(|error| self.deadline_error(error))?;
+
+        let submitted: Submitted = serde_json::from_slice(&response.body)?;
+        Ok(JobId::new(submitted.job_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_poll_policy_delay() {
+        let policy = PollPolicy::new().with_max_interval(Duration::from_secs(2));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_millis(1500));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_submit_and_await_result() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/jobs/query"))
+            .and(body_partial_json(json!({ "question": "Transcribe." })))
+            .respond_with(ResponseTemplate::new(202).set_body_json(json!({ "job_id": "j 1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/j%201"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": "running" })))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/j%201"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "completed",
+                "result": { "answer": "Hello" },
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let job = md.submit_query("img", "Transcribe.").await.unwrap();
+        assert_eq!(job.as_str(), "j 1");
+        assert_eq!(md.poll(&job).await.unwrap(), JobStatus::Running);
+
+        let policy = PollPolicy::new().with_interval(Duration::from_millis(5));
+        let response = md.await_result(&job, &policy).await.unwrap();
+        assert_eq!(response.answer, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_failed_job() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/jobs/j2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "failed",
+                "error": "out of memory",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let result = md
+            .await_result(&JobId::<QueryResponse>::new("j2"), &PollPolicy::new())
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::JobFailed { message, .. }) if message == "out of memory"
+        ));
+    }
+}
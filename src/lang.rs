@@ -0,0 +1,87 @@
+//! Language detection on captions and answers (feature `lang`).
+//!
+//! With a [`LanguagePolicy`] installed through
+//! [`MoonDream::with_language`](crate::MoonDream::with_language), every caption
+//! and answer is tagged with the ISO 639-3 code detected by
+//! [whatlang](https://docs.rs/whatlang). When an expected language is set, a
+//! response in another language is retried once with an explicit instruction.
+
+pub use whatlang::Lang;
+
+/// Controls language detection and retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LanguagePolicy {
+    expected: Option<Lang>,
+    retry: bool,
+}
+
+impl LanguagePolicy {
+    /// Only tag responses with the detected language.
+    pub fn detect() -> Self {
+        Self::default()
+    }
+
+    /// Tag responses and retry once, asking for `lang`, when the output is
+    /// reliably detected as another language.
+    pub fn expect(lang: Lang) -> Self {
+        Self {
+            expected: Some(lang),
+            retry: true,
+        }
+    }
+
+    /// Enable or disable the retry when the output language differs from the
+    /// expected one.
+    pub fn with_retry(mut self, retry: bool) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Return the expected language if `text` should be retried.
+    pub(crate) fn retry_language(&self, text: &str) -> Option<Lang> {
+        let expected = self.expected.filter(|_| self.retry)?;
+        let info = whatlang::detect(text)?;
+        (info.is_reliable() && info.lang() != expected).then_some(expected)
+    }
+}
+
+/// Detect the language of `text` and return its ISO 639-3 code.
+pub(crate) fn detect_code(text: &str) -> Option<String> {
+    whatlang::detect_lang(text).map(|lang| lang.code().to_string())
+}
+
+/// Instruction appended to prompts to request an answer in `lang`.
+pub(crate) fn instruction(lang: Lang) -> String {
+    format!("Answer in {}.", lang.eng_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_code() {
+        assert_eq!(
+            detect_code("The quick brown fox jumps over the lazy dog near the river bank."),
+            Some("eng".to_string())
+        );
+    }
+
+    #[test]
+    fn test_retry_language() {
+        let policy = LanguagePolicy::expect(Lang::Eng);
+        let italian = "Un gatto grigio dorme tranquillamente sopra il divano del soggiorno, \
+                       mentre fuori dalla finestra la pioggia continua a cadere sulla città.";
+        let english = "A grey cat is sleeping peacefully on the living room sofa.";
+
+        assert_eq!(policy.retry_language(italian), Some(Lang::Eng));
+        assert_eq!(policy.retry_language(english), None);
+        assert_eq!(policy.with_retry(false).retry_language(italian), None);
+        assert_eq!(LanguagePolicy::detect().retry_language(italian), None);
+    }
+
+    #[test]
+    fn test_instruction() {
+        assert_eq!(instruction(Lang::Ita), "Answer in Italian.");
+    }
+}
@@ -1,21 +1,324 @@
 //! Client for the [Moondream](https://moondream.ai/) vision API.
 //!
-//! Provides a simple wrapper around the Moondream HTTP endpoints. It is used to 
-//! detect objects in images, generate captions and answer visual questions. Examples 
+//! Provides a simple wrapper around the Moondream HTTP endpoints. It is used to
+//! detect objects in images, generate captions and answer visual questions. Examples
 //! are available in the `examples` directory.
 
+pub mod auth;
+pub mod batch;
+pub mod best_effort;
+pub mod cache;
+pub mod capabilities;
+pub mod continuation;
+pub mod debug;
+mod decode;
+pub mod decoder;
+pub mod defaults;
+#[cfg(feature = "dicom")]
+pub mod dicom;
+pub mod export;
+#[cfg(feature = "schemars")]
+pub mod extract;
+pub mod failover;
+pub mod fallback;
+pub mod filter;
+pub mod geo;
+pub mod health;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod http;
+pub mod input;
+pub mod interceptor;
+pub mod jobs;
+#[cfg(feature = "lang")]
+pub mod lang;
+#[cfg(feature = "local-model")]
+pub mod local_model;
+pub mod messages;
+pub mod meta;
+pub mod openai_compat;
+pub mod options;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod polling;
+pub mod postprocess;
+pub mod predicate;
+pub mod preprocess;
+pub mod presets;
+pub mod prompts;
+pub mod protocol;
+pub mod provenance;
+#[cfg(feature = "image")]
+pub mod report;
+pub mod retry;
+mod rt;
+pub mod scope;
+pub mod segment;
+mod telemetry;
+mod template;
+pub mod tenant;
+#[cfg(feature = "tiff")]
+pub mod tiff;
+pub mod tiles;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transport;
+pub mod usage;
+#[cfg(all(feature = "vcr", not(target_arch = "wasm32")))]
+pub mod vcr;
+#[cfg(feature = "image")]
+pub mod verify;
+#[cfg(feature = "video")]
+pub mod video;
+pub mod vision;
+pub mod wire;
+
+pub use auth::Auth;
+pub use batch::{BatchMetrics, BatchResult};
+pub use best_effort::BestEffort;
+#[cfg(not(target_arch = "wasm32"))]
+pub use cache::DiskStore;
+pub use cache::{Cache, CacheStore, Compression, MemoryStore};
+pub use capabilities::{Capabilities, CapabilitySource};
+pub use continuation::{Continuation, QueryFullResponse};
+pub use debug::{DebugRecord, DebugSink};
+pub use decoder::{DecoderRegistry, ImageDecoder};
+pub use defaults::MoonDreamDefaults;
+pub use failover::FailoverPolicy;
+pub use fallback::{Fallback, FallbackAnswer, FallbackChain};
+pub use filter::{ContentFilter, FilterAction};
+pub use geo::{GeoImage, GeoPolygon, GeoTransform};
+pub use health::{Health, HealthStatus};
+#[cfg(not(target_arch = "wasm32"))]
+pub use http::HttpConfig;
+pub use input::ImageInput;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub use input::MappedFile;
+pub use interceptor::{RequestInterceptor, ResponseContext, ResponseInterceptor};
+#[cfg(feature = "lang")]
+pub use lang::{Lang, LanguagePolicy};
+#[cfg(feature = "local-model")]
+pub use local_model::LocalMoonDream;
+pub use messages::Locale;
+pub use meta::{ApiResponse, ConnectionInfo, ResponseHeaders, ResponseMeta, TlsDetails};
+pub use options::RequestOptions;
+pub use predicate::Predicate;
+pub use preprocess::ImagePreprocessor;
+#[cfg(feature = "image")]
+pub use preprocess::{OutputFormat, Preprocess};
+pub use provenance::{Provenance, ProvenanceLog, ProvenanceSink};
+pub use retry::RetryPolicy;
+pub use scope::{EncodedImage, Scope};
+pub use segment::{Mask, Polygon, SegmentResponse};
+pub use tenant::{MoonDreamFactory, TenantLimits};
+pub use tiles::Tile;
+#[cfg(not(target_arch = "wasm32"))]
+pub use transport::ImageTransport;
+pub use usage::{EndpointUsage, UsageSnapshot, UsageTracker};
+#[cfg(feature = "image")]
+pub use verify::{Verification, Verifier};
+pub use vision::VisionClient;
+#[cfg(feature = "test-util")]
+pub use vision::{MockCall, MockVisionClient};
+
+use bytes::Bytes;
 use derive_new::new;
 use derive_setters::Setters;
-use serde::Deserialize;
-use serde_json::json;
+use failover::Failover;
+use protocol::Call;
+use reqwest::StatusCode;
+use reqwest::Url;
+use reqwest::header::HeaderValue;
+use rt::{Instant, SystemTime};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
+use telemetry::RequestSpan;
+use template::{RequestTemplate, TemplateCell};
+use uuid::Uuid;
 
 /// Errors returned by the [`MoonDream`] client when performing HTTP requests.
+///
+/// Some variants only exist with a cargo feature, such as `vcr` or
+/// `local-model`, so matches need a wildcard arm.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Wrapper around [`reqwest::Error`].
     #[error("MoonDream Error: {0}")]
     PointError(#[from] reqwest::Error),
+
+    /// The response body could not be decoded.
+    #[error("MoonDream Error: invalid response: {0}")]
+    Decode(#[from] serde_json::Error),
+
+    /// An image file could not be read.
+    #[error("MoonDream Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Error returned by a [`RequestInterceptor`].
+    #[error("MoonDream Error: interceptor failed: {0}")]
+    Interceptor(Box<dyn std::error::Error + Send + Sync>),
+
+    /// Some labels of a [`MoonDream::detect_many`] call failed.
+    #[error(
+        "MoonDream Error: detection failed for {} of {} labels",
+        .failures.len(),
+        .failures.len() + .detected.len()
+    )]
+    DetectMany {
+        /// Objects detected for the labels that succeeded.
+        detected: HashMap<String, Vec<DetectionObject>>,
+        /// Error returned for each label that failed.
+        failures: Vec<(String, Error)>,
+    },
+
+    /// The server could not be reached by a health or capability check.
+    #[error("MoonDream Error: {endpoint} is unavailable: {source}")]
+    Unavailable {
+        /// Endpoint of the client.
+        endpoint: String,
+        /// Connection error.
+        source: reqwest::Error,
+    },
+
+    /// A record could not be exported.
+    #[error("MoonDream Error: export failed: {0}")]
+    Export(String),
+
+    /// A [`Predicate`] expression could not be parsed.
+    #[error("MoonDream Error: invalid filter: {0}")]
+    InvalidFilter(String),
+
+    /// The call was aborted through the [`RequestOptions`] cancellation
+    /// token.
+    #[error("MoonDream Error: request cancelled")]
+    Cancelled,
+
+    /// The [`RequestOptions`] deadline passed before a response was received.
+    #[error("MoonDream Error: deadline exceeded")]
+    DeadlineExceeded,
+
+    /// The model answer could not be parsed into the requested type.
+    #[error("MoonDream Error: invalid model output: {source}")]
+    InvalidModelOutput {
+        /// Text returned by the model.
+        raw: String,
+        /// Parse error.
+        source: serde_json::Error,
+    },
+
+    /// The model answer does not fit a [`prompts::Task`].
+    #[error("MoonDream Error: unexpected answer for the {task} task: {answer:?}")]
+    UnexpectedAnswer {
+        /// Name of the task.
+        task: &'static str,
+        /// Text returned by the model.
+        answer: String,
+    },
+
+    /// An asynchronous [`jobs`] job failed, or reported an unknown state.
+    #[error("MoonDream Error: job {job} failed: {message}")]
+    JobFailed {
+        /// Identifier of the job.
+        job: String,
+        /// Error reported by the server.
+        message: String,
+    },
+
+    /// The server is still loading the model, see
+    /// [`MoonDream::with_startup_grace`].
+    #[error("MoonDream Error: model loading ({status}): {message}")]
+    ModelLoading {
+        /// HTTP status of the response.
+        status: StatusCode,
+        /// Body of the response.
+        message: String,
+    },
+
+    /// The server answered with an error status.
+    #[error("MoonDream Error: HTTP {status}: {message}")]
+    Status {
+        /// HTTP status of the response.
+        status: StatusCode,
+        /// Body of the response.
+        message: String,
+    },
+
+    /// The client settings, such as the endpoint or a header, are invalid.
+    #[error("MoonDream Error: invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// The backend does not implement the requested operation.
+    #[error("MoonDream Error: `{0}` is not supported by this backend")]
+    Unsupported(&'static str),
+
+    /// The provided image could not be decoded.
+    #[error("MoonDream Error: invalid image: {0}")]
+    InvalidImage(String),
+
+    /// The encoded image exceeds the configured maximum size.
+    #[error("MoonDream Error: image is {size} bytes, larger than the {limit} bytes limit")]
+    ImageTooLarge {
+        /// Size of the encoded image, in bytes.
+        size: usize,
+        /// Configured maximum size, in bytes.
+        limit: usize,
+    },
+
+    /// The request budget of a [`tenant`] is spent.
+    #[error("MoonDream Error: request budget of {budget} exhausted")]
+    BudgetExhausted {
+        /// Number of requests of the budget.
+        budget: u64,
+    },
+
+    /// A [`vcr`] fixture could not be read or written, or has no answer
+    /// for a replayed request.
+    #[cfg(all(feature = "vcr", not(target_arch = "wasm32")))]
+    #[error("MoonDream Error: vcr: {0}")]
+    Vcr(String),
+
+    /// Wrapper around errors raised by the local inference backend.
+    #[cfg(feature = "local-model")]
+    #[error("MoonDream Error: {0}")]
+    LocalModel(#[from] candle_core::Error),
+}
+
+impl Error {
+    /// Return `true` if the request may succeed when retried: timeouts,
+    /// connection errors, `429 Too Many Requests` and `5xx` responses.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::PointError(error) => {
+                error.is_timeout()
+                    || error.is_connect()
+                    || error.status().is_some_and(|status| {
+                        status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                    })
+            }
+            Error::Status { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            Error::ModelLoading { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Return `true` if the error is expected from a server still starting:
+    /// connection errors, `503 Service Unavailable` and
+    /// [`Error::ModelLoading`].
+    pub fn is_model_loading(&self) -> bool {
+        match self {
+            Error::PointError(error) => {
+                error.is_connect() || error.status() == Some(StatusCode::SERVICE_UNAVAILABLE)
+            }
+            Error::Status { status, .. } => *status == StatusCode::SERVICE_UNAVAILABLE,
+            Error::ModelLoading { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 /// Client for interacting with the [Moondream API](https://moondream.ai/).
@@ -30,23 +333,134 @@ pub struct MoonDream {
     token: String,
 
     #[new(value = "String::from(\"https://api.moondream.ai/v1\")")]
+    #[setters(skip)]
     endpoint: String,
 
     #[new(default)]
+    #[setters(skip)]
     headers: Vec<(String, String)>,
 
+    #[new(default)]
+    #[setters(skip)]
+    auth: Auth,
+
+    #[new(default)]
+    #[setters(skip)]
+    failover: Option<Failover>,
+
+    #[new(default)]
+    failover_policy: FailoverPolicy,
+
+    #[new(default)]
+    #[setters(skip)]
+    template: TemplateCell,
+
     #[new(value = "Duration::from_secs(5)")]
     timeout: Duration,
 
-    #[new(value = "reqwest::Client::new()")]
+    #[new(value = "rt::shared_client()")]
     client: reqwest::Client,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[new(default)]
+    #[setters(skip)]
+    http_config: Option<HttpConfig>,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[new(default)]
+    transport: ImageTransport,
+
+    #[new(default)]
+    #[setters(skip)]
+    preprocess: Option<Arc<dyn ImagePreprocessor>>,
+
+    #[new(default)]
+    decoders: Option<DecoderRegistry>,
+
+    #[new(default)]
+    content_filter: Option<ContentFilter>,
+
+    #[cfg(feature = "lang")]
+    #[new(default)]
+    language: Option<LanguagePolicy>,
+
+    #[new(default)]
+    cache: Option<Cache>,
+
+    #[new(default)]
+    retry: Option<RetryPolicy>,
+
+    #[new(default)]
+    #[setters(skip)]
+    startup_until: Option<Instant>,
+
+    #[new(default)]
+    lenient_decode: bool,
+
+    /// Log request and response bodies as `DEBUG` events inside the
+    /// `moondream.request` span, with inline images replaced by their hash
+    /// and size. Requires the `tracing` feature; without it, bodies are
+    /// never logged.
+    #[new(default)]
+    log_bodies: bool,
+
+    #[new(default)]
+    #[setters(skip)]
+    debug_sampling: f64,
+
+    #[new(default)]
+    #[setters(skip)]
+    debug_sink: Option<Arc<dyn DebugSink>>,
+
+    #[new(default)]
+    confidence_scores: bool,
+
+    #[new(default)]
+    #[setters(skip)]
+    max_image_size: Option<usize>,
+
+    #[new(default)]
+    defaults: Option<MoonDreamDefaults>,
+
+    #[new(default)]
+    options: RequestOptions,
+
+    #[new(default)]
+    #[setters(skip)]
+    usage: UsageTracker,
+
+    #[new(default)]
+    #[setters(skip)]
+    request_interceptors: Vec<Arc<dyn RequestInterceptor>>,
+
+    #[new(default)]
+    #[setters(skip)]
+    response_interceptors: Vec<Arc<dyn ResponseInterceptor>>,
+
+    #[new(default)]
+    #[setters(skip)]
+    recent_detections: best_effort::RecentDetections,
+
+    #[new(default)]
+    #[setters(skip)]
+    provenance: Option<Arc<dyn ProvenanceSink>>,
+
+    #[new(default)]
+    #[setters(skip)]
+    quota: Option<tenant::Quota>,
+
+    #[cfg(all(feature = "vcr", not(target_arch = "wasm32")))]
+    #[new(default)]
+    #[setters(skip)]
+    cassette: Option<vcr::Cassette>,
 }
 
 /// Response returned by the `/point` endpoint.
 ///
 /// Contains the request identifier, a list of centre [`Point`]s for each
 /// detected object and an optional count of how many were found.
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct PointsResponse {
     /// Unique request identifier returned by the API.
     pub request_id: Option<String>,
@@ -59,7 +473,8 @@ pub struct PointsResponse {
 /// Response returned by the `/detect` endpoint.
 ///
 /// Includes the request id and the bounding boxes for all detected objects.
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct DetectResponse {
     /// Unique request identifier returned by the API.
     pub request_id: Option<String>,
@@ -71,7 +486,8 @@ pub struct DetectResponse {
 ///
 /// Values are normalized to the image dimensions (0-1). To convert them to
 /// pixels multiply by the width and height of the source image.
-#[derive(Debug, Deserialize, PartialOrd, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialOrd, PartialEq, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct DetectionObject {
     /// Left boundary of the box (normalized 0-1).
     pub x_min: f64,
@@ -81,27 +497,126 @@ pub struct DetectionObject {
     pub x_max: f64,
     /// Bottom boundary of the box (normalized 0-1).
     pub y_max: f64,
+    /// Confidence of the detection (0-1), when returned by the API.
+    ///
+    /// Request it with [`MoonDream::with_confidence_scores`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
 }
 
 /// Centre point coordinates returned by the `/point` endpoint.
 ///
 /// Values are normalized to the image dimensions (0-1). To convert them to
 /// pixels multiply by the width and height of the source image.
-#[derive(Debug, Deserialize, PartialOrd, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialOrd, PartialEq, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct Point {
     /// Normalized X coordinate.
     pub x: f64,
     /// Normalized Y coordinate.
     pub y: f64,
+    /// Confidence of the point (0-1), when returned by the API.
+    ///
+    /// Request it with [`MoonDream::with_confidence_scores`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+/// Return `true` unless `confidence` is known to be below `min`.
+fn meets_confidence(confidence: Option<f64>, min: f64) -> bool {
+    confidence.is_none_or(|confidence| confidence >= min)
+}
+
+impl PointsResponse {
+    /// Drop the points whose confidence is below `min`.
+    ///
+    /// Points without a confidence score are kept. `count` is updated to the
+    /// number of remaining points.
+    pub fn filter_confidence(mut self, min: f64) -> Self {
+        self.points
+            .retain(|point| meets_confidence(point.confidence, min));
+        if self.count.is_some() {
+            self.count = Some(self.points.len());
+        }
+        self
+    }
+}
+
+impl DetectResponse {
+    /// Drop the objects whose confidence is below `min`.
+    ///
+    /// Objects without a confidence score are kept.
+    pub fn filter_confidence(mut self, min: f64) -> Self {
+        self.objects
+            .retain(|object| meets_confidence(object.confidence, min));
+        self
+    }
 }
 
 /// Response from the `/query` endpoint (Visual Question Answering).
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
 pub struct QueryResponse {
     /// Unique request identifier returned by the API.
     pub request_id: Option<String>,
     /// Answer returned for the asked question.
     pub answer: String,
+    /// Post-processing flags set by the client.
+    #[serde(default, skip_serializing_if = "ResultFlags::is_empty")]
+    pub flags: ResultFlags,
+    /// ISO 639-3 code of the answer language, detected by the client
+    /// (feature `lang`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// Markers set by the client when it altered or annotated a response.
+///
+/// Flags are combined with `|` and tested with [`ResultFlags::contains`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ResultFlags(u32);
+
+impl ResultFlags {
+    /// The text matched the configured [`ContentFilter`].
+    pub const CONTENT_FILTERED: Self = Self(1);
+    /// The response only decoded after lenient coercion, see
+    /// [`MoonDream::with_lenient_decode`].
+    pub const LENIENT_PARSE: Self = Self(2);
+
+    /// Return a set without any flag.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Return `true` if no flag is set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return `true` if every flag in `other` is set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set every flag in `other`.
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl std::ops::BitOr for ResultFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for ResultFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.insert(rhs);
+    }
 }
 
 impl MoonDream {
@@ -120,147 +635,1203 @@ impl MoonDream {
         MoonDream::new(token.into())
     }
 
+    /// Send requests to `endpoint`, for example `http://localhost:2020/v1`.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self.failover = None;
+        self.template = TemplateCell::default();
+        self
+    }
+
+    /// Send requests to the first of `endpoints`, failing over to the next
+    /// ones when it is down, see [`failover`].
+    ///
+    /// An empty list leaves the endpoint unchanged.
+    pub fn with_endpoints<I>(mut self, endpoints: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut endpoints = endpoints.into_iter().map(Into::into);
+        let Some(primary) = endpoints.next() else {
+            return self;
+        };
+        let fallbacks: Vec<String> = endpoints.collect();
+        self.endpoint = primary;
+        self.failover = (!fallbacks.is_empty()).then(|| Failover::new(fallbacks));
+        self.template = TemplateCell::default();
+        self
+    }
+
+    /// Send `headers` with every request, in addition to the authentication
+    /// header.
+    pub fn with_headers(mut self, headers: impl Into<Vec<(String, String)>>) -> Self {
+        self.headers = headers.into();
+        self.template = TemplateCell::default();
+        self
+    }
+
+    /// Send the token as `auth` describes instead of in the
+    /// `X-Moondream-Auth` header, see [`auth`].
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self.template = TemplateCell::default();
+        self
+    }
+
+    /// Retry calls failing because the server is still starting, for `grace`
+    /// from now.
+    ///
+    /// Local servers refuse connections, or answer `503` errors saying the
+    /// model is loading, until the model is ready. Within the grace window
+    /// such errors, see [`Error::is_model_loading`], are retried with the
+    /// backoff of the [`RetryPolicy`] whatever its number of retries, so
+    /// pipelines started alongside the server wait for it instead of
+    /// failing. Afterwards they are handled like any other error.
+    pub fn with_startup_grace(mut self, grace: Duration) -> Self {
+        self.startup_until = Some(Instant::now() + grace);
+        self
+    }
+
+    /// Reject images whose encoded value is larger than `max_image_size`
+    /// bytes, after preprocessing.
+    pub fn with_max_image_size(mut self, max_image_size: usize) -> Self {
+        self.max_image_size = Some(max_image_size);
+        self
+    }
+
+    /// Run `interceptor` on every outgoing request, after the interceptors
+    /// already registered.
+    pub fn with_request_interceptor(
+        mut self,
+        interceptor: impl RequestInterceptor + 'static,
+    ) -> Self {
+        self.request_interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Run `interceptor` on every HTTP response, after the interceptors
+    /// already registered.
+    pub fn with_response_interceptor(
+        mut self,
+        interceptor: impl ResponseInterceptor + 'static,
+    ) -> Self {
+        self.response_interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Record usage in `tracker` instead of the tracker shared with the
+    /// client this one was cloned from, for example to account each tenant
+    /// separately.
+    pub fn with_usage_tracker(mut self, tracker: UsageTracker) -> Self {
+        self.usage = tracker;
+        self
+    }
+
+    /// Usage recorded since the client was created or the tracker was last
+    /// reset.
+    pub fn usage(&self) -> UsageSnapshot {
+        self.usage.snapshot()
+    }
+
+    /// Return the recorded usage and reset the counters, for example at the
+    /// end of a billing window.
+    pub fn reset_usage(&self) -> UsageSnapshot {
+        self.usage.reset()
+    }
+
+    /// Replace the HTTP client with one built from `config`.
+    ///
+    /// A cookie store set earlier with `MoonDream::with_cookie_store` is
+    /// kept unless `config` has its own. Fails if the proxy URL or a
+    /// certificate of `config` is invalid.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_http_config(mut self, config: HttpConfig) -> Result<Self, Error> {
+        #[cfg(feature = "cookies")]
+        let config = match self.http_config.as_ref().and_then(HttpConfig::cookie_jar) {
+            Some(jar) if config.cookie_jar().is_none() => config.with_cookie_jar(jar),
+            _ => config,
+        };
+        self.client = config.build()?;
+        self.http_config = Some(config);
+        Ok(self)
+    }
+
+    /// Store cookies set by the server and send them back on later requests
+    /// to the same host (feature `cookies`).
+    ///
+    /// Load-balanced deployments often pin a client to one backend with a
+    /// session-affinity cookie; with a cookie store successive requests stick
+    /// to the same warm instance.
+    ///
+    /// The HTTP client is rebuilt from the [`HttpConfig`] set with
+    /// [`MoonDream::with_http_config`], or [`HttpConfig::tuned`], so its
+    /// proxy, certificates and pool settings are kept; a client set with
+    /// [`MoonDream::with_client`] is replaced.
+    #[cfg(all(feature = "cookies", not(target_arch = "wasm32")))]
+    pub fn with_cookie_store(self) -> Result<Self, Error> {
+        self.with_cookie_jar(Arc::new(reqwest::cookie::Jar::default()))
+    }
+
+    /// Like [`MoonDream::with_cookie_store`], using `jar` so cookies can be
+    /// shared between clients or inspected (feature `cookies`).
+    #[cfg(all(feature = "cookies", not(target_arch = "wasm32")))]
+    pub fn with_cookie_jar(mut self, jar: Arc<reqwest::cookie::Jar>) -> Result<Self, Error> {
+        let config = self
+            .http_config
+            .take()
+            .unwrap_or_else(HttpConfig::tuned)
+            .with_cookie_jar(jar);
+        self.with_http_config(config)
+    }
+
+    /// Preprocess every image with `preprocess` before it is uploaded.
+    ///
+    /// See `Preprocess` (feature `image`) for a built-in resizer.
+    pub fn with_preprocess(mut self, preprocess: impl ImagePreprocessor + 'static) -> Self {
+        self.preprocess = Some(Arc::new(preprocess));
+        self
+    }
+
     pub async fn points(
         &self,
-        image: impl Into<String>,
+        image: impl Into<ImageInput>,
         object: impl Into<String>,
     ) -> Result<PointsResponse, Error> {
+        self.points_with_meta(image, object)
+            .await
+            .map(ApiResponse::into_inner)
+    }
+
+    /// Same as [`MoonDream::points`], also returning the [`ResponseMeta`].
+    pub async fn points_with_meta(
+        &self,
+        image: impl Into<ImageInput>,
+        object: impl Into<String>,
+    ) -> Result<ApiResponse<PointsResponse>, Error> {
         let object = object.into();
-        let image = image.into();
-
-        let result = self
-            .client
-            .post(format!("{}/point", self.endpoint))
-            .header("X-Moondream-Auth", &self.token)
-            .timeout(self.timeout.clone())
-            .json(&json!({
-                "image_url": image,
-                "object": object,
-            }))
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(result.json().await?)
+        let (image, provenance) = self.prepare_traced(image.into())?;
+
+        self.send::<PointsResponse>("point", self.object_request(image, object))
+            .await
+            .map(|response| response.with_provenance(provenance))
     }
 
     pub async fn detect(
         &self,
-        image: impl Into<String>,
+        image: impl Into<ImageInput>,
         object: impl Into<String>,
     ) -> Result<DetectResponse, Error> {
+        self.detect_with_meta(image, object)
+            .await
+            .map(ApiResponse::into_inner)
+    }
+
+    /// Same as [`MoonDream::detect`], also returning the [`ResponseMeta`].
+    pub async fn detect_with_meta(
+        &self,
+        image: impl Into<ImageInput>,
+        object: impl Into<String>,
+    ) -> Result<ApiResponse<DetectResponse>, Error> {
         let object = object.into();
-        let image = image.into();
-
-        let result = self
-            .client
-            .post(format!("{}/detect", self.endpoint))
-            .header("X-Moondream-Auth", &self.token)
-            .timeout(self.timeout.clone())
-            .json(&json!({
-                "image_url": image,
-                "object": object,
-            }))
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(result.json().await?)
+        let (image, provenance) = self.prepare_traced(image.into())?;
+
+        self.send::<DetectResponse>("detect", self.object_request(image, object))
+            .await
+            .map(|response| response.with_provenance(provenance))
+    }
+
+    /// Detect several kinds of objects in the same image.
+    ///
+    /// One `/detect` request per label is sent concurrently; the image is
+    /// preprocessed only once. The boxes are returned keyed by label. If any
+    /// request fails, [`Error::DetectMany`] reports every failure together
+    /// with the labels that succeeded.
+    pub async fn detect_many(
+        &self,
+        image: impl Into<ImageInput>,
+        objects: &[&str],
+    ) -> Result<HashMap<String, Vec<DetectionObject>>, Error> {
+        let image = self.prepare_image(image)?;
+
+        let requests = objects.iter().map(|&object| {
+            let body = self.object_request(image.clone(), object.to_string());
+            async move {
+                let response = self.send::<DetectResponse>("detect", body).await;
+                (object.to_string(), response)
+            }
+        });
+
+        let mut detected = HashMap::new();
+        let mut failures = Vec::new();
+        for (object, response) in futures::future::join_all(requests).await {
+            match response {
+                Ok(response) => {
+                    detected.insert(object, response.into_inner().objects);
+                }
+                Err(error) => failures.push((object, error)),
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(detected)
+        } else {
+            Err(Error::DetectMany { detected, failures })
+        }
     }
 
     pub async fn caption(
         &self,
-        image: impl Into<String>,
+        image: impl Into<ImageInput>,
         length: Option<CaptionLength>,
     ) -> Result<CaptionResponse, Error> {
-        let image = image.into();
-        let length = length.unwrap_or(CaptionLength::Normal);
-
-        let result = self
-            .client
-            .post(format!("{}/caption", self.endpoint))
-            .header("X-Moondream-Auth", &self.token)
-            .timeout(self.timeout.clone())
-            .json(&json!({
-                "image_url": image,
-                "length": length.as_str(),
-            }))
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(result.json().await?)
+        self.caption_with_meta(image, length)
+            .await
+            .map(ApiResponse::into_inner)
+    }
+
+    /// Same as [`MoonDream::caption`], also returning the [`ResponseMeta`].
+    pub async fn caption_with_meta(
+        &self,
+        image: impl Into<ImageInput>,
+        length: Option<CaptionLength>,
+    ) -> Result<ApiResponse<CaptionResponse>, Error> {
+        let (image, provenance) = self.prepare_traced(image.into())?;
+        self.caption_prepared(&image, length)
+            .await
+            .map(|response| response.with_provenance(provenance))
+    }
+
+    /// Caption an image already returned by `prepare_image`.
+    async fn caption_prepared(
+        &self,
+        image: &str,
+        length: Option<CaptionLength>,
+    ) -> Result<ApiResponse<CaptionResponse>, Error> {
+        let length = length
+            .or_else(|| self.defaults().caption_length())
+            .unwrap_or(CaptionLength::Normal);
+
+        let mut response: ApiResponse<CaptionResponse> = self
+            .send(
+                "caption",
+                Call::Caption {
+                    image_url: image,
+                    length,
+                }
+                .body(),
+            )
+            .await?;
+
+        #[cfg(feature = "lang")]
+        if let Some(policy) = &self.language
+            && let Some(expected) = policy.retry_language(&response.caption)
+        {
+            let prompt = match length {
+                CaptionLength::Short => "Write a short caption for this image.",
+                CaptionLength::Normal => "Describe this image.",
+            };
+            let question = format!("{prompt} {}", lang::instruction(expected));
+            let retried: ApiResponse<QueryResponse> = self
+                .send(
+                    "query",
+                    Call::Query {
+                        image_url: image,
+                        question: &question,
+                    }
+                    .body(),
+                )
+                .await?;
+            let data = response.data;
+            response = retried.map(|retried| CaptionResponse {
+                request_id: retried.request_id,
+                caption: retried.answer,
+                ..data
+            });
+        }
+
+        let data = &mut response.data;
+        data.flags |= response.meta.flags;
+        self.finish_text(&mut data.caption, &mut data.flags, &mut data.language);
+        Ok(response)
     }
 
     pub async fn query(
         &self,
-        image: impl Into<String>,
+        image: impl Into<ImageInput>,
         question: impl Into<String>,
     ) -> Result<QueryResponse, Error> {
-        let image = image.into();
-        let question = question.into();
-
-        let result = self
-            .client
-            .post(format!("{}/query", self.endpoint))
-            .header("X-Moondream-Auth", &self.token)
-            .timeout(self.timeout.clone())
-            .json(&json!({
-                "image_url": image,
-                "question": question,
-            }))
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(result.json().await?)
+        self.query_with_meta(image, question)
+            .await
+            .map(ApiResponse::into_inner)
     }
-}
 
-/// Controls the length of the caption returned by [`MoonDream::caption`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum CaptionLength {
-    /// A brief caption.
-    Short,
-    /// A normal length caption.
-    Normal,
-}
+    /// Same as [`MoonDream::query`], also returning the [`ResponseMeta`].
+    pub async fn query_with_meta(
+        &self,
+        image: impl Into<ImageInput>,
+        question: impl Into<String>,
+    ) -> Result<ApiResponse<QueryResponse>, Error> {
+        let (image, provenance) = self.prepare_traced(image.into())?;
+        self.query_prepared(&image, question.into())
+            .await
+            .map(|response| response.with_provenance(provenance))
+    }
 
-impl CaptionLength {
-    fn as_str(&self) -> &'static str {
-        match self {
-            CaptionLength::Short => "short",
-            CaptionLength::Normal => "normal",
+    /// Ask `question` about an image already returned by `prepare_image`.
+    async fn query_prepared(
+        &self,
+        image: &str,
+        question: String,
+    ) -> Result<ApiResponse<QueryResponse>, Error> {
+        let mut response: ApiResponse<QueryResponse> = self
+            .send(
+                "query",
+                Call::Query {
+                    image_url: image,
+                    question: &question,
+                }
+                .body(),
+            )
+            .await?;
+
+        #[cfg(feature = "lang")]
+        if let Some(policy) = &self.language
+            && let Some(expected) = policy.retry_language(&response.answer)
+        {
+            let question = format!("{question}\n\n{}", lang::instruction(expected));
+            response = self
+                .send(
+                    "query",
+                    Call::Query {
+                        image_url: image,
+                        question: &question,
+                    }
+                    .body(),
+                )
+                .await?;
         }
+
+        let data = &mut response.data;
+        data.flags |= response.meta.flags;
+        self.finish_text(&mut data.answer, &mut data.flags, &mut data.language);
+        Ok(response)
     }
-}
 
-/// Response from the `/caption` endpoint.
-#[derive(Debug, Deserialize, PartialEq, Clone)]
-pub struct CaptionResponse {
-    /// Unique request identifier returned by the API.
-    pub request_id: Option<String>,
-    /// The generated caption text.
-    pub caption: String,
-}
+    /// Ask `question` and deserialize the answer as JSON into `T`.
+    ///
+    /// The model is instructed to answer with JSON only; markdown code fences
+    /// around the answer are stripped. Describing the expected fields in the
+    /// question gives the best results. Returns
+    /// [`Error::InvalidModelOutput`] with the raw answer if it is not valid
+    /// JSON for `T`.
+    pub async fn query_json<T: DeserializeOwned>(
+        &self,
+        image: impl Into<ImageInput>,
+        question: impl Into<String>,
+    ) -> Result<T, Error> {
+        self.query_json_with_meta(image, question)
+            .await
+            .map(ApiResponse::into_inner)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use wiremock::matchers::{header, method, path};
-    use wiremock::{Mock, MockServer, ResponseTemplate};
+    /// Same as [`MoonDream::query_json`], also returning the
+    /// [`ResponseMeta`].
+    pub async fn query_json_with_meta<T: DeserializeOwned>(
+        &self,
+        image: impl Into<ImageInput>,
+        question: impl Into<String>,
+    ) -> Result<ApiResponse<T>, Error> {
+        let question = format!(
+            "{}\n\nAnswer only with valid JSON, without any other text.",
+            question.into()
+        );
+        let response = self.query_with_meta(image, question).await?;
 
-    #[tokio::test]
-    async fn test_points_response_deserialization() {
-        let json = r#"{
-            "request_id": "abc",
-            "points": [{"x": 0.1, "y": 0.2}],
-            "count": 1
-        }"#;
+        let data = serde_json::from_str(strip_code_fence(&response.answer)).map_err(|source| {
+            Error::InvalidModelOutput {
+                raw: response.answer.clone(),
+                source,
+            }
+        })?;
+        Ok(ApiResponse {
+            data,
+            meta: response.meta,
+        })
+    }
 
-        let resp: PointsResponse = serde_json::from_str(json).unwrap();
-        assert_eq!(resp.request_id, Some("abc".to_string()));
-        assert_eq!(resp.points, vec![Point { x: 0.1, y: 0.2 }]);
-        assert_eq!(resp.count, Some(1));
+    /// Ask `question` about several images at once, for example "what
+    /// changed between these two photos?".
+    ///
+    /// The images are sent as `image_urls` in a single `/query` request, which
+    /// requires a server that accepts multiple images per query.
+    pub async fn query_multi(
+        &self,
+        images: Vec<ImageInput>,
+        question: impl Into<String>,
+    ) -> Result<QueryResponse, Error> {
+        self.query_multi_with_meta(images, question)
+            .await
+            .map(ApiResponse::into_inner)
     }
 
-    #[tokio::test]
+    /// Same as [`MoonDream::query_multi`], also returning the
+    /// [`ResponseMeta`].
+    pub async fn query_multi_with_meta(
+        &self,
+        images: Vec<ImageInput>,
+        question: impl Into<String>,
+    ) -> Result<ApiResponse<QueryResponse>, Error> {
+        if images.is_empty() {
+            return Err(Error::InvalidImage(
+                "query_multi requires at least one image".to_string(),
+            ));
+        }
+        let (image_urls, provenance): (Vec<_>, Vec<_>) = images
+            .into_iter()
+            .map(|image| self.prepare_traced(image))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(image, provenance)| (Cow::Owned(image), provenance))
+            .unzip();
+        let request = wire::MultiQueryRequest {
+            image_urls,
+            question: Cow::Owned(question.into()),
+        };
+
+        let mut response = self
+            .send::<QueryResponse>("query", serde_json::to_value(&request)?)
+            .await?
+            .with_provenance(provenance.into_iter().flatten());
+
+        let data = &mut response.data;
+        data.flags |= response.meta.flags;
+        self.finish_text(&mut data.answer, &mut data.flags, &mut data.language);
+        Ok(response)
+    }
+
+    /// Defaults of this client, completed with the global defaults.
+    fn defaults(&self) -> MoonDreamDefaults {
+        let global = MoonDreamDefaults::global();
+        match &self.defaults {
+            Some(defaults) => defaults.clone().or(global),
+            None => global,
+        }
+    }
+
+    /// Decode `image` with the registered decoders, encode it, run the
+    /// configured [`ImagePreprocessor`], if any, and enforce the maximum image
+    /// size.
+    ///
+    /// With [`ImageTransport::Multipart`], files that need neither decoding
+    /// nor preprocessing are not read but referred to by a `file://` URL and
+    /// streamed when the request is sent.
+    ///
+    /// Memory-mapped inputs are never encoded whole: those that are not
+    /// streamed must be turned into a smaller image by a decoder, or fail
+    /// with [`Error::InvalidConfig`].
+    fn prepare_image(&self, image: impl Into<ImageInput>) -> Result<String, Error> {
+        self.prepare_traced(image.into()).map(|(image, _)| image)
+    }
+
+    /// Same as `prepare_image`, also returning the [`Provenance`] of `image`
+    /// when the client records it.
+    fn prepare_traced(&self, image: ImageInput) -> Result<(String, Option<Provenance>), Error> {
+        let Some(sink) = &self.provenance else {
+            return Ok((self.prepare_source(image)?, None));
+        };
+        // Files that are not streamed are read once, for the hash and for
+        // the request.
+        let read = !self.streams(&image);
+        let (image, provenance) = match image {
+            ImageInput::File { path, mime } if read => {
+                let data = std::fs::read(&path)?;
+                let provenance = Provenance::of_file(&data, &path);
+                (ImageInput::Bytes { data, mime }, provenance)
+            }
+            image => {
+                let provenance = Provenance::of(&image)?;
+                (image, provenance)
+            }
+        };
+        let image = self.prepare_source(image)?;
+        sink.record(&provenance);
+        Ok((image, Some(provenance)))
+    }
+
+    /// Return `true` if `image` is a file streamed from disk by the
+    /// multipart transport.
+    fn streams(&self, image: &ImageInput) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.transport == ImageTransport::Multipart
+            && self
+                .preprocess
+                .as_ref()
+                .or(self.defaults().preprocess())
+                .is_none()
+            && let Some((_, mime)) = image.local_file()
+        {
+            return self
+                .decoders
+                .as_ref()
+                .is_none_or(|decoders| !decoders.supports(mime));
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = image;
+        false
+    }
+
+    fn prepare_source(&self, image: ImageInput) -> Result<String, Error> {
+        let defaults = self.defaults();
+        let preprocess = self.preprocess.as_ref().or(defaults.preprocess());
+        let limit = self.max_image_size.or(defaults.max_image_size());
+        let check_size = |size: usize| match limit {
+            Some(limit) if size > limit => Err(Error::ImageTooLarge { size, limit }),
+            _ => Ok(()),
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.streams(&image)
+            && let Some((path, _)) = image.local_file()
+        {
+            check_size(std::fs::metadata(path)?.len() as usize)?;
+            return Ok(transport::file_url(path));
+        }
+
+        let image = match &self.decoders {
+            Some(decoders) => decoders.decode(image)?,
+            None => image,
+        };
+        #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+        if let ImageInput::Mapped { file, .. } = &image {
+            return Err(Error::InvalidConfig(format!(
+                "memory-mapped image {} must be decoded or sent with ImageTransport::Multipart \
+                 and no preprocessing",
+                file.path().display()
+            )));
+        }
+        let image = image.into_url()?;
+        let image = match preprocess {
+            Some(preprocess) => preprocess.process(image)?,
+            None => image,
+        };
+        check_size(image.len())?;
+        Ok(image)
+    }
+
+    /// Body of a `/point` or `/detect` request.
+    fn object_request(&self, image: String, object: String) -> Value {
+        protocol::object_body(&image, &object, self.confidence_scores)
+    }
+
+    /// Tag the language of a caption or answer and run the content filter.
+    fn finish_text(
+        &self,
+        text: &mut String,
+        flags: &mut ResultFlags,
+        language: &mut Option<String>,
+    ) {
+        #[cfg(feature = "lang")]
+        if self.language.is_some() {
+            *language = lang::detect_code(text);
+        }
+        #[cfg(not(feature = "lang"))]
+        let _ = language;
+
+        *flags |= self.filter_text(text);
+    }
+
+    /// Run the configured [`ContentFilter`], if any.
+    fn filter_text(&self, text: &mut String) -> ResultFlags {
+        match &self.content_filter {
+            Some(filter) => filter.apply(text),
+            None => ResultFlags::empty(),
+        }
+    }
+
+    /// POST `body` to `{endpoint}/{path}` and decode the JSON response.
+    ///
+    /// Responses are served from and stored into the configured [`Cache`].
+    /// Calls picked by the [`debug`] sampling are recorded.
+    async fn send<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Value,
+    ) -> Result<ApiResponse<T>, Error> {
+        let client_request_id = Uuid::new_v4();
+        if !debug::sampled(&client_request_id, self.debug_sampling) {
+            return self.send_as(path, body, &client_request_id).await;
+        }
+        let request = serde_json::to_vec(&body)?;
+        let start = Instant::now();
+        let result = self.send_as(path, body, &client_request_id).await;
+        self.record_debug(&client_request_id, path, &request, &result, start.elapsed())
+            .await;
+        result
+    }
+
+    /// Same as [`MoonDream::send`], with the given client request ID.
+    async fn send_as<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Value,
+        client_request_id: &Uuid,
+    ) -> Result<ApiResponse<T>, Error> {
+        let url = self.template()?.url(path)?;
+        let payload = self.payload(body)?;
+        let client_request_id = client_request_id.to_string();
+        let span = RequestSpan::new(url.as_str(), payload.json().len(), &client_request_id);
+        if self.log_bodies {
+            span.request_body(payload.json());
+        }
+        let started_at = SystemTime::now();
+        let start = Instant::now();
+
+        let cache = self
+            .cache
+            .as_ref()
+            .filter(|_| payload.is_cacheable())
+            .map(|cache| (cache, Cache::key(url.as_str(), payload.json())));
+        if let Some((cache, key)) = &cache
+            && let Some((cached, refresh)) = cache.get(key, path).await
+        {
+            if refresh {
+                self.revalidate(cache, key, path, &payload);
+            }
+            let (data, flags) = decode::decode(&cached, self.lenient_decode)?;
+            span.cache_hit(start.elapsed());
+            self.usage.record_cache_hit(path);
+            return Ok(ApiResponse {
+                data,
+                meta: ResponseMeta {
+                    started_at,
+                    completed_at: SystemTime::now(),
+                    latency: start.elapsed(),
+                    endpoint: url.into(),
+                    failovers: 0,
+                    attempt: 0,
+                    backoff: Duration::ZERO,
+                    cached: true,
+                    client_request_id: None,
+                    connection: None,
+                    flags,
+                    status: None,
+                    headers: ResponseHeaders::default(),
+                    raw_body: Bytes::from(cached),
+                    provenance: Vec::new(),
+                },
+            });
+        }
+
+        let retry = self
+            .retry
+            .or_else(|| self.defaults().retry())
+            .unwrap_or_default();
+        let mut attempt = 0;
+        let mut waited = Duration::ZERO;
+        let response = loop {
+            attempt += 1;
+            let result = match self.attempt_timeout() {
+                Ok(timeout) => {
+                    let execute = async {
+                        if let Some(quota) = &self.quota {
+                            quota.acquire().await?;
+                        }
+                        self.usage.record_request(path, payload.json().len());
+                        self.execute_any(path, &payload, &client_request_id, attempt, timeout)
+                            .await
+                    };
+                    self.cancellable(span.instrument(execute))
+                        .await
+                        .map_err(|error| self.deadline_error(error))
+                }
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(response) => break response,
+                Err(error)
+                    if (attempt <= retry.max_retries() && error.is_retryable())
+                        || (self.in_startup_grace() && error.is_model_loading()) =>
+                {
+                    let backoff = retry.backoff(attempt);
+                    span.retry(attempt, &error, backoff);
+                    let sleep = async {
+                        rt::sleep(backoff).await;
+                        Ok(())
+                    };
+                    if let Err(error) = self.cancellable(sleep).await {
+                        span.failure(attempt, &error, start.elapsed());
+                        self.usage.record_error(path, attempt, waited);
+                        return Err(error);
+                    }
+                    waited += backoff;
+                }
+                Err(error) => {
+                    span.failure(attempt, &error, start.elapsed());
+                    self.usage.record_error(path, attempt, waited);
+                    return Err(error);
+                }
+            }
+        };
+        if self.log_bodies {
+            span.response_body(&response.body);
+        }
+        let (data, flags) = match decode::decode(&response.body, self.lenient_decode) {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                let error = Error::from(error);
+                span.failure(attempt, &error, start.elapsed());
+                self.usage.record_error(path, attempt, waited);
+                return Err(error);
+            }
+        };
+        let latency = start.elapsed();
+        span.success(response.status.as_u16(), attempt, &response.body, latency);
+        self.usage.record_success(path, &response.body, latency);
+
+        if let Some((cache, key)) = &cache {
+            cache.put(key, response.body.to_vec()).await;
+        }
+        Ok(ApiResponse {
+            data,
+            meta: ResponseMeta {
+                started_at,
+                completed_at: SystemTime::now(),
+                latency,
+                endpoint: response.url.into(),
+                failovers: response.failovers,
+                attempt,
+                backoff: waited,
+                cached: false,
+                client_request_id: Some(client_request_id),
+                connection: response.connection,
+                flags,
+                status: Some(response.status),
+                headers: response.headers,
+                raw_body: response.body,
+                provenance: Vec::new(),
+            },
+        })
+    }
+
+    /// Refresh the cache entry `key` with a background request, see
+    /// [`Cache::with_stale_while_revalidate`].
+    fn revalidate(&self, cache: &Cache, key: &str, path: &str, payload: &Payload) {
+        let (md, cache, key, path, payload) = (
+            self.clone(),
+            cache.clone(),
+            key.to_string(),
+            path.to_string(),
+            payload.clone(),
+        );
+        rt::spawn(async move {
+            let client_request_id = Uuid::new_v4().to_string();
+            let start = Instant::now();
+            if let Some(quota) = &md.quota
+                && quota.acquire().await.is_err()
+            {
+                cache.refreshed(&key);
+                return;
+            }
+            md.usage.record_request(&path, payload.json().len());
+            let result = md
+                .execute_any(&path, &payload, &client_request_id, 1, md.timeout)
+                .await;
+            match result {
+                Ok(response) if serde_json::from_slice::<Value>(&response.body).is_ok() => {
+                    md.usage
+                        .record_success(&path, &response.body, start.elapsed());
+                    cache.put(&key, response.body.to_vec()).await;
+                }
+                _ => md.usage.record_error(&path, 1, Duration::ZERO),
+            }
+            cache.refreshed(&key);
+        });
+    }
+
+    /// Encode `body` as JSON, or as a multipart form with
+    /// [`ImageTransport::Multipart`] when it carries an `image_url`.
+    fn payload(&self, body: Value) -> Result<Payload, Error> {
+        let json = Bytes::from(serde_json::to_vec(&body)?);
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.transport == ImageTransport::Multipart
+            && body.get("image_url").is_some_and(Value::is_string)
+        {
+            return Ok(Payload::Multipart { json, fields: body });
+        }
+        Ok(Payload::Json(json))
+    }
+
+    /// Return `true` within the [`MoonDream::with_startup_grace`] window.
+    fn in_startup_grace(&self) -> bool {
+        self.startup_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Timeout of the next attempt, shortened to the remaining time before
+    /// the deadline.
+    fn attempt_timeout(&self) -> Result<Duration, Error> {
+        let timeout = self.options.timeout().unwrap_or(self.timeout);
+        match self.options.deadline() {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    Err(Error::DeadlineExceeded)
+                } else {
+                    Ok(timeout.min(remaining))
+                }
+            }
+            None => Ok(timeout),
+        }
+    }
+
+    /// Report a timeout caused by the deadline as [`Error::DeadlineExceeded`].
+    fn deadline_error(&self, error: Error) -> Error {
+        let timed_out = matches!(&error, Error::PointError(error) if error.is_timeout());
+        let expired = self
+            .options
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if timed_out && expired {
+            Error::DeadlineExceeded
+        } else {
+            error
+        }
+    }
+
+    /// Run `future` until it completes or the cancellation token fires.
+    async fn cancellable<T>(
+        &self,
+        future: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        match self.options.cancellation() {
+            Some(token) => token
+                .run_until_cancelled(future)
+                .await
+                .unwrap_or(Err(Error::Cancelled)),
+            None => future.await,
+        }
+    }
+
+    /// Request template of the client, see [`template`].
+    fn template(&self) -> Result<&RequestTemplate, Error> {
+        let fallbacks = self.failover.as_ref().map_or(&[][..], Failover::fallbacks);
+        self.template.get_or_build(
+            &self.endpoint,
+            fallbacks,
+            &self.auth,
+            &self.token,
+            &self.headers,
+        )
+    }
+
+    /// Perform a single attempt against the primary endpoint, or against
+    /// each [`failover`] endpoint in turn until one answers.
+    async fn execute_any(
+        &self,
+        path: &str,
+        payload: &Payload,
+        client_request_id: &str,
+        attempt: u32,
+        timeout: Duration,
+    ) -> Result<RawResponse, Error> {
+        let template = self.template()?;
+        let Some(failover) = &self.failover else {
+            let url = template.url(path)?;
+            return self
+                .execute(&url, payload, client_request_id, attempt, timeout)
+                .await;
+        };
+
+        let mut failovers = 0;
+        let mut last_error = None;
+        for index in failover.order(&self.failover_policy) {
+            let url = template.url_at(index, path)?;
+            match self
+                .execute(&url, payload, client_request_id, attempt, timeout)
+                .await
+            {
+                Ok(response) => {
+                    failover.succeeded(index);
+                    return Ok(RawResponse {
+                        failovers,
+                        ..response
+                    });
+                }
+                Err(error) if Failover::should_fail_over(&error) => {
+                    failover.failed(index, &self.failover_policy);
+                    failovers += 1;
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.expect("failover has at least two endpoints"))
+    }
+
+    /// Perform a single POST request and return the raw response.
+    async fn execute(
+        &self,
+        url: &Url,
+        payload: &Payload,
+        client_request_id: &str,
+        attempt: u32,
+        timeout: Duration,
+    ) -> Result<RawResponse, Error> {
+        let template = self.template()?;
+        let mut request = match payload {
+            Payload::Json(json) => reqwest::Request::try_from(protocol::build_request(
+                template,
+                &template.with_query(url),
+                json.clone(),
+            )?)?,
+            #[cfg(not(target_arch = "wasm32"))]
+            Payload::Multipart { fields, .. } => template
+                .authorize(self.client.post(url.clone()))
+                .multipart(transport::form(fields).await?)
+                .build()?,
+        };
+        let client_request_id = HeaderValue::from_str(client_request_id)
+            .map_err(|e| Error::InvalidConfig(format!("client request ID: {e}")))?;
+        request
+            .headers_mut()
+            .insert("X-Client-Request-Id", client_request_id);
+        // The browser `fetch` API has no per-request timeout.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            *request.timeout_mut() = Some(timeout);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = timeout;
+        for interceptor in &self.request_interceptors {
+            interceptor.intercept(&mut request).await?;
+        }
+
+        let sent_at = Instant::now();
+        #[cfg(all(feature = "vcr", not(target_arch = "wasm32")))]
+        let result = match &self.cassette {
+            Some(cassette) => cassette.execute(&self.client, request).await?,
+            None => self.client.execute(request).await?,
+        };
+        #[cfg(not(all(feature = "vcr", not(target_arch = "wasm32"))))]
+        let result = self.client.execute(request).await?;
+        let context = ResponseContext {
+            url: url.as_str(),
+            status: result.status(),
+            headers: result.headers(),
+            elapsed: sent_at.elapsed(),
+            attempt,
+        };
+        for interceptor in &self.response_interceptors {
+            interceptor.observe(&context).await;
+        }
+
+        let status = result.status();
+        let headers = ResponseHeaders::from_headers(result.headers());
+        #[cfg(not(target_arch = "wasm32"))]
+        let connection = Some(ConnectionInfo {
+            version: result.version(),
+            remote_addr: result.remote_addr(),
+            tls: result
+                .extensions()
+                .get::<reqwest::tls::TlsInfo>()
+                .map(|info| TlsDetails {
+                    peer_certificate: info.peer_certificate().map(Bytes::copy_from_slice),
+                }),
+        });
+        #[cfg(target_arch = "wasm32")]
+        let connection = None;
+        let body = result.bytes().await?;
+        protocol::check_status(status, &body)?;
+        Ok(RawResponse {
+            url: url.clone(),
+            failovers: 0,
+            status,
+            headers,
+            connection,
+            body,
+        })
+    }
+}
+
+/// Remove a markdown code fence, such as `` ```json ... ``` ``, around `text`.
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(inner) = text
+        .strip_prefix("```")
+        .and_then(|inner| inner.strip_suffix("```"))
+    else {
+        return text;
+    };
+    // Skip the language tag on the opening line.
+    match inner.split_once('\n') {
+        Some((tag, body)) if !tag.trim_start().starts_with(['{', '[']) => body.trim(),
+        _ => inner.trim(),
+    }
+}
+
+/// A successful HTTP response, before decoding.
+struct RawResponse {
+    /// URL of the endpoint that answered.
+    url: Url,
+    /// Number of failover endpoints that failed before `url`.
+    failovers: u32,
+    status: StatusCode,
+    headers: ResponseHeaders,
+    connection: Option<ConnectionInfo>,
+    body: Bytes,
+}
+
+/// Body of a request.
+#[derive(Clone)]
+enum Payload {
+    /// A JSON body.
+    Json(Bytes),
+    /// A multipart form built from the fields of a JSON object, see
+    /// [`transport`].
+    #[cfg(not(target_arch = "wasm32"))]
+    Multipart { json: Bytes, fields: Value },
+}
+
+impl Payload {
+    /// JSON encoding of the body, used for logging, caching and usage.
+    fn json(&self) -> &Bytes {
+        match self {
+            Payload::Json(json) => json,
+            #[cfg(not(target_arch = "wasm32"))]
+            Payload::Multipart { json, .. } => json,
+        }
+    }
+
+    /// Return `false` for uploads of local files, whose content may change
+    /// under the same path.
+    fn is_cacheable(&self) -> bool {
+        match self {
+            Payload::Json(_) => true,
+            #[cfg(not(target_arch = "wasm32"))]
+            Payload::Multipart { fields, .. } => !transport::uploads_file(fields),
+        }
+    }
+}
+
+/// Controls the length of the caption returned by [`MoonDream::caption`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionLength {
+    /// A brief caption.
+    Short,
+    /// A normal length caption.
+    Normal,
+}
+
+impl CaptionLength {
+    #[cfg(test)]
+    fn as_str(&self) -> &'static str {
+        match self {
+            CaptionLength::Short => "short",
+            CaptionLength::Normal => "normal",
+        }
+    }
+}
+
+/// Response from the `/caption` endpoint.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct CaptionResponse {
+    /// Unique request identifier returned by the API.
+    pub request_id: Option<String>,
+    /// The generated caption text.
+    pub caption: String,
+    /// Post-processing flags set by the client.
+    #[serde(default, skip_serializing_if = "ResultFlags::is_empty")]
+    pub flags: ResultFlags,
+    /// ISO 639-3 code of the caption language, detected by the client
+    /// (feature `lang`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_points_response_deserialization() {
+        let json = r#"{
+            "request_id": "abc",
+            "points": [{"x": 0.1, "y": 0.2}],
+            "count": 1
+        }"#;
+
+        let resp: PointsResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(resp.request_id, Some("abc".to_string()));
+        assert_eq!(
+            resp.points,
+            vec![Point {
+                x: 0.1,
+                y: 0.2,
+                confidence: None
+            }]
+        );
+        assert_eq!(resp.count, Some(1));
+    }
+
+    #[test]
+    fn test_responses_round_trip() {
+        fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: T) {
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(serde_json::from_str::<T>(&json).unwrap(), value, "{json}");
+        }
+
+        round_trip(PointsResponse {
+            request_id: Some("abc".into()),
+            points: vec![Point {
+                x: 0.25,
+                y: 0.5,
+                confidence: Some(0.75),
+            }],
+            count: Some(1),
+        });
+        round_trip(DetectResponse {
+            request_id: None,
+            objects: vec![DetectionObject {
+                x_min: 0.125,
+                y_min: 0.25,
+                x_max: 0.5,
+                y_max: 1.0,
+                confidence: None,
+            }],
+        });
+        round_trip(QueryResponse {
+            request_id: Some("q".into()),
+            answer: "yes".into(),
+            flags: ResultFlags::CONTENT_FILTERED | ResultFlags::LENIENT_PARSE,
+            language: Some("eng".into()),
+        });
+        round_trip(CaptionResponse {
+            request_id: None,
+            caption: "a cat".into(),
+            flags: ResultFlags::empty(),
+            language: None,
+        });
+
+        let json = serde_json::to_value(DetectionObject {
+            x_min: 0.0,
+            y_min: 0.0,
+            x_max: 1.0,
+            y_max: 1.0,
+            confidence: None,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"x_min": 0.0, "y_min": 0.0, "x_max": 1.0, "y_max": 1.0})
+        );
+    }
+
+    #[tokio::test]
     async fn test_points_functional() {
         let server = MockServer::start().await;
 
@@ -288,7 +1859,11 @@ mod tests {
             resp,
             PointsResponse {
                 request_id: Some("abc".to_string()),
-                points: vec![Point { x: 0.5, y: 0.5 }],
+                points: vec![Point {
+                    x: 0.5,
+                    y: 0.5,
+                    confidence: None
+                }],
                 count: Some(1),
             }
         );
@@ -309,7 +1884,8 @@ mod tests {
                 x_min: 0.1,
                 y_min: 0.2,
                 x_max: 0.3,
-                y_max: 0.4
+                y_max: 0.4,
+                confidence: None,
             }]
         );
     }
@@ -345,7 +1921,8 @@ mod tests {
                     x_min: 0.1,
                     y_min: 0.2,
                     x_max: 0.3,
-                    y_max: 0.4
+                    y_max: 0.4,
+                    confidence: None,
                 }],
             }
         );
@@ -391,6 +1968,8 @@ mod tests {
             CaptionResponse {
                 request_id: Some("req2".to_string()),
                 caption: "a cat on a mat".to_string(),
+                flags: ResultFlags::empty(),
+                language: None,
             }
         );
     }
@@ -435,6 +2014,8 @@ mod tests {
             QueryResponse {
                 request_id: Some("req3".to_string()),
                 answer: "It is a cat".to_string(),
+                flags: ResultFlags::empty(),
+                language: None,
             }
         );
     }
@@ -490,6 +2071,8 @@ mod tests {
             QueryResponse {
                 request_id: Some("req4".to_string()),
                 answer: "Remote answer".to_string(),
+                flags: ResultFlags::empty(),
+                language: None,
             }
         );
     }
@@ -522,9 +2105,854 @@ mod tests {
             resp,
             PointsResponse {
                 request_id: Some("abc".to_string()),
-                points: vec![Point { x: 0.5, y: 0.5 }],
+                points: vec![Point {
+                    x: 0.5,
+                    y: 0.5,
+                    confidence: None
+                }],
                 count: Some(1),
             }
         );
     }
+
+    #[tokio::test]
+    async fn test_auth_strategies() {
+        use wiremock::matchers::query_param;
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .and(header("authorization", "Bearer secret"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"caption": "bearer"})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .and(query_param("sig", "secret"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"caption": "query"})),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("secret").with_endpoint(server.uri());
+        let bearer = md.clone().with_auth(Auth::Bearer);
+        assert_eq!(bearer.caption("img", None).await.unwrap().caption, "bearer");
+
+        let signed = md.with_auth(Auth::Query("sig".into()));
+        let response = signed.caption_with_meta("img", None).await.unwrap();
+        assert_eq!(response.data.caption, "query");
+        assert!(!response.meta.endpoint.contains("secret"));
+
+        let requests = server.received_requests().await.unwrap();
+        assert!(
+            requests
+                .iter()
+                .all(|request| !request.headers.contains_key("x-moondream-auth"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_content_filter() {
+        let server = MockServer::start().await;
+
+        let body = serde_json::json!({
+            "request_id": "req5",
+            "answer": "A cat sits next to a knife",
+        });
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&body))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_content_filter(ContentFilter::new().deny_word("knife"));
+
+        let resp = md
+            .query("data:image/png;base64,AAA", "What is this?")
+            .await
+            .unwrap();
+
+        assert_eq!(resp.answer, "A cat sits next to a [redacted]");
+        assert!(resp.flags.contains(ResultFlags::CONTENT_FILTERED));
+    }
+
+    #[tokio::test]
+    async fn test_cache_serves_repeated_calls() {
+        let server = MockServer::start().await;
+
+        let body = serde_json::json!({
+            "request_id": "req6",
+            "caption": "a cat on a mat"
+        });
+
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&body))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_cache(Cache::memory(10));
+
+        for _ in 0..3 {
+            let resp = md.caption("data:image/png;base64,AAA", None).await.unwrap();
+            assert_eq!(resp.caption, "a cat on a mat");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_revalidates_stale_entries() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "a cat"
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "a dog"
+            })))
+            .mount(&server)
+            .await;
+
+        // Entries are always within the window, so every hit refreshes.
+        let cache = Cache::memory(10)
+            .with_ttl(Duration::from_secs(60))
+            .with_stale_while_revalidate(Duration::from_secs(120));
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_cache(cache);
+        let image = "data:image/png;base64,AAA";
+
+        assert_eq!(md.caption(image, None).await.unwrap().caption, "a cat");
+        let stale = md.caption_with_meta(image, None).await.unwrap();
+        assert!(stale.meta.cached);
+        assert_eq!(stale.caption, "a cat");
+
+        while server.received_requests().await.unwrap().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        for _ in 0..100 {
+            if md.caption(image, None).await.unwrap().caption == "a dog" {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the entry was not refreshed");
+    }
+
+    #[tokio::test]
+    async fn test_detect_with_meta() {
+        let server = MockServer::start().await;
+
+        let body = serde_json::json!({
+            "request_id": "req7",
+            "objects": []
+        });
+
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&body))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+
+        let resp = md
+            .detect_with_meta("data:image/png;base64,AAA", "object")
+            .await
+            .unwrap();
+
+        assert_eq!(resp.request_id, Some("req7".to_string()));
+        assert_eq!(resp.meta.endpoint, format!("{}/detect", server.uri()));
+        assert_eq!(resp.meta.attempt, 1);
+        assert!(!resp.meta.cached);
+        assert!(resp.meta.completed_at >= resp.meta.started_at);
+
+        let connection = resp.meta.connection.unwrap();
+        assert_eq!(connection.version, reqwest::Version::HTTP_11);
+        assert!(connection.remote_addr.is_some());
+        assert!(connection.tls.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_server_error() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "req8",
+                "answer": "Retried answer",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_retry(RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(1)));
+
+        let resp = md
+            .query_with_meta("data:image/png;base64,AAA", "What is this?")
+            .await
+            .unwrap();
+
+        assert_eq!(resp.answer, "Retried answer");
+        assert_eq!(resp.meta.attempt, 2);
+        assert!(resp.meta.backoff >= Duration::from_millis(1));
+
+        let id = resp.meta.client_request_id.as_deref().unwrap();
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert_eq!(request.headers["x-client-request-id"], id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_startup_grace_retries_model_loading() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Model is still loading"))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"answer": "Ready"})),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_retry(RetryPolicy::none().with_initial_backoff(Duration::from_millis(1)));
+        let error = md.query("img", "What is this?").await.unwrap_err();
+        assert!(matches!(error, Error::ModelLoading { .. }));
+
+        let md = md.with_startup_grace(Duration::from_secs(5));
+        let resp = md.query_with_meta("img", "What is this?").await.unwrap();
+        assert_eq!(resp.answer, "Ready");
+        assert_eq!(resp.meta.attempt, 2);
+    }
+
+    #[tokio::test]
+    async fn test_error_status_matches_protocol() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(429).set_body_string("slow down"))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_retry(RetryPolicy::none());
+        let error = md.query("img", "What is this?").await.unwrap_err();
+        let response = ::http::Response::builder()
+            .status(429)
+            .body("slow down")
+            .unwrap();
+        let expected = md
+            .protocol()
+            .response::<QueryResponse>(&response)
+            .unwrap_err();
+        assert!(matches!(
+            (&error, &expected),
+            (Error::Status { status, message }, Error::Status { status: s, message: m })
+                if status == s && message == m
+        ));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_startup_grace_does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("error loading image"))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_retry(RetryPolicy::none().with_initial_backoff(Duration::from_millis(50)))
+            .with_startup_grace(Duration::from_secs(5));
+        let start = std::time::Instant::now();
+        let error = md.query("img", "What is this?").await.unwrap_err();
+        assert!(!error.is_model_loading());
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_defaults() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .and(body_partial_json(serde_json::json!({"length": "short"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "req9",
+                "caption": "a cat",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_defaults(
+                MoonDreamDefaults::new()
+                    .with_caption_length(CaptionLength::Short)
+                    .with_max_image_size(64),
+            );
+
+        let resp = md.caption("data:image/png;base64,AAA", None).await.unwrap();
+        assert_eq!(resp.caption, "a cat");
+
+        let err = md
+            .caption(format!("data:image/png;base64,{}", "A".repeat(64)), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ImageTooLarge { limit: 64, .. }));
+    }
+
+    #[derive(Debug)]
+    struct SignRequest;
+
+    #[async_trait::async_trait]
+    impl RequestInterceptor for SignRequest {
+        async fn intercept(&self, request: &mut reqwest::Request) -> Result<(), Error> {
+            request
+                .headers_mut()
+                .insert("x-signature", "signed".parse().unwrap());
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordStatus(std::sync::Mutex<Vec<u16>>);
+
+    #[async_trait::async_trait]
+    impl ResponseInterceptor for Arc<RecordStatus> {
+        async fn observe(&self, response: &ResponseContext<'_>) {
+            self.0.lock().unwrap().push(response.status.as_u16());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interceptors() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header("x-signature", "signed"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "req10",
+                "answer": "Signed answer",
+            })))
+            .mount(&server)
+            .await;
+
+        let statuses = Arc::new(RecordStatus::default());
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_request_interceptor(SignRequest)
+            .with_response_interceptor(statuses.clone());
+
+        let resp = md
+            .query("data:image/png;base64,AAA", "What is this?")
+            .await
+            .unwrap();
+
+        assert_eq!(resp.answer, "Signed answer");
+        assert_eq!(*statuses.0.lock().unwrap(), vec![200]);
+    }
+
+    #[tokio::test]
+    async fn test_query_multi_functional() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_partial_json(serde_json::json!({
+                "image_urls": ["https://example.com/a.png", "data:image/png;base64,AAE="],
+                "question": "What changed?",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "req11",
+                "answer": "The door is open",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+
+        let resp = md
+            .query_multi(
+                vec![
+                    ImageInput::url("https://example.com/a.png"),
+                    ImageInput::bytes(vec![0, 1], "image/png"),
+                ],
+                "What changed?",
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.answer, "The door is open");
+
+        let err = md.query_multi(vec![], "What changed?").await.unwrap_err();
+        assert!(matches!(err, Error::InvalidImage(_)));
+    }
+
+    #[tokio::test]
+    async fn test_lenient_decode_fallback() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": 42,
+                "answer": "A cat",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/point"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "points": [{"x": "0.5", "y": "0.5"}],
+                "count": "1",
+            })))
+            .mount(&server)
+            .await;
+
+        let strict = MoonDream::remote("token").with_endpoint(server.uri());
+        let err = strict.points("img", "cat").await.unwrap_err();
+        assert!(matches!(err, Error::Decode(_)));
+
+        let md = strict.with_lenient_decode(true);
+        let resp = md.points_with_meta("img", "cat").await.unwrap();
+        assert_eq!(
+            resp.points,
+            vec![Point {
+                x: 0.5,
+                y: 0.5,
+                confidence: None
+            }]
+        );
+        assert!(resp.meta.flags.contains(ResultFlags::LENIENT_PARSE));
+
+        let err = md.query("img", "What is this?").await.unwrap_err();
+        assert!(matches!(err, Error::Decode(_)));
+    }
+
+    #[tokio::test]
+    async fn test_confidence_scores() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .and(body_partial_json(serde_json::json!({ "confidence": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objects": [
+                    {"x_min": 0.1, "y_min": 0.1, "x_max": 0.2, "y_max": 0.2, "confidence": 0.9},
+                    {"x_min": 0.3, "y_min": 0.3, "x_max": 0.4, "y_max": 0.4, "confidence": 0.2},
+                    {"x_min": 0.5, "y_min": 0.5, "x_max": 0.6, "y_max": 0.6},
+                ],
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_confidence_scores(true);
+
+        let resp = md
+            .detect("img", "cat")
+            .await
+            .unwrap()
+            .filter_confidence(0.5);
+        let confidences: Vec<_> = resp.objects.iter().map(|o| o.confidence).collect();
+        assert_eq!(confidences, vec![Some(0.9), None]);
+    }
+
+    #[test]
+    fn test_points_filter_confidence_updates_count() {
+        let resp = PointsResponse {
+            request_id: None,
+            points: vec![
+                Point {
+                    x: 0.1,
+                    y: 0.1,
+                    confidence: Some(0.3),
+                },
+                Point {
+                    x: 0.2,
+                    y: 0.2,
+                    confidence: Some(0.8),
+                },
+            ],
+            count: Some(2),
+        };
+
+        let resp = resp.filter_confidence(0.5);
+        assert_eq!(resp.points.len(), 1);
+        assert_eq!(resp.count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_detect_many() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .and(body_partial_json(serde_json::json!({ "object": "person" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objects": [{"x_min": 0.1, "y_min": 0.1, "x_max": 0.2, "y_max": 0.2}],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .and(body_partial_json(serde_json::json!({ "object": "car" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objects": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .and(body_partial_json(
+                serde_json::json!({ "object": "bicycle" }),
+            ))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+
+        let found = md.detect_many("img", &["person", "car"]).await.unwrap();
+        assert_eq!(found["person"].len(), 1);
+        assert!(found["car"].is_empty());
+
+        let err = md
+            .detect_many("img", &["person", "bicycle"])
+            .await
+            .unwrap_err();
+        match err {
+            Error::DetectMany { detected, failures } => {
+                assert!(detected.contains_key("person"));
+                assert_eq!(failures.len(), 1);
+                assert_eq!(failures[0].0, "bicycle");
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[cfg(feature = "cookies")]
+    #[tokio::test]
+    async fn test_cookie_store_sends_affinity_cookie() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header("cookie", "backend=b2"))
+            .and(header("user-agent", "probe/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "sticky",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("set-cookie", "backend=b2; Path=/")
+                    .set_body_json(serde_json::json!({ "answer": "first" })),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_http_config(HttpConfig::new().with_user_agent("probe/1"))
+            .unwrap()
+            .with_cookie_store()
+            .unwrap();
+
+        assert_eq!(md.query("img", "q").await.unwrap().answer, "first");
+        assert_eq!(md.query("img", "q").await.unwrap().answer, "sticky");
+    }
+
+    #[test]
+    fn test_strip_code_fence() {
+        assert_eq!(strip_code_fence("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(strip_code_fence("```\n[1, 2]\n```"), "[1, 2]");
+        assert_eq!(strip_code_fence(" {\"a\": 1} "), "{\"a\": 1}");
+    }
+
+    #[tokio::test]
+    async fn test_query_json() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Car {
+            color: String,
+            doors: u32,
+        }
+
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_partial_json(serde_json::json!({
+                "question": "Describe the car.\n\nAnswer only with valid JSON, without any other text.",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "```json\n{\"color\": \"red\", \"doors\": 3}\n```",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "It is a red car.",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+
+        let car: Car = md.query_json("img", "Describe the car.").await.unwrap();
+        assert_eq!(
+            car,
+            Car {
+                color: "red".to_string(),
+                doors: 3
+            }
+        );
+
+        let err = md
+            .query_json::<Car>("img", "What is it?")
+            .await
+            .unwrap_err();
+        match err {
+            Error::InvalidModelOutput { raw, .. } => assert_eq!(raw, "It is a red car."),
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_options_deadline_and_cancellation() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_delay(Duration::from_secs(2))
+                    .set_body_json(serde_json::json!({ "answer": "late" })),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let err = md
+            .clone()
+            .with_options(RequestOptions::new().with_deadline(deadline))
+            .query("img", "q")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DeadlineExceeded));
+
+        let token = tokio_util::sync::CancellationToken::new();
+        let cancellable = md
+            .clone()
+            .with_options(RequestOptions::new().with_cancellation(token.clone()));
+        let call = tokio::spawn(async move { cancellable.query("img", "q").await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        token.cancel();
+        assert!(matches!(call.await.unwrap(), Err(Error::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_http_config_user_agent() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header("user-agent", "pipeline/1.0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "ok",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_http_config(HttpConfig::new().with_user_agent("pipeline/1.0"))
+            .unwrap();
+
+        assert_eq!(md.query("img", "q").await.unwrap().answer, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_meta_status_headers_and_raw_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-request-id", "srv-1")
+                    .insert_header("x-ratelimit-remaining", "9")
+                    .set_body_json(serde_json::json!({
+                        "request_id": "abc",
+                        "caption": "a cat",
+                    })),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let response = md.caption_with_meta("img", None).await.unwrap();
+        assert_eq!(response.meta.status, Some(StatusCode::OK));
+        assert_eq!(response.meta.headers.request_id.as_deref(), Some("srv-1"));
+        assert_eq!(response.meta.headers.rate_limit_remaining, Some(9));
+        assert_eq!(response.meta.raw_json().unwrap()["request_id"], "abc");
+        assert!(response.meta.raw_text().contains("a cat"));
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_endpoint() {
+        let primary = MockServer::start().await;
+        let backup = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "ok",
+            })))
+            .expect(2)
+            .mount(&backup)
+            .await;
+
+        let md = MoonDream::local("unused").with_endpoints([primary.uri(), backup.uri()]);
+        let response = md.query_with_meta("img", "q").await.unwrap();
+        assert_eq!(response.answer, "ok");
+        assert_eq!(response.meta.endpoint, format!("{}/query", backup.uri()));
+        assert_eq!(response.meta.failovers, 1);
+
+        // The primary endpoint is skipped during its cooldown.
+        let response = md.query_with_meta("img", "q").await.unwrap();
+        assert_eq!(response.meta.failovers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_streams_file() {
+        use wiremock::matchers::{body_string_contains, header_regex};
+
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(header_regex("content-type", "^multipart/form-data"))
+            .and(body_string_contains("name=\"image\"; filename=\"cat.png\""))
+            .and(body_string_contains("raw-png-bytes"))
+            .and(body_string_contains("What is this?"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "a cat",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = std::env::temp_dir().join(format!("moondream-upload-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("cat.png");
+        std::fs::write(&file, "raw-png-bytes").unwrap();
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_transport(ImageTransport::Multipart);
+        let response = md
+            .query(ImageInput::file(&file), "What is this?")
+            .await
+            .unwrap();
+        assert_eq!(response.answer, "a cat");
+
+        let small = md.with_max_image_size(4);
+        assert!(matches!(
+            small.query(ImageInput::file(&file), "q").await,
+            Err(Error::ImageTooLarge { size: 13, .. })
+        ));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tokio::test]
+    async fn test_mapped_file_requires_multipart() {
+        let file = std::env::temp_dir().join(format!("moondream-{}.png", Uuid::new_v4()));
+        std::fs::write(&file, "raw-png-bytes").unwrap();
+
+        let md = MoonDream::remote("token").with_endpoint("http://127.0.0.1:9");
+        assert!(matches!(
+            md.query(ImageInput::from_mmap(&file).unwrap(), "q").await,
+            Err(Error::InvalidConfig(_))
+        ));
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_usage_tracking() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "yes",
+                "usage": {"input_tokens": 20, "output_tokens": 2},
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let tenant = md.clone().with_usage_tracker(UsageTracker::new());
+
+        md.query("img", "q").await.unwrap();
+        md.query("img", "q").await.unwrap();
+        tenant.query("img", "q").await.unwrap();
+
+        let usage = md.reset_usage();
+        assert_eq!(usage.endpoints["query"].requests, 2);
+        assert_eq!(usage.endpoints["query"].input_tokens, 40);
+        assert!(usage.endpoints["query"].bytes_uploaded > 0);
+        assert!(md.usage().endpoints.is_empty());
+        assert_eq!(tenant.usage().total().requests, 1);
+    }
 }
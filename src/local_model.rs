@@ -0,0 +1,254 @@
+//! On-device inference backend (feature `local-model`).
+//!
+//! [`LocalMoonDream`] runs the Moondream 2B weights through
+//! [candle](https://github.com/huggingface/candle) and implements
+//! [`VisionClient`], so application code can switch between the hosted API
+//! and local inference without changes. Only `caption` and `query` are
+//! supported for now; `points` and `detect` return [`Error::Unsupported`].
+
+use crate::input::decode_data_uri;
+use crate::{
+    CaptionLength, CaptionResponse, DetectResponse, Error, PointsResponse, QueryResponse,
+    ResultFlags, VisionClient,
+};
+use async_trait::async_trait;
+use candle_core::{DType, Device, Module, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::moondream::{Config, Model};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+/// Side of the square image expected by the vision encoder.
+const IMAGE_SIZE: u32 = 378;
+
+/// Token sequence for `<END>`, emitted by some revisions instead of EOS.
+const END_SEQUENCE: [u32; 3] = [27, 10619, 29];
+
+/// Client running Moondream inference locally.
+///
+/// The model is loaded once and shared between clones. Inference runs on a
+/// blocking thread so it does not stall the async runtime; concurrent calls
+/// are serialized.
+#[derive(Clone)]
+pub struct LocalMoonDream {
+    inner: Arc<Mutex<LocalModel>>,
+    max_tokens: usize,
+}
+
+struct LocalModel {
+    model: Model,
+    tokenizer: Tokenizer,
+    device: Device,
+    dtype: DType,
+}
+
+impl std::fmt::Debug for LocalMoonDream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalMoonDream")
+            .field("max_tokens", &self.max_tokens)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalMoonDream {
+    /// Load the model on the CPU from a `model.safetensors` file and a
+    /// `tokenizer.json` file, as published in the `vikhyatk/moondream2`
+    /// repository.
+    pub fn load(weights: impl AsRef<Path>, tokenizer: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::load_on(weights, tokenizer, Device::Cpu)
+    }
+
+    /// Load the model on the given candle [`Device`].
+    ///
+    /// Weights are kept in `f16` on accelerators and `f32` on the CPU.
+    pub fn load_on(
+        weights: impl AsRef<Path>,
+        tokenizer: impl AsRef<Path>,
+        device: Device,
+    ) -> Result<Self, Error> {
+        let dtype = if device.is_cpu() {
+            DType::F32
+        } else {
+            DType::F16
+        };
+        let tokenizer = Tokenizer::from_file(tokenizer).map_err(candle_core::Error::msg)?;
+        // SAFETY: the weights file is memory-mapped read-only and must not be
+        // modified while the model is alive.
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights.as_ref()], dtype, &device)? };
+        let model = Model::new(&Config::v2(), vb)?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(LocalModel {
+                model,
+                tokenizer,
+                device,
+                dtype,
+            })),
+            max_tokens: 256,
+        })
+    }
+
+    /// Set the maximum number of tokens generated per answer (default 256).
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    async fn generate(&self, image: String, question: String) -> Result<String, Error> {
+        let bytes = decode_image(&image)?;
+        let inner = self.inner.clone();
+        let max_tokens = self.max_tokens;
+
+        tokio::task::spawn_blocking(move || {
+            let mut model = inner.lock().unwrap_or_else(|e| e.into_inner());
+            model.generate(&bytes, &question, max_tokens)
+        })
+        .await
+        .map_err(candle_core::Error::wrap)?
+    }
+}
+
+impl LocalModel {
+    fn generate(
+        &mut self,
+        image: &[u8],
+        question: &str,
+        max_tokens: usize,
+    ) -> Result<String, Error> {
+        let image = self.load_image(image)?;
+        let image_embeds = self.model.vision_encoder().forward(&image.unsqueeze(0)?)?;
+
+        let prompt = format!("\n\nQuestion: {question}\n\nAnswer:");
+        let mut tokens = self
+            .tokenizer
+            .encode(prompt, true)
+            .map_err(candle_core::Error::msg)?
+            .get_ids()
+            .to_vec();
+        let eos = *self
+            .tokenizer
+            .get_vocab(true)
+            .get("<|endoftext|>")
+            .ok_or_else(|| candle_core::Error::msg("tokenizer has no <|endoftext|> token"))?;
+
+        let mut logits_processor = LogitsProcessor::new(0, None, None);
+        let mut generated = Vec::new();
+        self.model.text_model.clear_kv_cache();
+
+        for index in 0..max_tokens {
+            let logits = if index == 0 {
+                let bos = Tensor::new(&[eos], &self.device)?.unsqueeze(0)?;
+                let input = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+                self.model
+                    .text_model
+                    .forward_with_img(&bos, &input, &image_embeds)?
+            } else {
+                let input = Tensor::new(&tokens[tokens.len() - 1..], &self.device)?.unsqueeze(0)?;
+                self.model.text_model.forward(&input)?
+            };
+            let logits = logits.squeeze(0)?.to_dtype(DType::F32)?;
+            let next = logits_processor.sample(&logits)?;
+            tokens.push(next);
+
+            if next == eos {
+                break;
+            }
+            generated.push(next);
+            if generated.ends_with(&END_SEQUENCE) {
+                generated.truncate(generated.len() - END_SEQUENCE.len());
+                break;
+            }
+        }
+
+        let answer = self
+            .tokenizer
+            .decode(&generated, true)
+            .map_err(candle_core::Error::msg)?;
+        Ok(answer.trim().to_string())
+    }
+
+    /// Decode, resize and normalize an image into a `(3, 378, 378)` tensor.
+    fn load_image(&self, bytes: &[u8]) -> Result<Tensor, Error> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| Error::InvalidImage(e.to_string()))?
+            .resize_to_fill(
+                IMAGE_SIZE,
+                IMAGE_SIZE,
+                image::imageops::FilterType::Triangle,
+            )
+            .to_rgb8();
+        let size = IMAGE_SIZE as usize;
+        let data = Tensor::from_vec(image.into_raw(), (size, size, 3), &Device::Cpu)?
+            .permute((2, 0, 1))?
+            .to_dtype(DType::F32)?;
+        // Scale to [-1, 1], matching the mean/std of 0.5 used in training.
+        let data = ((data / 127.5)? - 1.0)?;
+        Ok(data.to_device(&self.device)?.to_dtype(self.dtype)?)
+    }
+}
+
+/// Extract the raw bytes from a base64 `data:` URI.
+fn decode_image(image: &str) -> Result<Vec<u8>, Error> {
+    let (_, bytes) = decode_data_uri(image)
+        .ok_or_else(|| Error::InvalidImage("expected a base64 data URI".to_string()))??;
+    Ok(bytes)
+}
+
+#[async_trait]
+impl VisionClient for LocalMoonDream {
+    async fn points(&self, _image: String, _object: String) -> Result<PointsResponse, Error> {
+        Err(Error::Unsupported("points"))
+    }
+
+    async fn detect(&self, _image: String, _object: String) -> Result<DetectResponse, Error> {
+        Err(Error::Unsupported("detect"))
+    }
+
+    async fn caption(
+        &self,
+        image: String,
+        length: Option<CaptionLength>,
+    ) -> Result<CaptionResponse, Error> {
+        let prompt = match length.unwrap_or(CaptionLength::Normal) {
+            CaptionLength::Short => "Write a short, one sentence caption for this image.",
+            CaptionLength::Normal => "Describe this image.",
+        };
+        let caption = self.generate(image, prompt.to_string()).await?;
+        Ok(CaptionResponse {
+            request_id: None,
+            caption,
+            flags: ResultFlags::empty(),
+            language: None,
+        })
+    }
+
+    async fn query(&self, image: String, question: String) -> Result<QueryResponse, Error> {
+        let answer = self.generate(image, question).await?;
+        Ok(QueryResponse {
+            request_id: None,
+            answer,
+            flags: ResultFlags::empty(),
+            language: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_image() {
+        assert_eq!(
+            decode_image("data:image/png;base64,AAE=").unwrap(),
+            vec![0, 1]
+        );
+        assert!(matches!(
+            decode_image("https://example.com/cat.png"),
+            Err(Error::InvalidImage(_))
+        ));
+    }
+}
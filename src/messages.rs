@@ -0,0 +1,199 @@
+//! Short, non-technical error messages for end users.
+//!
+//! [`Error::to_user_message`] maps an error to a sentence that can be shown
+//! as is in a user interface, in one of the supported [`Locale`]s. Details
+//! useful to developers, such as status codes or server messages, are left
+//! out: log the [`Error`] itself for those.
+//!
+//! ```
+//! use moondream::{Error, Locale};
+//!
+//! let error = Error::ImageTooLarge { size: 30_000_000, limit: 10_000_000 };
+//! let locale: Locale = "fr-CA".parse().unwrap_or_default();
+//! assert_eq!(error.to_user_message(locale), "Cette image est trop volumineuse.");
+//! ```
+
+use crate::Error;
+use reqwest::StatusCode;
+use std::str::FromStr;
+
+/// Language of the messages returned by [`Error::to_user_message`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    /// English.
+    #[default]
+    En,
+    /// French.
+    Fr,
+    /// German.
+    De,
+    /// Spanish.
+    Es,
+}
+
+impl FromStr for Locale {
+    type Err = Error;
+
+    /// Parse a language tag such as `fr` or `de-CH`, ignoring the region.
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "en" => Ok(Locale::En),
+            "fr" => Ok(Locale::Fr),
+            "de" => Ok(Locale::De),
+            "es" => Ok(Locale::Es),
+            _ => Err(Error::InvalidConfig(format!("unsupported locale {tag:?}"))),
+        }
+    }
+}
+
+/// Kind of failure, as explained to end users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    BadKey,
+    RateLimited,
+    ImageTooLarge,
+    InvalidImage,
+    Unavailable,
+    TimedOut,
+    Cancelled,
+    Other,
+}
+
+impl Failure {
+    fn of(error: &Error) -> Self {
+        match error {
+            Error::PointError(error) if error.is_timeout() => Failure::TimedOut,
+            Error::PointError(error) if error.is_connect() => Failure::Unavailable,
+            Error::PointError(error) => error.status().map_or(Failure::Other, Failure::of_status),
+            Error::Status { status, .. } => Failure::of_status(*status),
+            Error::ModelLoading { .. } | Error::Unavailable { .. } => Failure::Unavailable,
+            Error::BudgetExhausted { .. } => Failure::RateLimited,
+            Error::ImageTooLarge { .. } => Failure::ImageTooLarge,
+            Error::InvalidImage(_) => Failure::InvalidImage,
+            Error::DeadlineExceeded => Failure::TimedOut,
+            Error::Cancelled => Failure::Cancelled,
+            Error::DetectMany { failures, .. } => failures
+                .first()
+                .map_or(Failure::Other, |(_, error)| Failure::of(error)),
+            _ => Failure::Other,
+        }
+    }
+
+    fn of_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Failure::BadKey,
+            StatusCode::TOO_MANY_REQUESTS => Failure::RateLimited,
+            StatusCode::PAYLOAD_TOO_LARGE => Failure::ImageTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => Failure::InvalidImage,
+            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => Failure::TimedOut,
+            status if status.is_server_error() => Failure::Unavailable,
+            _ => Failure::Other,
+        }
+    }
+
+    fn message(self, locale: Locale) -> &'static str {
+        use Failure::*;
+        use Locale::*;
+        match (self, locale) {
+            (BadKey, En) => "The service could not verify your access key.",
+            (BadKey, Fr) => "Le service n'a pas pu vérifier votre clé d'accès.",
+            (BadKey, De) => "Der Dienst konnte Ihren Zugangsschlüssel nicht überprüfen.",
+            (BadKey, Es) => "El servicio no pudo verificar su clave de acceso.",
+            (RateLimited, En) => "Too many requests. Please wait a moment and try again.",
+            (RateLimited, Fr) => "Trop de requêtes. Veuillez patienter un instant et réessayer.",
+            (RateLimited, De) => {
+                "Zu viele Anfragen. Bitte warten Sie einen Moment und versuchen Sie es erneut."
+            }
+            (RateLimited, Es) => "Demasiadas solicitudes. Espere un momento y vuelva a intentarlo.",
+            (ImageTooLarge, En) => "This image is too large.",
+            (ImageTooLarge, Fr) => "Cette image est trop volumineuse.",
+            (ImageTooLarge, De) => "Dieses Bild ist zu groß.",
+            (ImageTooLarge, Es) => "Esta imagen es demasiado grande.",
+            (InvalidImage, En) => "This image could not be read.",
+            (InvalidImage, Fr) => "Cette image n'a pas pu être lue.",
+            (InvalidImage, De) => "Dieses Bild konnte nicht gelesen werden.",
+            (InvalidImage, Es) => "No se pudo leer esta imagen.",
+            (Unavailable, En) => "The service is unavailable. Please try again later.",
+            (Unavailable, Fr) => "Le service est indisponible. Veuillez réessayer plus tard.",
+            (Unavailable, De) => {
+                "Der Dienst ist nicht verfügbar. Bitte versuchen Sie es später erneut."
+            }
+            (Unavailable, Es) => "El servicio no está disponible. Inténtelo de nuevo más tarde.",
+            (TimedOut, En) => "The service took too long to respond. Please try again.",
+            (TimedOut, Fr) => "Le service a mis trop de temps à répondre. Veuillez réessayer.",
+            (TimedOut, De) => {
+                "Der Dienst hat zu lange für eine Antwort gebraucht. Bitte versuchen Sie es erneut."
+            }
+            (TimedOut, Es) => "El servicio tardó demasiado en responder. Inténtelo de nuevo.",
+            (Cancelled, En) => "The request was cancelled.",
+            (Cancelled, Fr) => "La requête a été annulée.",
+            (Cancelled, De) => "Die Anfrage wurde abgebrochen.",
+            (Cancelled, Es) => "La solicitud fue cancelada.",
+            (Other, En) => "Something went wrong. Please try again.",
+            (Other, Fr) => "Une erreur est survenue. Veuillez réessayer.",
+            (Other, De) => "Etwas ist schiefgelaufen. Bitte versuchen Sie es erneut.",
+            (Other, Es) => "Algo salió mal. Inténtelo de nuevo.",
+        }
+    }
+}
+
+impl Error {
+    /// Short message describing the error to end users in `locale`, see
+    /// the [module documentation](crate::messages).
+    pub fn to_user_message(&self, locale: Locale) -> &'static str {
+        Failure::of(self).message(locale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_parse_locale() {
+        assert_eq!("de-CH".parse::<Locale>().unwrap(), Locale::De);
+        assert_eq!("ES".parse::<Locale>().unwrap(), Locale::Es);
+        assert_eq!("en_GB".parse::<Locale>().unwrap(), Locale::En);
+        assert!(matches!(
+            "ja".parse::<Locale>(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_failure_of_status() {
+        assert_eq!(Failure::of_status(StatusCode::FORBIDDEN), Failure::BadKey);
+        assert_eq!(
+            Failure::of_status(StatusCode::PAYLOAD_TOO_LARGE),
+            Failure::ImageTooLarge
+        );
+        assert_eq!(
+            Failure::of_status(StatusCode::BAD_GATEWAY),
+            Failure::Unavailable
+        );
+        assert_eq!(Failure::of_status(StatusCode::NOT_FOUND), Failure::Other);
+    }
+
+    #[tokio::test]
+    async fn test_user_message_for_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429))
+            .mount(&server)
+            .await;
+
+        let md = crate::MoonDream::remote("token").with_endpoint(server.uri());
+        let error = md.caption("img", None).await.unwrap_err();
+        assert_eq!(
+            error.to_user_message(Locale::En),
+            "Too many requests. Please wait a moment and try again."
+        );
+        assert_eq!(
+            Error::Cancelled.to_user_message(Locale::Fr),
+            "La requête a été annulée."
+        );
+    }
+}
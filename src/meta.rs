@@ -0,0 +1,204 @@
+//! Response envelope carrying timing metadata.
+//!
+//! Every operation has a `*_with_meta` variant (for example
+//! [`MoonDream::query_with_meta`](crate::MoonDream::query_with_meta)) returning
+//! an [`ApiResponse`] that pairs the parsed response with a [`ResponseMeta`].
+//! Responses fetched over the network also describe their connection with a
+//! [`ConnectionInfo`], and carry their HTTP status and rate-limit headers in
+//! [`ResponseHeaders`]. The raw body is kept for debugging.
+
+use crate::provenance::Provenance;
+use crate::{Error, ResultFlags};
+use bytes::Bytes;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Version};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::time::Duration;
+use web_time::SystemTime;
+
+/// Timing metadata recorded by the client for a single call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// Wall-clock time at which the request was started.
+    pub started_at: SystemTime,
+    /// Wall-clock time at which the response was fully received.
+    pub completed_at: SystemTime,
+    /// Time elapsed between sending the request and decoding the response.
+    pub latency: Duration,
+    /// Full URL of the endpoint that served the response.
+    pub endpoint: String,
+    /// Number of [failover](crate::failover) endpoints that failed before
+    /// `endpoint` served the response, during the last attempt.
+    pub failovers: u32,
+    /// Number of the attempt that produced the response, starting at 1.
+    /// Responses served from the cache report 0.
+    pub attempt: u32,
+    /// Time spent waiting between attempts, before the one that produced
+    /// the response.
+    pub backoff: Duration,
+    /// `true` if the response was served from the [`Cache`](crate::Cache).
+    pub cached: bool,
+    /// Identifier generated by the client for the call and sent as the
+    /// `X-Client-Request-Id` header of every attempt; `None` for cached
+    /// responses.
+    pub client_request_id: Option<String>,
+    /// Connection used for the response; `None` for cached responses and on
+    /// WebAssembly, where the browser does not expose it.
+    pub connection: Option<ConnectionInfo>,
+    /// Flags set while decoding, such as [`ResultFlags::LENIENT_PARSE`].
+    pub flags: ResultFlags,
+    /// HTTP status of the response; `None` for cached responses.
+    pub status: Option<StatusCode>,
+    /// Selected response headers; empty for cached responses.
+    pub headers: ResponseHeaders,
+    /// Body of the response, as received.
+    pub raw_body: Bytes,
+    /// [`Provenance`] of the images sent, in order; empty unless the client
+    /// records it with [`MoonDream::with_provenance`](crate::MoonDream::with_provenance).
+    pub provenance: Vec<Provenance>,
+}
+
+impl ResponseMeta {
+    /// Return the raw body as text, for logs.
+    pub fn raw_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.raw_body)
+    }
+
+    /// Parse the raw body as JSON, including the fields the client does not
+    /// know about.
+    pub fn raw_json(&self) -> Result<Value, Error> {
+        Ok(serde_json::from_slice(&self.raw_body)?)
+    }
+}
+
+/// Response headers kept in the [`ResponseMeta`].
+///
+/// Both the `X-RateLimit-*` headers and the unprefixed `RateLimit-*` headers
+/// of the IETF draft are read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseHeaders {
+    /// Identifier assigned to the request by the server (`X-Request-Id`).
+    pub request_id: Option<String>,
+    /// Requests allowed in the current rate-limit window.
+    pub rate_limit: Option<u64>,
+    /// Requests left in the current rate-limit window.
+    pub rate_limit_remaining: Option<u64>,
+    /// Seconds until the rate-limit window resets.
+    pub rate_limit_reset: Option<u64>,
+}
+
+impl ResponseHeaders {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let text = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                let value = headers.get(*name)?.to_str().ok()?.trim();
+                (!value.is_empty()).then(|| value.to_string())
+            })
+        };
+        let number = |name: &str| {
+            text(&[&format!("x-ratelimit-{name}"), &format!("ratelimit-{name}")])
+                .and_then(|value| value.parse().ok())
+        };
+        Self {
+            request_id: text(&["x-request-id", "request-id"]),
+            rate_limit: number("limit"),
+            rate_limit_remaining: number("remaining"),
+            rate_limit_reset: number("reset"),
+        }
+    }
+}
+
+/// Transport details of the connection that served a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// HTTP version of the response, for example `HTTP/1.1` or `HTTP/2.0`.
+    pub version: Version,
+    /// Address of the server or proxy the client connected to, when known.
+    pub remote_addr: Option<SocketAddr>,
+    /// TLS details of the connection; `None` for plain HTTP, and for
+    /// clients set with [`MoonDream::with_client`](crate::MoonDream::with_client)
+    /// that were not built with `tls_info(true)`.
+    pub tls: Option<TlsDetails>,
+}
+
+/// TLS details of a connection, see [`ConnectionInfo::tls`].
+///
+/// The negotiated protocol version and cipher suite are not exposed by the
+/// HTTP stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsDetails {
+    /// DER-encoded certificate presented by the server, for example to pin
+    /// it or check its expiry.
+    pub peer_certificate: Option<Bytes>,
+}
+
+/// A parsed response together with its [`ResponseMeta`].
+///
+/// Dereferences to the inner response, so fields can be read directly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiResponse<T> {
+    /// The parsed response.
+    pub data: T,
+    /// Metadata recorded while performing the call.
+    pub meta: ResponseMeta,
+}
+
+impl<T> ApiResponse<T> {
+    /// Discard the metadata and return the parsed response.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+
+    /// Attach the provenance of the images of the call to the metadata.
+    pub(crate) fn with_provenance(
+        mut self,
+        provenance: impl IntoIterator<Item = Provenance>,
+    ) -> Self {
+        self.meta.provenance.extend(provenance);
+        self
+    }
+
+    /// Transform the parsed response, keeping the metadata.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ApiResponse<U> {
+        ApiResponse {
+            data: f(self.data),
+            meta: self.meta,
+        }
+    }
+}
+
+impl<T> Deref for ApiResponse<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_response_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("req-1"));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("42"));
+        headers.insert("ratelimit-reset", HeaderValue::from_static("30"));
+        headers.insert("x-ratelimit-limit", HeaderValue::from_static("many"));
+
+        assert_eq!(
+            ResponseHeaders::from_headers(&headers),
+            ResponseHeaders {
+                request_id: Some("req-1".to_string()),
+                rate_limit: None,
+                rate_limit_remaining: Some(42),
+                rate_limit_reset: Some(30),
+            }
+        );
+    }
+}
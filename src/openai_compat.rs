@@ -0,0 +1,312 @@
+//! Adapter for the OpenAI chat-completions format.
+//!
+//! [`MoonDream::chat`] answers a chat-completions conversation: the last
+//! image sent by the user is analyzed with `/query` using the text of the
+//! last user message, or captioned when that message has no text. The
+//! request and response types serialize to the OpenAI wire format, so a
+//! service can accept and return OpenAI payloads unchanged.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::openai_compat::ChatMessage;
+//!
+//! # async fn run() -> Result<(), moondream::Error> {
+//! let md = MoonDream::remote("token");
+//! let response = md
+//!     .chat(&[ChatMessage::user_with_image(
+//!         "How many people are there?",
+//!         "https://example.com/crowd.jpg",
+//!     )])
+//!     .await?;
+//! println!("{}", response.choices[0].message.content.text());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::rt::SystemTime;
+use crate::{Error, MoonDream};
+use serde::{Deserialize, Serialize};
+
+/// Model name reported in [`ChatResponse::model`].
+const MODEL: &str = "moondream";
+
+/// A chat-completions request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatRequest {
+    /// Requested model; ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Conversation so far.
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Author of a [`ChatMessage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Instructions for the model.
+    System,
+    /// The end user.
+    User,
+    /// The model.
+    Assistant,
+}
+
+/// One message of a conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// Author of the message.
+    pub role: Role,
+    /// Text and images of the message.
+    pub content: MessageContent,
+}
+
+impl ChatMessage {
+    /// A user message with text only.
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: MessageContent::Text(text.into()),
+        }
+    }
+
+    /// A user message with `text` and one image, given as a URL or a base64
+    /// `data:` URI.
+    pub fn user_with_image(text: impl Into<String>, image_url: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: MessageContent::Parts(vec![
+                ContentPart::Text { text: text.into() },
+                ContentPart::ImageUrl {
+                    image_url: ImageUrl {
+                        url: image_url.into(),
+                        detail: None,
+                    },
+                },
+            ]),
+        }
+    }
+
+    /// An assistant message.
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: MessageContent::Text(text.into()),
+        }
+    }
+}
+
+/// Content of a [`ChatMessage`]: plain text or a list of parts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    /// Text only.
+    Text(String),
+    /// Text and image parts.
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// Text of the message, with the text parts joined by newlines.
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// URL of the last image part, if any.
+    pub fn image_url(&self) -> Option<&str> {
+        match self {
+            MessageContent::Text(_) => None,
+            MessageContent::Parts(parts) => parts.iter().rev().find_map(|part| match part {
+                ContentPart::ImageUrl { image_url } => Some(image_url.url.as_str()),
+                ContentPart::Text { .. } => None,
+            }),
+        }
+    }
+}
+
+/// A part of a multi-part [`MessageContent`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// A text part.
+    Text {
+        /// The text.
+        text: String,
+    },
+    /// An image part.
+    ImageUrl {
+        /// The image.
+        image_url: ImageUrl,
+    },
+}
+
+/// Image referenced by a [`ContentPart::ImageUrl`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// Remote URL or base64 `data:` URI.
+    pub url: String,
+    /// Requested detail level; ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// A chat-completions response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatResponse {
+    /// Identifier of the completion.
+    pub id: String,
+    /// Always `chat.completion`.
+    pub object: String,
+    /// Creation time, in seconds since the Unix epoch.
+    pub created: u64,
+    /// Always `moondream`.
+    pub model: String,
+    /// The single generated answer.
+    pub choices: Vec<ChatChoice>,
+}
+
+/// A generated message of a [`ChatResponse`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatChoice {
+    /// Position of the choice, always 0.
+    pub index: u32,
+    /// The assistant message.
+    pub message: ChatMessage,
+    /// Always `stop`.
+    pub finish_reason: String,
+}
+
+impl MoonDream {
+    /// Answer a chat-completions conversation, see the
+    /// [module documentation](crate::openai_compat).
+    ///
+    /// Fails with [`Error::InvalidImage`] if no user message contains an
+    /// image.
+    pub async fn chat(&self, messages: &[ChatMessage]) -> Result<ChatResponse, Error> {
+        let user = || messages.iter().filter(|message| message.role == Role::User);
+        let image = user()
+            .rev()
+            .find_map(|message| message.content.image_url())
+            .ok_or_else(|| Error::InvalidImage("no image in the chat messages".to_string()))?
+            .to_string();
+        let prompt = user()
+            .next_back()
+            .map(|message| message.content.text())
+            .unwrap_or_default();
+
+        let (request_id, answer) = if prompt.trim().is_empty() {
+            let response = self.caption(image, None).await?;
+            (response.request_id, response.caption)
+        } else {
+            let response = self.query(image, prompt).await?;
+            (response.request_id, response.answer)
+        };
+
+        let created = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Ok(ChatResponse {
+            id: format!(
+                "chatcmpl-{}",
+                request_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+            ),
+            object: "chat.completion".to_string(),
+            created,
+            model: MODEL.to_string(),
+            choices: vec![ChatChoice {
+                index: 0,
+                message: ChatMessage::assistant(answer),
+                finish_reason: "stop".to_string(),
+            }],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_request_wire_format() {
+        let request: ChatRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/a.png", "detail": "low"}},
+                ]},
+            ],
+        }))
+        .unwrap();
+
+        let user = &request.messages[1];
+        assert_eq!(user.content.text(), "What is this?");
+        assert_eq!(user.content.image_url(), Some("https://example.com/a.png"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_maps_to_query_and_caption() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_partial_json(serde_json::json!({
+                "image_url": "https://example.com/a.png",
+                "question": "What color is it?",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "q1",
+                "answer": "Red",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "c1",
+                "caption": "A red car",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+
+        let response = md
+            .chat(&[
+                ChatMessage::user_with_image("", "https://example.com/a.png"),
+                ChatMessage::assistant("A car"),
+                ChatMessage::user("What color is it?"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(response.id, "chatcmpl-q1");
+        assert_eq!(response.choices[0].message.content.text(), "Red");
+
+        let response = md
+            .chat(&[ChatMessage::user_with_image(
+                "",
+                "https://example.com/a.png",
+            )])
+            .await
+            .unwrap();
+        assert_eq!(response.choices[0].message.role, Role::Assistant);
+        assert_eq!(response.choices[0].message.content.text(), "A red car");
+
+        let err = md.chat(&[ChatMessage::user("Hi")]).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidImage(_)));
+    }
+}
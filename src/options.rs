@@ -0,0 +1,74 @@
+//! Per-call overrides of the client settings.
+
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use web_time::Instant;
+
+/// Timeout, deadline and cancellation applied to the calls of a client.
+///
+/// Options apply to every call made through the client they are set on, so
+/// a per-call override is a cheap clone of the client:
+///
+/// ```
+/// use moondream::{MoonDream, RequestOptions};
+/// use std::time::Duration;
+/// use tokio_util::sync::CancellationToken;
+///
+/// let md = MoonDream::remote("token");
+/// let shutdown = CancellationToken::new();
+///
+/// let slow = md.clone().with_options(
+///     RequestOptions::new()
+///         .with_timeout(Duration::from_secs(30))
+///         .with_cancellation(shutdown.child_token()),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl RequestOptions {
+    /// Create options that keep the client settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timeout of each attempt, replacing the client timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Instant after which no attempt is started and pending attempts fail
+    /// with [`Error::DeadlineExceeded`](crate::Error::DeadlineExceeded),
+    /// retries included.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Abort pending calls with [`Error::Cancelled`](crate::Error::Cancelled)
+    /// once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Timeout override, if set.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Deadline, if set.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Cancellation token, if set.
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+}
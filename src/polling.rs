@@ -0,0 +1,247 @@
+//! Non-async access to the client, for frame loops.
+//!
+//! Game engines and immediate-mode GUIs run a frame loop that cannot await
+//! futures. A [`PollingHandle`] runs the calls on a background thread with
+//! its own runtime: [`PollingHandle::submit`] starts a call and returns a
+//! [`Ticket`] at once, and [`PollingHandle::poll`] returns its result once
+//! it is ready, without ever blocking.
+//!
+//! ```no_run
+//! use moondream::polling::{Operation, Output, PollingHandle};
+//! use moondream::MoonDream;
+//!
+//! # fn run(frames: Vec<String>) -> Result<(), moondream::Error> {
+//! let handle = PollingHandle::new(MoonDream::remote("token"))?;
+//! let mut pending = None;
+//! for frame in frames {
+//!     // Once per frame:
+//!     if pending.is_none() {
+//!         pending = Some(handle.submit(Operation::detect(frame, "enemy")));
+//!     }
+//!     if let Some(ticket) = &pending
+//!         && let Some(result) = handle.poll(ticket)
+//!     {
+//!         if let Ok(Output::Detect(detected)) = result {
+//!             // draw detected.objects
+//!         }
+//!         pending = None;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The background thread stops when the last clone of the handle is
+//! dropped, aborting the calls still running.
+
+use crate::{
+    CaptionLength, CaptionResponse, DetectResponse, Error, ImageInput, MoonDream, PointsResponse,
+    QueryResponse,
+};
+use futures::channel::oneshot;
+use futures::future::AbortHandle;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A call to run through a [`PollingHandle`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// See [`MoonDream::caption`].
+    Caption(ImageInput, Option<CaptionLength>),
+    /// See [`MoonDream::query`].
+    Query(ImageInput, String),
+    /// See [`MoonDream::detect`].
+    Detect(ImageInput, String),
+    /// See [`MoonDream::points`].
+    Points(ImageInput, String),
+}
+
+impl Operation {
+    /// Caption `image`.
+    pub fn caption(image: impl Into<ImageInput>, length: Option<CaptionLength>) -> Self {
+        Operation::Caption(image.into(), length)
+    }
+
+    /// Ask `question` about `image`.
+    pub fn query(image: impl Into<ImageInput>, question: impl Into<String>) -> Self {
+        Operation::Query(image.into(), question.into())
+    }
+
+    /// Detect `object` in `image`.
+    pub fn detect(image: impl Into<ImageInput>, object: impl Into<String>) -> Self {
+        Operation::Detect(image.into(), object.into())
+    }
+
+    /// Point at `object` in `image`.
+    pub fn points(image: impl Into<ImageInput>, object: impl Into<String>) -> Self {
+        Operation::Points(image.into(), object.into())
+    }
+}
+
+/// Response of an [`Operation`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// Response of [`Operation::Caption`].
+    Caption(CaptionResponse),
+    /// Response of [`Operation::Query`].
+    Query(QueryResponse),
+    /// Response of [`Operation::Detect`].
+    Detect(DetectResponse),
+    /// Response of [`Operation::Points`].
+    Points(PointsResponse),
+}
+
+/// Identifies a call submitted to a [`PollingHandle`].
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Ticket(u64);
+
+#[derive(Debug)]
+enum Slot {
+    Pending(AbortHandle),
+    Ready(Result<Output, Error>),
+}
+
+#[derive(Debug)]
+struct Inner {
+    md: MoonDream,
+    runtime: tokio::runtime::Handle,
+    slots: Mutex<HashMap<u64, Slot>>,
+    next: AtomicU64,
+    // Stops the runtime thread when dropped.
+    _shutdown: oneshot::Sender<()>,
+}
+
+/// Runs calls in the background and hands out their results without
+/// blocking, see the [module documentation](crate::polling).
+#[derive(Debug, Clone)]
+pub struct PollingHandle(Arc<Inner>);
+
+impl PollingHandle {
+    /// Start the background thread running the calls of `md`.
+    pub fn new(md: MoonDream) -> Result<Self, Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let handle = runtime.handle().clone();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        std::thread::Builder::new()
+            .name("moondream-polling".into())
+            .spawn(move || {
+                let _ = runtime.block_on(stopped);
+            })?;
+        Ok(Self(Arc::new(Inner {
+            md,
+            runtime: handle,
+            slots: Mutex::default(),
+            next: AtomicU64::new(0),
+            _shutdown: shutdown,
+        })))
+    }
+
+    /// Start `operation` and return its ticket.
+    pub fn submit(&self, operation: Operation) -> Ticket {
+        let id = self.0.next.fetch_add(1, Ordering::Relaxed);
+        let md = self.0.md.clone();
+        let inner = Arc::downgrade(&self.0);
+        let (call, abort) = futures::future::abortable(async move {
+            let result = match operation {
+                Operation::Caption(image, length) => {
+                    md.caption(image, length).await.map(Output::Caption)
+                }
+                Operation::Query(image, question) => {
+                    md.query(image, question).await.map(Output::Query)
+                }
+                Operation::Detect(image, object) => {
+                    md.detect(image, object).await.map(Output::Detect)
+                }
+                Operation::Points(image, object) => {
+                    md.points(image, object).await.map(Output::Points)
+                }
+            };
+            if let Some(inner) = inner.upgrade()
+                && let Some(slot) = inner.lock().get_mut(&id)
+            {
+                *slot = Slot::Ready(result);
+            }
+        });
+        self.0.lock().insert(id, Slot::Pending(abort));
+        self.0.runtime.spawn(call);
+        Ticket(id)
+    }
+
+    /// Return the result of the call of `ticket` if it completed, handing
+    /// it out only once.
+    ///
+    /// Returns `None` while the call runs, and after its result was
+    /// returned or the call was cancelled.
+    pub fn poll(&self, ticket: &Ticket) -> Option<Result<Output, Error>> {
+        let mut slots = self.0.lock();
+        match slots.get(&ticket.0)? {
+            Slot::Pending(_) => None,
+            Slot::Ready(_) => match slots.remove(&ticket.0) {
+                Some(Slot::Ready(result)) => Some(result),
+                _ => None,
+            },
+        }
+    }
+
+    /// Return `true` while the call of `ticket` runs.
+    pub fn is_pending(&self, ticket: &Ticket) -> bool {
+        matches!(self.0.lock().get(&ticket.0), Some(Slot::Pending(_)))
+    }
+
+    /// Abort the call of `ticket` and discard its result.
+    pub fn cancel(&self, ticket: Ticket) {
+        if let Some(Slot::Pending(abort)) = self.0.lock().remove(&ticket.0) {
+            abort.abort();
+        }
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Slot>> {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_submit_and_poll() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "caption": "a level" }))
+                    .set_delay(Duration::from_millis(50)),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let handle = PollingHandle::new(md).unwrap();
+        let ticket = handle.submit(Operation::caption("img", None));
+        assert!(handle.poll(&ticket).is_none());
+        assert!(handle.is_pending(&ticket));
+
+        let result = loop {
+            if let Some(result) = handle.poll(&ticket) {
+                break result;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        };
+        assert!(matches!(result, Ok(Output::Caption(c)) if c.caption == "a level"));
+        assert!(handle.poll(&ticket).is_none());
+
+        let ticket = handle.submit(Operation::caption("img", None));
+        handle.cancel(ticket);
+        assert!(handle.0.lock().is_empty());
+    }
+}
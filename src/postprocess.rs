@@ -0,0 +1,733 @@
+//! Post-processing of detections: suppression, merging and tracking.
+//!
+//! [`nms`] drops boxes overlapping a more confident one, [`merge_overlapping`]
+//! fuses overlapping boxes into the box enclosing them, and [`Tracker`]
+//! gives objects detected on successive video frames persistent identities
+//! by matching their boxes on intersection over union (IoU). For continuous
+//! monitoring, [`Deduplicator`] turns repeated detections into
+//! [`Change`]s, reporting objects once when they appear and once when they
+//! disappear, and [`PresenceTracker`] follows whether each class of objects
+//! is present, ignoring detections flickering for less than a debounce
+//! delay.
+//!
+//! ```
+//! use moondream::DetectionObject;
+//! use moondream::postprocess::{Tracker, nms};
+//!
+//! # fn run(frames: Vec<Vec<DetectionObject>>) {
+//! let mut tracker = Tracker::new();
+//! for objects in frames {
+//!     for tracked in tracker.update(&nms(objects, 0.5)) {
+//!         println!("object #{} at {:?}", tracked.id, tracked.object);
+//!     }
+//! }
+//! # }
+//! ```
+
+use crate::DetectionObject;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::Duration;
+
+impl DetectionObject {
+    /// Area of the box, in normalized units.
+    pub fn area(&self) -> f64 {
+        (self.x_max - self.x_min).max(0.0) * (self.y_max - self.y_min).max(0.0)
+    }
+
+    /// Intersection over union of two boxes, from 0 (disjoint) to 1
+    /// (identical).
+    pub fn iou(&self, other: &DetectionObject) -> f64 {
+        let width = self.x_max.min(other.x_max) - self.x_min.max(other.x_min);
+        let height = self.y_max.min(other.y_max) - self.y_min.max(other.y_min);
+        let intersection = width.max(0.0) * height.max(0.0);
+        let union = self.area() + other.area() - intersection;
+        if union <= 0.0 {
+            0.0
+        } else {
+            intersection / union
+        }
+    }
+}
+
+/// Non-maximum suppression: keep the most confident box of each group of
+/// boxes overlapping by more than `iou_threshold`.
+///
+/// Boxes without confidence rank after scored ones, in their original
+/// order.
+pub fn nms(mut objects: Vec<DetectionObject>, iou_threshold: f64) -> Vec<DetectionObject> {
+    objects.sort_by(|a, b| by_confidence(b, a));
+    let mut kept: Vec<DetectionObject> = Vec::with_capacity(objects.len());
+    for object in objects {
+        if kept.iter().all(|k| k.iou(&object) <= iou_threshold) {
+            kept.push(object);
+        }
+    }
+    kept
+}
+
+/// Replace each group of boxes overlapping by more than `iou_threshold`,
+/// directly or through other boxes of the group, with the box enclosing
+/// them, keeping the highest confidence.
+pub fn merge_overlapping(
+    objects: Vec<DetectionObject>,
+    iou_threshold: f64,
+) -> Vec<DetectionObject> {
+    let mut merged: Vec<DetectionObject> = Vec::with_capacity(objects.len());
+    for mut object in objects {
+        // Absorb every merged box the new one overlaps, repeating since the
+        // enclosing box grows.
+        while let Some(index) = merged.iter().position(|m| m.iou(&object) > iou_threshold) {
+            object = enclose(&merged.swap_remove(index), &object);
+        }
+        merged.push(object);
+    }
+    merged
+}
+
+fn enclose(a: &DetectionObject, b: &DetectionObject) -> DetectionObject {
+    DetectionObject {
+        x_min: a.x_min.min(b.x_min),
+        y_min: a.y_min.min(b.y_min),
+        x_max: a.x_max.max(b.x_max),
+        y_max: a.y_max.max(b.y_max),
+        confidence: match (a.confidence, b.confidence) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        },
+    }
+}
+
+fn by_confidence(a: &DetectionObject, b: &DetectionObject) -> Ordering {
+    match (a.confidence, b.confidence) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// An object with the identity assigned by a [`Tracker`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackedObject {
+    /// Identity of the object, stable across frames.
+    pub id: u64,
+    /// Box of the object on the current frame.
+    pub object: DetectionObject,
+    /// Number of frames the object was detected on, including this one.
+    pub hits: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Track {
+    id: u64,
+    object: DetectionObject,
+    hits: u32,
+    missed: u32,
+}
+
+/// Assigns persistent identities to objects detected on successive frames.
+///
+/// Each detection is matched to the track whose last box overlaps it most,
+/// above the IoU threshold (0.3 by default). Unmatched detections start new
+/// tracks; tracks unmatched for more than `max_missed` frames (5 by default)
+/// are forgotten, so objects hidden for a few frames keep their identity.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    iou_threshold: f64,
+    max_missed: u32,
+    next_id: u64,
+    tracks: Vec<Track>,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracker {
+    /// Create a tracker with an IoU threshold of 0.3, forgetting objects
+    /// after 5 frames.
+    pub fn new() -> Self {
+        Self {
+            iou_threshold: 0.3,
+            max_missed: 5,
+            next_id: 1,
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Set the minimum IoU between a detection and the last box of a track.
+    pub fn with_iou_threshold(mut self, iou_threshold: f64) -> Self {
+        self.iou_threshold = iou_threshold;
+        self
+    }
+
+    /// Forget tracks unmatched for more than `frames` frames.
+    pub fn with_max_missed(mut self, frames: u32) -> Self {
+        self.max_missed = frames;
+        self
+    }
+
+    /// Match the detections of the next frame, returning them in the same
+    /// order with their identities.
+    pub fn update(&mut self, objects: &[DetectionObject]) -> Vec<TrackedObject> {
+        let known: Vec<_> = self
+            .tracks
+            .iter()
+            .map(|track| Some(&track.object))
+            .collect();
+        let assigned = match_boxes(&known, objects, self.iou_threshold);
+        let mut matched = vec![false; self.tracks.len()];
+        for index in assigned.iter().flatten() {
+            matched[*index] = true;
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(&matched) {
+            if !matched {
+                track.missed += 1;
+            }
+        }
+        let result = objects
+            .iter()
+            .zip(assigned)
+            .map(|(object, track)| {
+                let track = match track {
+                    Some(index) => {
+                        let track = &mut self.tracks[index];
+                        track.object = object.clone();
+                        track.hits += 1;
+                        track.missed = 0;
+                        track.clone()
+                    }
+                    None => {
+                        let track = Track {
+                            id: self.next_id,
+                            object: object.clone(),
+                            hits: 1,
+                            missed: 0,
+                        };
+                        self.next_id += 1;
+                        self.tracks.push(track.clone());
+                        track
+                    }
+                };
+                TrackedObject {
+                    id: track.id,
+                    object: track.object,
+                    hits: track.hits,
+                }
+            })
+            .collect();
+        let max_missed = self.max_missed;
+        self.tracks.retain(|track| track.missed <= max_missed);
+        result
+    }
+
+    /// Number of objects currently tracked, including those missed on
+    /// recent frames.
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Return `true` if no object is tracked.
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+}
+
+/// A change reported by a [`Deduplicator`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Change {
+    /// An object not seen within the time window was detected.
+    Appeared {
+        /// Identity of the object, shared by its `Disappeared` change.
+        id: u64,
+        /// Label the object was detected with.
+        label: String,
+        /// Box of the object when it appeared.
+        object: DetectionObject,
+        /// Timestamp of the detection.
+        timestamp: Duration,
+    },
+    /// An object was not detected for longer than the time window.
+    Disappeared {
+        /// Identity of the object.
+        id: u64,
+        /// Label the object was detected with.
+        label: String,
+        /// Box of the object when it was last seen.
+        object: DetectionObject,
+        /// Timestamp of the last detection of the object.
+        timestamp: Duration,
+    },
+}
+
+impl Change {
+    /// Identity of the object that changed.
+    pub fn id(&self) -> u64 {
+        match self {
+            Change::Appeared { id, .. } | Change::Disappeared { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Seen {
+    id: u64,
+    label: String,
+    object: DetectionObject,
+    last_seen: Duration,
+}
+
+/// Suppresses repeated detections, reporting only objects appearing and
+/// disappearing.
+///
+/// A detection matching an object of the same label seen within the time
+/// window, with an IoU of at least the spatial tolerance (0.5 by default),
+/// is a repeat and is dropped. Objects not detected for longer than the
+/// window are reported as gone. Timestamps are measured from any fixed
+/// origin, such as the start of a video or of the monitoring loop, and must
+/// not go backwards.
+///
+/// ```
+/// use moondream::DetectionObject;
+/// use moondream::postprocess::{Change, Deduplicator};
+/// use std::time::Duration;
+///
+/// # fn run(frames: Vec<(Duration, Vec<DetectionObject>)>) {
+/// let mut alerts = Deduplicator::new(Duration::from_secs(10));
+/// for (timestamp, people) in frames {
+///     for change in alerts.update("person", &people, timestamp) {
+///         if let Change::Appeared { id, .. } = change {
+///             println!("person #{id} entered at {timestamp:?}");
+///         }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    window: Duration,
+    tolerance: f64,
+    next_id: u64,
+    seen: Vec<Seen>,
+}
+
+impl Deduplicator {
+    /// Consider detections within `window` of each other as repeats.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            tolerance: 0.5,
+            next_id: 1,
+            seen: Vec::new(),
+        }
+    }
+
+    /// Set the minimum IoU between a detection and the last box of an
+    /// object for the detection to be a repeat.
+    pub fn with_tolerance(mut self, iou_threshold: f64) -> Self {
+        self.tolerance = iou_threshold;
+        self
+    }
+
+    /// Process the objects detected with `label` at `timestamp`, returning
+    /// the objects that appeared, then those that disappeared.
+    ///
+    /// Objects of every label are checked for disappearance, so calling
+    /// this with no objects only reports the objects that are gone.
+    pub fn update(
+        &mut self,
+        label: &str,
+        objects: &[DetectionObject],
+        timestamp: Duration,
+    ) -> Vec<Change> {
+        let known: Vec<_> = self
+            .seen
+            .iter()
+            .map(|seen| (seen.label == label).then_some(&seen.object))
+            .collect();
+        let assigned = match_boxes(&known, objects, self.tolerance);
+
+        let mut changes = Vec::new();
+        for (object, index) in objects.iter().zip(assigned) {
+            match index {
+                Some(index) => {
+                    let seen = &mut self.seen[index];
+                    seen.object = object.clone();
+                    seen.last_seen = timestamp;
+                }
+                None => {
+                    self.seen.push(Seen {
+                        id: self.next_id,
+                        label: label.to_string(),
+                        object: object.clone(),
+                        last_seen: timestamp,
+                    });
+                    changes.push(Change::Appeared {
+                        id: self.next_id,
+                        label: label.to_string(),
+                        object: object.clone(),
+                        timestamp,
+                    });
+                    self.next_id += 1;
+                }
+            }
+        }
+
+        let window = self.window;
+        let (gone, kept): (Vec<Seen>, Vec<Seen>) = std::mem::take(&mut self.seen)
+            .into_iter()
+            .partition(|seen| timestamp.saturating_sub(seen.last_seen) > window);
+        self.seen = kept;
+        changes.extend(gone.into_iter().map(|seen| Change::Disappeared {
+            id: seen.id,
+            label: seen.label,
+            object: seen.object,
+            timestamp: seen.last_seen,
+        }));
+        changes
+    }
+
+    /// Number of objects currently considered present.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Return `true` if no object is considered present.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+/// Presence of a class of objects, as followed by a [`PresenceTracker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    /// Not detected, or not for long enough.
+    Absent,
+    /// Detected, but not for the enter delay yet.
+    Entering,
+    /// Detected for at least the enter delay.
+    Present,
+    /// Present, but missing on the last frames for less than the leave
+    /// delay.
+    Leaving,
+}
+
+/// A transition reported by a [`PresenceTracker`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PresenceEvent {
+    /// Objects of the class have been detected for the enter delay.
+    Entered {
+        /// Label of the class.
+        label: String,
+        /// Number of objects on the frame confirming the presence.
+        count: usize,
+        /// Timestamp of the first detection.
+        timestamp: Duration,
+    },
+    /// No object of the class has been detected for the leave delay.
+    Left {
+        /// Label of the class.
+        label: String,
+        /// Timestamp of the last detection.
+        timestamp: Duration,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClassState {
+    presence: Presence,
+    /// First detection of the current run of detections.
+    since: Duration,
+    last_seen: Duration,
+}
+
+/// Follows the presence of each class of objects across frames, the core
+/// of occupancy monitoring.
+///
+/// A class enters once its objects have been detected on every frame for
+/// the enter delay, and leaves once none has been detected for the leave
+/// delay, so a missed detection or a spurious box does not produce a pair
+/// of events. Both delays are set by [`PresenceTracker::new`]. Timestamps
+/// are measured as for the [`Deduplicator`].
+///
+/// ```
+/// use moondream::DetectionObject;
+/// use moondream::postprocess::{PresenceEvent, PresenceTracker};
+/// use std::time::Duration;
+///
+/// # fn run(frames: Vec<(Duration, Vec<DetectionObject>)>) {
+/// let mut room = PresenceTracker::new(Duration::from_secs(2));
+/// for (timestamp, people) in frames {
+///     if let Some(PresenceEvent::Left { .. }) = room.update("person", &people, timestamp) {
+///         println!("room empty since {timestamp:?}");
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PresenceTracker {
+    enter_after: Duration,
+    leave_after: Duration,
+    classes: HashMap<String, ClassState>,
+}
+
+impl PresenceTracker {
+    /// Debounce both entering and leaving by `debounce`.
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            enter_after: debounce,
+            leave_after: debounce,
+            classes: HashMap::new(),
+        }
+    }
+
+    /// Set how long objects must be detected before their class enters.
+    pub fn with_enter_after(mut self, delay: Duration) -> Self {
+        self.enter_after = delay;
+        self
+    }
+
+    /// Set how long objects must be missing before their class leaves.
+    pub fn with_leave_after(mut self, delay: Duration) -> Self {
+        self.leave_after = delay;
+        self
+    }
+
+    /// Process the objects detected with `label` at `timestamp`, returning
+    /// the transition of the class, if any.
+    ///
+    /// Only the class of `label` is updated: other classes keep their state
+    /// until they are updated themselves.
+    pub fn update(
+        &mut self,
+        label: &str,
+        objects: &[DetectionObject],
+        timestamp: Duration,
+    ) -> Option<PresenceEvent> {
+        let detected = !objects.is_empty();
+        let state = self.classes.entry(label.to_string()).or_insert(ClassState {
+            presence: Presence::Absent,
+            since: timestamp,
+            last_seen: timestamp,
+        });
+
+        if detected {
+            if matches!(state.presence, Presence::Absent) {
+                state.presence = Presence::Entering;
+                state.since = timestamp;
+            }
+            state.last_seen = timestamp;
+        }
+
+        match state.presence {
+            Presence::Entering if !detected => state.presence = Presence::Absent,
+            Presence::Entering if timestamp.saturating_sub(state.since) >= self.enter_after => {
+                state.presence = Presence::Present;
+                return Some(PresenceEvent::Entered {
+                    label: label.to_string(),
+                    count: objects.len(),
+                    timestamp: state.since,
+                });
+            }
+            Presence::Present | Presence::Leaving if detected => {
+                state.presence = Presence::Present;
+            }
+            Presence::Present | Presence::Leaving => {
+                if timestamp.saturating_sub(state.last_seen) >= self.leave_after {
+                    state.presence = Presence::Absent;
+                    return Some(PresenceEvent::Left {
+                        label: label.to_string(),
+                        timestamp: state.last_seen,
+                    });
+                }
+                state.presence = Presence::Leaving;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Presence of the class of `label`.
+    pub fn presence(&self, label: &str) -> Presence {
+        self.classes
+            .get(label)
+            .map_or(Presence::Absent, |state| state.presence)
+    }
+
+    /// Labels of the classes currently present or leaving.
+    pub fn present(&self) -> impl Iterator<Item = &str> {
+        self.classes
+            .iter()
+            .filter(|(_, state)| matches!(state.presence, Presence::Present | Presence::Leaving))
+            .map(|(label, _)| label.as_str())
+    }
+}
+
+/// Greedily match `objects` to the `known` boxes, best IoU first, returning
+/// the index of the known box matched by each object. `None` entries of
+/// `known` never match.
+fn match_boxes(
+    known: &[Option<&DetectionObject>],
+    objects: &[DetectionObject],
+    iou_threshold: f64,
+) -> Vec<Option<usize>> {
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+    for (detection, object) in objects.iter().enumerate() {
+        for (index, candidate) in known.iter().enumerate() {
+            let iou = candidate.map_or(0.0, |candidate| candidate.iou(object));
+            if iou >= iou_threshold && iou > 0.0 {
+                pairs.push((iou, detection, index));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut assigned: Vec<Option<usize>> = vec![None; objects.len()];
+    let mut matched = vec![false; known.len()];
+    for (_, detection, index) in pairs {
+        if assigned[detection].is_none() && !matched[index] {
+            assigned[detection] = Some(index);
+            matched[index] = true;
+        }
+    }
+    assigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x_min: f64, x_max: f64, confidence: Option<f64>) -> DetectionObject {
+        DetectionObject {
+            x_min,
+            y_min: 0.0,
+            x_max,
+            y_max: 0.5,
+            confidence,
+        }
+    }
+
+    #[test]
+    fn test_iou() {
+        let a = bbox(0.0, 0.4, None);
+        assert_eq!(a.iou(&a), 1.0);
+        assert!((a.iou(&bbox(0.2, 0.6, None)) - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(a.iou(&bbox(0.5, 0.9, None)), 0.0);
+    }
+
+    #[test]
+    fn test_nms_keeps_most_confident() {
+        let objects = vec![
+            bbox(0.0, 0.4, Some(0.6)),
+            bbox(0.02, 0.42, Some(0.9)),
+            bbox(0.6, 0.9, Some(0.5)),
+        ];
+        assert_eq!(
+            nms(objects, 0.5),
+            [bbox(0.02, 0.42, Some(0.9)), bbox(0.6, 0.9, Some(0.5))]
+        );
+    }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let objects = vec![
+            bbox(0.0, 0.4, Some(0.6)),
+            bbox(0.6, 0.9, None),
+            bbox(0.1, 0.5, Some(0.8)),
+        ];
+        assert_eq!(
+            merge_overlapping(objects, 0.3),
+            [bbox(0.6, 0.9, None), bbox(0.0, 0.5, Some(0.8))]
+        );
+    }
+
+    #[test]
+    fn test_tracker_keeps_identities() {
+        let mut tracker = Tracker::new().with_max_missed(1);
+        let first = tracker.update(&[bbox(0.0, 0.2, None), bbox(0.5, 0.7, None)]);
+        assert_eq!(first.iter().map(|t| t.id).collect::<Vec<_>>(), [1, 2]);
+
+        // Both objects moved a little, and listed in the other order.
+        let second = tracker.update(&[bbox(0.52, 0.72, None), bbox(0.02, 0.22, None)]);
+        assert_eq!(second.iter().map(|t| t.id).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(second[0].hits, 2);
+
+        // Object 1 disappears for two frames and is forgotten.
+        tracker.update(&[bbox(0.54, 0.74, None)]);
+        tracker.update(&[bbox(0.56, 0.76, None)]);
+        assert_eq!(tracker.len(), 1);
+        let back = tracker.update(&[bbox(0.02, 0.22, None)]);
+        assert_eq!(back[0].id, 3);
+    }
+
+    #[test]
+    fn test_deduplicator_reports_changes() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(5));
+        let at = Duration::from_secs;
+
+        let changes = dedup.update("car", &[bbox(0.0, 0.2, None)], at(0));
+        assert_eq!(changes.iter().map(Change::id).collect::<Vec<_>>(), [1]);
+
+        // Repeats, a little shifted, and another label at the same place.
+        assert!(
+            dedup
+                .update("car", &[bbox(0.01, 0.21, None)], at(2))
+                .is_empty()
+        );
+        let changes = dedup.update("person", &[bbox(0.0, 0.2, None)], at(3));
+        assert!(
+            matches!(&changes[..], [Change::Appeared { id: 2, label, .. }] if label == "person")
+        );
+        assert!(
+            dedup
+                .update("car", &[bbox(0.02, 0.22, None)], at(6))
+                .is_empty()
+        );
+
+        // The person is gone, then the car.
+        let changes = dedup.update("car", &[], at(9));
+        assert!(
+            matches!(&changes[..], [Change::Disappeared { id: 2, timestamp, .. }] if *timestamp == at(3))
+        );
+        assert_eq!(dedup.update("car", &[], at(12))[0].id(), 1);
+        assert!(dedup.is_empty());
+    }
+
+    #[test]
+    fn test_presence_debounces_transitions() {
+        let mut room = PresenceTracker::new(Duration::from_secs(2));
+        let at = Duration::from_secs;
+        let person = [bbox(0.1, 0.3, None)];
+
+        // A single spurious detection does not enter.
+        assert_eq!(room.update("person", &person, at(0)), None);
+        assert_eq!(room.presence("person"), Presence::Entering);
+        assert_eq!(room.update("person", &[], at(1)), None);
+        assert_eq!(room.presence("person"), Presence::Absent);
+
+        assert_eq!(room.update("person", &person, at(2)), None);
+        assert_eq!(room.update("person", &person, at(3)), None);
+        let entered = room.update("person", &person, at(4));
+        assert!(
+            matches!(entered, Some(PresenceEvent::Entered { count: 1, timestamp, .. }) if timestamp == at(2))
+        );
+        assert_eq!(room.present().collect::<Vec<_>>(), ["person"]);
+
+        // A missed detection does not leave.
+        assert_eq!(room.update("person", &[], at(5)), None);
+        assert_eq!(room.presence("person"), Presence::Leaving);
+        assert_eq!(room.update("person", &person, at(6)), None);
+        assert_eq!(room.update("person", &[], at(7)), None);
+        let left = room.update("person", &[], at(8));
+        assert!(matches!(left, Some(PresenceEvent::Left { timestamp, .. }) if timestamp == at(6)));
+        assert_eq!(room.presence("person"), Presence::Absent);
+    }
+}
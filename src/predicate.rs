@@ -0,0 +1,441 @@
+//! Filter expressions over results.
+//!
+//! A [`Predicate`] is parsed from a small expression language and evaluated
+//! against JSON records, such as a detected object or a query response:
+//!
+//! ```text
+//! confidence > 0.5 && area < 0.1
+//! answer contains 'yes'
+//! !(x < 0.5) || label == "car"
+//! ```
+//!
+//! Comparisons are `==`, `!=`, `<`, `<=`, `>`, `>=` and `contains`, combined
+//! with `&&`, `||`, `!` and parentheses. Fields are record keys, with dots
+//! for nested keys; bounding boxes also expose `width`, `height` and `area`.
+//! Comparisons on missing fields are false.
+
+use crate::{DetectResponse, DetectionObject, Error, Point, PointsResponse};
+use serde_json::{Value, json};
+use std::fmt;
+
+/// A parsed filter expression.
+///
+/// ```
+/// use moondream::Predicate;
+///
+/// let predicate: Predicate = "confidence > 0.5 && area < 0.1".parse().unwrap();
+/// let object = serde_json::json!({"confidence": 0.9, "area": 0.05});
+/// assert!(predicate.matches(&object));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    expr: Expr,
+    source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Op, Literal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Predicate {
+    /// Parse `source`.
+    ///
+    /// Fails with [`Error::InvalidFilter`] on syntax errors.
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(invalid(format!(
+                "unexpected {:?}",
+                parser.tokens[parser.pos]
+            )));
+        }
+        Ok(Self {
+            expr,
+            source: source.to_string(),
+        })
+    }
+
+    /// Return `true` if `record` satisfies the expression.
+    pub fn matches(&self, record: &Value) -> bool {
+        self.expr.eval(record)
+    }
+
+    /// Keep the objects of `response` satisfying the expression.
+    pub fn filter_detections(&self, mut response: DetectResponse) -> DetectResponse {
+        response
+            .objects
+            .retain(|object| self.matches(&object_record(object)));
+        response
+    }
+
+    /// Keep the points of `response` satisfying the expression. `count` is
+    /// updated to the number of remaining points.
+    pub fn filter_points(&self, mut response: PointsResponse) -> PointsResponse {
+        response
+            .points
+            .retain(|point| self.matches(&point_record(point)));
+        if response.count.is_some() {
+            response.count = Some(response.points.len());
+        }
+        response
+    }
+}
+
+impl std::str::FromStr for Predicate {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Error> {
+        Self::parse(source)
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Record of a detected object, with its derived fields.
+pub fn object_record(object: &DetectionObject) -> Value {
+    let width = object.x_max - object.x_min;
+    let height = object.y_max - object.y_min;
+    json!({
+        "x_min": object.x_min,
+        "y_min": object.y_min,
+        "x_max": object.x_max,
+        "y_max": object.y_max,
+        "confidence": object.confidence,
+        "width": width,
+        "height": height,
+        "area": width * height,
+    })
+}
+
+/// Record of a point.
+pub fn point_record(point: &Point) -> Value {
+    json!({
+        "x": point.x,
+        "y": point.y,
+        "confidence": point.confidence,
+    })
+}
+
+impl Expr {
+    fn eval(&self, record: &Value) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(record) && right.eval(record),
+            Expr::Or(left, right) => left.eval(record) || right.eval(record),
+            Expr::Not(inner) => !inner.eval(record),
+            Expr::Compare(field, op, literal) => {
+                let value = field
+                    .split('.')
+                    .try_fold(record, |value, key| value.get(key));
+                match value {
+                    Some(value) => compare(value, *op, literal),
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    match (value, literal) {
+        (Value::Number(number), Literal::Number(expected)) => {
+            let Some(number) = number.as_f64() else {
+                return false;
+            };
+            match op {
+                Op::Eq => number == *expected,
+                Op::Ne => number != *expected,
+                Op::Lt => number < *expected,
+                Op::Le => number <= *expected,
+                Op::Gt => number > *expected,
+                Op::Ge => number >= *expected,
+                Op::Contains => false,
+            }
+        }
+        (Value::String(text), Literal::Text(expected)) => match op {
+            Op::Eq => text == expected,
+            Op::Ne => text != expected,
+            Op::Lt => text < expected,
+            Op::Le => text <= expected,
+            Op::Gt => text > expected,
+            Op::Ge => text >= expected,
+            Op::Contains => text.to_lowercase().contains(&expected.to_lowercase()),
+        },
+        (Value::Bool(flag), Literal::Bool(expected)) => match op {
+            Op::Eq => flag == expected,
+            Op::Ne => flag != expected,
+            _ => false,
+        },
+        (Value::Array(items), _) if op == Op::Contains => {
+            items.iter().any(|item| compare(item, Op::Eq, literal))
+        }
+        _ => false,
+    }
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidFilter(message.into())
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '\'' | '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, ch)) if ch == c => break,
+                        Some((_, ch)) => text.push(ch),
+                        None => return Err(invalid("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Text(text));
+                continue;
+            }
+            '&' | '|' | '=' | '!' | '<' | '>' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, ch)| ch);
+                let (token, pair) = match (c, next) {
+                    ('&', Some('&')) => (Token::And, true),
+                    ('|', Some('|')) => (Token::Or, true),
+                    ('=', Some('=')) => (Token::Op(Op::Eq), true),
+                    ('!', Some('=')) => (Token::Op(Op::Ne), true),
+                    ('<', Some('=')) => (Token::Op(Op::Le), true),
+                    ('>', Some('=')) => (Token::Op(Op::Ge), true),
+                    ('!', _) => (Token::Not, false),
+                    ('<', _) => (Token::Op(Op::Lt), false),
+                    ('>', _) => (Token::Op(Op::Gt), false),
+                    _ => return Err(invalid(format!("unexpected `{c}` at {start}"))),
+                };
+                if pair {
+                    chars.next();
+                }
+                tokens.push(token);
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut number = String::new();
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch.is_ascii_digit() || matches!(ch, '-' | '.' | 'e' | 'E') {
+                        number.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid number `{number}`")))?;
+                tokens.push(Token::Number(number));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch.is_alphanumeric() || matches!(ch, '_' | '.') {
+                        ident.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(match ident.as_str() {
+                    "contains" => Token::Op(Op::Contains),
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(ident),
+                });
+                continue;
+            }
+            _ => return Err(invalid(format!("unexpected `{c}` at {start}"))),
+        };
+        chars.next();
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Open) {
+            let expr = self.or()?;
+            if !self.eat(&Token::Close) {
+                return Err(invalid("missing `)`"));
+            }
+            return Ok(expr);
+        }
+
+        let field = match self.next() {
+            Some(Token::Ident(field)) => field,
+            other => return Err(invalid(format!("expected a field, found {other:?}"))),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            other => return Err(invalid(format!("expected an operator, found {other:?}"))),
+        };
+        let literal = match self.next() {
+            Some(Token::Number(number)) => Literal::Number(number),
+            Some(Token::Text(text)) => Literal::Text(text),
+            Some(Token::Ident(ident)) if ident == "true" => Literal::Bool(true),
+            Some(Token::Ident(ident)) if ident == "false" => Literal::Bool(false),
+            other => return Err(invalid(format!("expected a value, found {other:?}"))),
+        };
+        Ok(Expr::Compare(field, op, literal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(source: &str, record: Value) -> bool {
+        Predicate::parse(source).unwrap().matches(&record)
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let record = json!({"confidence": 0.7, "area": 0.05, "label": "car"});
+
+        assert!(matches("confidence > 0.5 && area < 0.1", record.clone()));
+        assert!(!matches("confidence > 0.8 && area < 0.1", record.clone()));
+        assert!(matches(
+            "confidence > 0.8 || label == 'car'",
+            record.clone()
+        ));
+        assert!(matches("!(confidence <= 0.5)", record.clone()));
+        assert!(!matches("missing > 1", record));
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(matches(
+            "answer contains 'YES'",
+            json!({"answer": "Yes, there is a dog"})
+        ));
+        assert!(matches(
+            "tags contains \"dog\"",
+            json!({"tags": ["cat", "dog"]})
+        ));
+    }
+
+    #[test]
+    fn test_filter_detections_uses_area() {
+        let object = |size: f64| DetectionObject {
+            x_min: 0.0,
+            y_min: 0.0,
+            x_max: size,
+            y_max: size,
+            confidence: None,
+        };
+        let response = DetectResponse {
+            request_id: None,
+            objects: vec![object(0.1), object(0.5)],
+        };
+
+        let predicate = Predicate::parse("area < 0.1").unwrap();
+        assert_eq!(
+            predicate.filter_detections(response).objects,
+            vec![object(0.1)]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for source in ["confidence >", "(a == 1", "a == 'x", "a ~ 1", "a == 1 b"] {
+            assert!(
+                matches!(Predicate::parse(source), Err(Error::InvalidFilter(_))),
+                "{source}"
+            );
+        }
+    }
+}
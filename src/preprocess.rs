@@ -0,0 +1,191 @@
+//! Image preprocessing applied before upload.
+//!
+//! A client configured with [`MoonDream::with_preprocess`](crate::MoonDream::with_preprocess)
+//! passes every image through an [`ImagePreprocessor`] before sending it. With
+//! the `image` feature enabled, `Preprocess` downsizes large photos and
+//! re-encodes them to keep payloads small.
+
+use crate::Error;
+
+/// Transformation applied to every image before it is sent to the API.
+///
+/// `image` is the value given to the client, usually a base64 `data:` URI or
+/// a remote URL. Implementations should return values they do not know how
+/// to handle unchanged.
+pub trait ImagePreprocessor: std::fmt::Debug + Send + Sync {
+    /// Transform `image` into the value that will be uploaded.
+    fn process(&self, image: String) -> Result<String, Error>;
+}
+
+#[cfg(feature = "image")]
+pub use resize::{OutputFormat, Preprocess};
+
+#[cfg(feature = "image")]
+mod resize {
+    use super::ImagePreprocessor;
+    use crate::Error;
+    use crate::input::{decode_data_uri, encode_data_uri};
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::webp::WebPEncoder;
+    use image::imageops::FilterType;
+    use image::{DynamicImage, GenericImageView};
+
+    /// Encoding used for preprocessed images.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum OutputFormat {
+        /// Lossy JPEG with the given quality (1-100).
+        Jpeg { quality: u8 },
+        /// Lossless WebP.
+        WebP,
+    }
+
+    /// Downsize and re-encode images before upload.
+    ///
+    /// Only base64 `data:` URIs are processed; remote URLs are sent unchanged.
+    /// Images are never upscaled and keep their aspect ratio.
+    ///
+    /// ```
+    /// use moondream::{MoonDream, Preprocess};
+    ///
+    /// let md = MoonDream::remote("token").with_preprocess(Preprocess::max_dim(1536).jpeg_quality(85));
+    /// ```
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Preprocess {
+        max_dim: Option<u32>,
+        format: OutputFormat,
+    }
+
+    impl Default for Preprocess {
+        fn default() -> Self {
+            Self {
+                max_dim: None,
+                format: OutputFormat::Jpeg { quality: 85 },
+            }
+        }
+    }
+
+    impl Preprocess {
+        /// Re-encode images without resizing them.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Downsize images so that their longest side is at most `max_dim`
+        /// pixels.
+        pub fn max_dim(max_dim: u32) -> Self {
+            Self::default().with_max_dim(max_dim)
+        }
+
+        /// Set the maximum size of the longest side, in pixels.
+        pub fn with_max_dim(mut self, max_dim: u32) -> Self {
+            self.max_dim = Some(max_dim);
+            self
+        }
+
+        /// Encode images as JPEG with the given quality (clamped to 1-100).
+        pub fn jpeg_quality(mut self, quality: u8) -> Self {
+            self.format = OutputFormat::Jpeg {
+                quality: quality.clamp(1, 100),
+            };
+            self
+        }
+
+        /// Encode images as lossless WebP.
+        pub fn webp(mut self) -> Self {
+            self.format = OutputFormat::WebP;
+            self
+        }
+
+        /// Resize and re-encode raw image bytes, returning the MIME type and
+        /// the encoded bytes.
+        pub fn apply(&self, bytes: &[u8]) -> Result<(&'static str, Vec<u8>), Error> {
+            let mut image =
+                image::load_from_memory(bytes).map_err(|e| Error::InvalidImage(e.to_string()))?;
+
+            if let Some(max_dim) = self.max_dim {
+                let (width, height) = image.dimensions();
+                if width.max(height) > max_dim {
+                    image = image.resize(max_dim, max_dim, FilterType::Lanczos3);
+                }
+            }
+
+            let mut data = Vec::new();
+            let mime = match self.format {
+                OutputFormat::Jpeg { quality } => {
+                    let encoder = JpegEncoder::new_with_quality(&mut data, quality);
+                    DynamicImage::ImageRgb8(image.to_rgb8())
+                        .write_with_encoder(encoder)
+                        .map_err(|e| Error::InvalidImage(e.to_string()))?;
+                    "image/jpeg"
+                }
+                OutputFormat::WebP => {
+                    let encoder = WebPEncoder::new_lossless(&mut data);
+                    DynamicImage::ImageRgba8(image.to_rgba8())
+                        .write_with_encoder(encoder)
+                        .map_err(|e| Error::InvalidImage(e.to_string()))?;
+                    "image/webp"
+                }
+            };
+            Ok((mime, data))
+        }
+    }
+
+    impl ImagePreprocessor for Preprocess {
+        fn process(&self, image: String) -> Result<String, Error> {
+            let Some(decoded) = decode_data_uri(&image) else {
+                return Ok(image);
+            };
+            let (_, bytes) = decoded?;
+            let (mime, data) = self.apply(&bytes)?;
+            Ok(encode_data_uri(mime, &data))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+    use crate::input::{decode_data_uri, encode_data_uri};
+    use image::{GenericImageView, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn png_data_uri(width: u32, height: u32) -> String {
+        let mut data = Vec::new();
+        RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        encode_data_uri("image/png", &data)
+    }
+
+    #[test]
+    fn test_preprocess_downsizes_and_reencodes() {
+        let processed = Preprocess::max_dim(20)
+            .jpeg_quality(80)
+            .process(png_data_uri(100, 50))
+            .unwrap();
+
+        let (mime, bytes) = decode_data_uri(&processed).unwrap().unwrap();
+        assert_eq!(mime, "image/jpeg");
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.dimensions(), (20, 10));
+    }
+
+    #[test]
+    fn test_preprocess_never_upscales() {
+        let processed = Preprocess::max_dim(1536)
+            .webp()
+            .process(png_data_uri(30, 40))
+            .unwrap();
+
+        let (mime, bytes) = decode_data_uri(&processed).unwrap().unwrap();
+        assert_eq!(mime, "image/webp");
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.dimensions(), (30, 40));
+    }
+
+    #[test]
+    fn test_preprocess_passes_urls_through() {
+        let url = "https://example.com/cat.jpg".to_string();
+        assert_eq!(Preprocess::max_dim(10).process(url.clone()).unwrap(), url);
+    }
+}
@@ -0,0 +1,27 @@
+//! Ready-made presets for common uses of the API.
+//!
+//! - `receipt`, `id_card` and `invoice` extract typed fields from
+//!   documents (feature `schemars`).
+//! - [`alt_text`] writes accessible image descriptions for web publishing.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::presets;
+//!
+//! # #[cfg(feature = "schemars")]
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let receipt = md
+//!     .extract_preset("https://example.com/receipt.jpg", &presets::receipt())
+//!     .await?;
+//! println!("{:?} paid at {:?}", receipt.total, receipt.merchant);
+//! # Ok(())
+//! # }
+//! ```
+
+mod alt_text;
+#[cfg(feature = "schemars")]
+mod documents;
+
+pub use alt_text::{AltText, alt_text};
+#[cfg(feature = "schemars")]
+pub use documents::{IdCard, Invoice, LineItem, Preset, Receipt, id_card, invoice, receipt};
@@ -0,0 +1,216 @@
+//! Alt text for screen readers.
+//!
+//! [`MoonDream::alt_text`] captions the image, or asks about it when the
+//! surrounding context is known, and cleans the answer up: leading "image
+//! of" boilerplate is removed, the text is capped at a length screen readers
+//! read comfortably and always ends with a period.
+
+use crate::{CaptionLength, Error, ImageInput, MoonDream};
+
+/// Phrases screen readers make redundant, since they already announce an
+/// image.
+const BOILERPLATE: [&str; 20] = [
+    "alt text:",
+    "alt:",
+    "this is an image of",
+    "this is a picture of",
+    "this is a photo of",
+    "this image shows",
+    "this image depicts",
+    "this picture shows",
+    "this photo shows",
+    "the image shows",
+    "the image depicts",
+    "the picture shows",
+    "the photo shows",
+    "in this image,",
+    "in the image,",
+    "an image of",
+    "image of",
+    "a picture of",
+    "picture of",
+    "a photo of",
+];
+
+/// Settings of [`MoonDream::alt_text`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AltText {
+    context: Option<String>,
+    max_chars: usize,
+}
+
+impl Default for AltText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AltText {
+    /// Describe images without context, in at most 125 characters.
+    pub fn new() -> Self {
+        Self {
+            context: None,
+            max_chars: 125,
+        }
+    }
+
+    /// Describe images as used in `context`, for example the title or
+    /// paragraph of the article showing them.
+    pub fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// Set the maximum length of the alt text, in characters.
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars.max(1);
+        self
+    }
+
+    /// Question sent to `/query` when a context is set.
+    fn prompt(&self, context: &str) -> String {
+        format!(
+            "Write alt text for this image, which appears next to: \"{}\". Describe what \
+             matters in that context in one short sentence, without starting with \"image \
+             of\" or \"picture of\".",
+            context.trim()
+        )
+    }
+
+    /// Clean `text` up into alt text.
+    pub fn clean(&self, text: &str) -> String {
+        let mut text = text.trim().trim_matches(['"', '\'']).trim();
+        while let Some(rest) = BOILERPLATE
+            .iter()
+            .find_map(|phrase| strip_phrase(text, phrase))
+        {
+            text = rest;
+        }
+
+        let mut text = truncate(text, self.max_chars.saturating_sub(1)).to_string();
+        if let Some(first) = text.chars().next() {
+            text.replace_range(..first.len_utf8(), &first.to_uppercase().to_string());
+        }
+        if !text.is_empty() && !text.ends_with(['.', '!', '?']) {
+            text.push('.');
+        }
+        text
+    }
+}
+
+/// Describe images in at most 125 characters, as used in `context`.
+pub fn alt_text(context: impl Into<String>) -> AltText {
+    AltText::new().with_context(context)
+}
+
+impl MoonDream {
+    /// Write alt text for the image, see [`presets`](crate::presets).
+    pub async fn alt_text(
+        &self,
+        image: impl Into<ImageInput>,
+        settings: &AltText,
+    ) -> Result<String, Error> {
+        let description = match &settings.context {
+            Some(context) => self.query(image, settings.prompt(context)).await?.answer,
+            None => {
+                self.caption(image, Some(CaptionLength::Short))
+                    .await?
+                    .caption
+            }
+        };
+        Ok(settings.clean(&description))
+    }
+}
+
+/// Remove `phrase` from the start of `text`, ignoring case.
+fn strip_phrase<'a>(text: &'a str, phrase: &str) -> Option<&'a str> {
+    let head = text.get(..phrase.len())?;
+    let rest = &text[phrase.len()..];
+    let word_ends = phrase.ends_with([':', ',']) || rest.starts_with(char::is_whitespace);
+    (head.eq_ignore_ascii_case(phrase) && word_ends).then(|| rest.trim_start())
+}
+
+/// Shorten `text` to at most `max_chars` characters, at the end of a
+/// sentence or else of a word.
+fn truncate(text: &str, max_chars: usize) -> &str {
+    let Some((cut, _)) = text.char_indices().nth(max_chars) else {
+        return text;
+    };
+    let head = &text[..cut];
+    if let Some(end) = head.rfind(['.', '!', '?'])
+        && end >= cut / 2
+    {
+        return &head[..=end];
+    }
+    let head = match head.rfind(char::is_whitespace) {
+        Some(space) if !text[cut..].starts_with(char::is_whitespace) => &head[..space],
+        _ => head,
+    };
+    head.trim_end_matches(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_clean_removes_boilerplate() {
+        let settings = AltText::new();
+        assert_eq!(
+            settings.clean("This image shows an image of a red bicycle"),
+            "A red bicycle."
+        );
+        assert_eq!(settings.clean("\"Alt text: two cats\""), "Two cats.");
+        assert_eq!(
+            settings.clean("Imagery of the coast at dusk."),
+            "Imagery of the coast at dusk."
+        );
+    }
+
+    #[test]
+    fn test_clean_caps_length() {
+        let settings = AltText::new().with_max_chars(30);
+        assert_eq!(
+            settings.clean("A dog runs on the beach while children fly kites"),
+            "A dog runs on the beach while."
+        );
+        assert_eq!(
+            settings.clean("A dog on a beach. Children fly kites behind it."),
+            "A dog on a beach."
+        );
+        assert!(settings.clean(&"word ".repeat(40)).chars().count() <= 30);
+    }
+
+    #[tokio::test]
+    async fn test_alt_text_uses_caption_or_context() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .and(body_partial_json(serde_json::json!({ "length": "short" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "A photo of a lighthouse on a cliff",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_string_contains("Storm season"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "Waves crash against a lighthouse",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        assert_eq!(
+            md.alt_text("img", &AltText::new()).await.unwrap(),
+            "A lighthouse on a cliff."
+        );
+        assert_eq!(
+            md.alt_text("img", &alt_text("Storm season")).await.unwrap(),
+            "Waves crash against a lighthouse."
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Document extraction presets (feature `schemars`).
+//!
+//! A [`Preset`] pairs tuned instructions with a typed result, and is run with
+//! [`MoonDream::extract_preset`] on top of [`MoonDream::extract`]. Fields
+//! the model cannot read are left empty rather than guessed.
+
+use crate::{Error, ImageInput, MoonDream};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// Instructions extracting a `T` from a document image.
+#[derive(Debug, Clone)]
+pub struct Preset<T> {
+    instructions: String,
+    output: PhantomData<fn() -> T>,
+}
+
+impl<T> Preset<T> {
+    /// Create a preset from custom instructions.
+    pub fn new(instructions: impl Into<String>) -> Self {
+        Self {
+            instructions: instructions.into(),
+            output: PhantomData,
+        }
+    }
+
+    /// Append `hint` to the instructions, for example the expected language
+    /// or date format of the documents.
+    pub fn with_hint(mut self, hint: impl AsRef<str>) -> Self {
+        self.instructions.push(' ');
+        self.instructions.push_str(hint.as_ref().trim());
+        self
+    }
+
+    /// Instructions sent to the model, before the JSON schema.
+    pub fn instructions(&self) -> &str {
+        &self.instructions
+    }
+}
+
+/// One line of a [`Receipt`] or an [`Invoice`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LineItem {
+    /// Description of the product or service, as printed.
+    pub description: String,
+    /// Number of units, if printed.
+    #[serde(default)]
+    pub quantity: Option<f64>,
+    /// Price of one unit, if printed.
+    #[serde(default)]
+    pub unit_price: Option<f64>,
+    /// Total amount of the line.
+    #[serde(default)]
+    pub amount: Option<f64>,
+}
+
+/// Fields of a shop or restaurant receipt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Receipt {
+    /// Name of the shop or restaurant.
+    #[serde(default)]
+    pub merchant: Option<String>,
+    /// Purchase date, as YYYY-MM-DD.
+    #[serde(default)]
+    pub date: Option<String>,
+    /// ISO 4217 currency code, for example EUR.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Purchased items, in printed order.
+    #[serde(default)]
+    pub items: Vec<LineItem>,
+    /// Amount before taxes.
+    #[serde(default)]
+    pub subtotal: Option<f64>,
+    /// Total amount of taxes.
+    #[serde(default)]
+    pub tax: Option<f64>,
+    /// Total amount paid.
+    #[serde(default)]
+    pub total: Option<f64>,
+}
+
+/// Fields of an identity card, passport or driving licence.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IdCard {
+    /// Kind of document, for example "passport".
+    #[serde(default)]
+    pub document_type: Option<String>,
+    /// Document number.
+    #[serde(default)]
+    pub document_number: Option<String>,
+    /// Surname of the holder.
+    #[serde(default)]
+    pub surname: Option<String>,
+    /// Given names of the holder.
+    #[serde(default)]
+    pub given_names: Option<String>,
+    /// Date of birth, as YYYY-MM-DD.
+    #[serde(default)]
+    pub date_of_birth: Option<String>,
+    /// Sex of the holder as printed, for example "F".
+    #[serde(default)]
+    pub sex: Option<String>,
+    /// Nationality of the holder.
+    #[serde(default)]
+    pub nationality: Option<String>,
+    /// Country or authority issuing the document.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Date of issue, as YYYY-MM-DD.
+    #[serde(default)]
+    pub date_of_issue: Option<String>,
+    /// Date of expiry, as YYYY-MM-DD.
+    #[serde(default)]
+    pub date_of_expiry: Option<String>,
+}
+
+/// Fields of a supplier invoice.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Invoice {
+    /// Invoice number.
+    #[serde(default)]
+    pub invoice_number: Option<String>,
+    /// Date of issue, as YYYY-MM-DD.
+    #[serde(default)]
+    pub issue_date: Option<String>,
+    /// Payment due date, as YYYY-MM-DD.
+    #[serde(default)]
+    pub due_date: Option<String>,
+    /// Name of the company issuing the invoice.
+    #[serde(default)]
+    pub seller: Option<String>,
+    /// Name of the customer.
+    #[serde(default)]
+    pub buyer: Option<String>,
+    /// ISO 4217 currency code, for example EUR.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Invoiced products and services, in printed order.
+    #[serde(default)]
+    pub items: Vec<LineItem>,
+    /// Amount before taxes.
+    #[serde(default)]
+    pub subtotal: Option<f64>,
+    /// Total amount of taxes.
+    #[serde(default)]
+    pub tax: Option<f64>,
+    /// Total amount due.
+    #[serde(default)]
+    pub total: Option<f64>,
+}
+
+/// Extract the fields of a receipt.
+pub fn receipt() -> Preset<Receipt> {
+    Preset::new(
+        "Read the receipt in the image. Copy names exactly as printed, write amounts as plain \
+         numbers without currency symbols or thousands separators, and use null for fields \
+         that are missing or unreadable.",
+    )
+}
+
+/// Extract the fields of an identity document.
+pub fn id_card() -> Preset<IdCard> {
+    Preset::new(
+        "Read the identity document in the image. Copy names and numbers exactly as printed, \
+         prefer the machine readable zone when it disagrees with the printed fields, and use \
+         null for fields that are missing or unreadable.",
+    )
+}
+
+/// Extract the fields of an invoice.
+pub fn invoice() -> Preset<Invoice> {
+    Preset::new(
+        "Read the invoice in the image. Copy names and numbers exactly as printed, write \
+         amounts as plain numbers without currency symbols or thousands separators, and use \
+         null for fields that are missing or unreadable.",
+    )
+}
+
+impl MoonDream {
+    /// Extract the fields of `preset` from the image, see
+    /// [`presets`](crate::presets).
+    pub async fn extract_preset<T: JsonSchema + DeserializeOwned>(
+        &self,
+        image: impl Into<ImageInput>,
+        preset: &Preset<T>,
+    ) -> Result<T, Error> {
+        self.extract(image, preset.instructions()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_preset_hint() {
+        let preset = invoice().with_hint(" Dates are written DD/MM/YYYY. ");
+        assert!(preset.instructions().starts_with("Read the invoice"));
+        assert!(
+            preset
+                .instructions()
+                .ends_with("unreadable. Dates are written DD/MM/YYYY.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_receipt() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .and(body_string_contains("Read the receipt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": r#"{"merchant": "Corner Cafe", "currency": "EUR",
+                    "items": [{"description": "Espresso", "quantity": 2, "amount": 3.2}],
+                    "total": 3.2, "date": null}"#,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::local(server.uri());
+        let parsed = md.extract_preset("img", &receipt()).await.unwrap();
+        assert_eq!(parsed.merchant.as_deref(), Some("Corner Cafe"));
+        assert_eq!(parsed.items[0].quantity, Some(2.0));
+        assert_eq!(parsed.total, Some(3.2));
+        assert_eq!(parsed.date, None);
+        assert_eq!(parsed.tax, None);
+    }
+}
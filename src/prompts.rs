@@ -0,0 +1,253 @@
+//! Prompt templates for common question answering tasks.
+//!
+//! A [`Task`] builds the question sent to `/query` and parses the answer
+//! into a [`TaskOutput`]. Answers that do not fit the task fail with
+//! [`Error::UnexpectedAnswer`], which carries the raw answer.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::prompts::Task;
+//!
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let people = md
+//!     .run_task("https://example.com/street.jpg", &Task::Count("people".into()))
+//!     .await?
+//!     .as_count();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, ImageInput, MoonDream};
+
+/// A question answering task with a known answer type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Task {
+    /// Transcribe the text of the image, answered with
+    /// [`TaskOutput::Text`].
+    Ocr,
+    /// Count the given objects, answered with [`TaskOutput::Count`].
+    Count(String),
+    /// Ask a yes/no question, answered with [`TaskOutput::YesNo`].
+    YesNo(String),
+    /// List the main colors of the given object, answered with
+    /// [`TaskOutput::Colors`].
+    Colors(String),
+}
+
+/// Parsed answer of a [`Task`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TaskOutput {
+    /// Answer of [`Task::Ocr`].
+    Text(String),
+    /// Answer of [`Task::Count`].
+    Count(usize),
+    /// Answer of [`Task::YesNo`].
+    YesNo(bool),
+    /// Answer of [`Task::Colors`], lowercase color names.
+    Colors(Vec<String>),
+}
+
+impl TaskOutput {
+    /// Return the text of a [`TaskOutput::Text`].
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            TaskOutput::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Return the number of a [`TaskOutput::Count`].
+    pub fn as_count(&self) -> Option<usize> {
+        match self {
+            TaskOutput::Count(count) => Some(*count),
+            _ => None,
+        }
+    }
+
+    /// Return the answer of a [`TaskOutput::YesNo`].
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            TaskOutput::YesNo(answer) => Some(*answer),
+            _ => None,
+        }
+    }
+
+    /// Return the colors of a [`TaskOutput::Colors`].
+    pub fn as_colors(&self) -> Option<&[String]> {
+        match self {
+            TaskOutput::Colors(colors) => Some(colors),
+            _ => None,
+        }
+    }
+}
+
+const NUMBERS: [&str; 21] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+    "twenty",
+];
+
+impl Task {
+    /// Name of the task, used in errors.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Task::Ocr => "ocr",
+            Task::Count(_) => "count",
+            Task::YesNo(_) => "yes/no",
+            Task::Colors(_) => "colors",
+        }
+    }
+
+    /// Question sent to the model.
+    pub fn prompt(&self) -> String {
+        match self {
+            Task::Ocr => "Transcribe all the text in the image exactly as written, keeping \
+                          the line breaks. Reply with the text only."
+                .to_string(),
+            Task::Count(object) => {
+                format!("How many {object} are in the image? Reply with a single number.")
+            }
+            Task::YesNo(question) => format!("{} Answer yes or no.", question.trim()),
+            Task::Colors(object) => format!(
+                "What are the main colors of the {object}? Reply with a comma-separated list \
+                 of color names."
+            ),
+        }
+    }
+
+    /// Parse the model answer.
+    pub fn parse(&self, answer: &str) -> Result<TaskOutput, Error> {
+        let unexpected = || Error::UnexpectedAnswer {
+            task: self.name(),
+            answer: answer.to_string(),
+        };
+        let trimmed = answer.trim();
+
+        match self {
+            Task::Ocr => Ok(TaskOutput::Text(strip_fences(trimmed).to_string())),
+            Task::Count(_) => words(trimmed)
+                .find_map(|word| {
+                    word.parse()
+                        .ok()
+                        .or_else(|| NUMBERS.iter().position(|number| *number == word))
+                        .or_else(|| matches!(word.as_str(), "no" | "none").then_some(0))
+                })
+                .map(TaskOutput::Count)
+                .ok_or_else(unexpected),
+            Task::YesNo(_) => match words(trimmed).next().as_deref() {
+                Some("yes" | "yeah" | "yep" | "true" | "correct") => Ok(TaskOutput::YesNo(true)),
+                Some("no" | "nope" | "false" | "incorrect") => Ok(TaskOutput::YesNo(false)),
+                _ => Err(unexpected()),
+            },
+            Task::Colors(_) => {
+                let colors: Vec<String> = trimmed
+                    .split([',', '/', ';', '\n'])
+                    .flat_map(|part| part.split(" and "))
+                    .map(|color| {
+                        color
+                            .trim()
+                            .trim_start_matches("and ")
+                            .trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
+                            .to_lowercase()
+                    })
+                    .filter(|color| !color.is_empty())
+                    .collect();
+                if colors.is_empty() {
+                    Err(unexpected())
+                } else {
+                    Ok(TaskOutput::Colors(colors))
+                }
+            }
+        }
+    }
+}
+
+/// Lowercase words of `text`, without punctuation.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Remove a markdown code fence around `text`.
+fn strip_fences(text: &str) -> &str {
+    match text.strip_prefix("```") {
+        Some(rest) => {
+            let rest = rest.split_once('\n').map_or("", |(_, body)| body);
+            rest.trim_end().trim_end_matches("```").trim()
+        }
+        None => text,
+    }
+}
+
+impl MoonDream {
+    /// Ask the question of `task` and parse the answer.
+    pub async fn run_task(
+        &self,
+        image: impl Into<ImageInput>,
+        task: &Task,
+    ) -> Result<TaskOutput, Error> {
+        let response = self.query(image, task.prompt()).await?;
+        task.parse(&response.answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_count() {
+        let task = Task::Count("people".into());
+        assert_eq!(task.parse("3").unwrap(), TaskOutput::Count(3));
+        assert_eq!(
+            task.parse("There are 12 people.").unwrap(),
+            TaskOutput::Count(12)
+        );
+        assert_eq!(task.parse("Two.").unwrap(), TaskOutput::Count(2));
+        assert_eq!(task.parse("None").unwrap(), TaskOutput::Count(0));
+        assert!(matches!(
+            task.parse("I cannot tell"),
+            Err(Error::UnexpectedAnswer { task: "count", .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_yes_no() {
+        let task = Task::YesNo("Is the door open?".into());
+        assert_eq!(task.prompt(), "Is the door open? Answer yes or no.");
+        assert_eq!(task.parse("Yes, it is.").unwrap().as_bool(), Some(true));
+        assert_eq!(task.parse("no").unwrap().as_bool(), Some(false));
+        assert!(task.parse("Maybe").is_err());
+    }
+
+    #[test]
+    fn test_parse_colors_and_ocr() {
+        let task = Task::Colors("car".into());
+        assert_eq!(
+            task.parse("Red, white and Blue.").unwrap().as_colors(),
+            Some(&["red".to_string(), "white".into(), "blue".into()][..])
+        );
+
+        let text = Task::Ocr.parse("```text\nEXIT\nOnly\n```").unwrap();
+        assert_eq!(text.as_text(), Some("EXIT\nOnly"));
+    }
+}
@@ -0,0 +1,317 @@
+//! Sans-IO core of the Moondream protocol.
+//!
+//! [`Protocol`] turns a [`Call`] into an [`http::Request`] and an
+//! [`http::Response`] into a decoded response, without performing any I/O.
+//! Embedded targets, custom transports and runtimes other than the bundled
+//! `reqwest` stack can send the requests themselves and still share the
+//! authentication, URL building and response decoding of [`MoonDream`],
+//! which builds its own JSON requests and classifies its error responses
+//! here. The API only uses `http` types, but the crate itself still depends
+//! on `reqwest`.
+//!
+//! ```
+//! use moondream::protocol::{Call, Protocol};
+//! use moondream::QueryResponse;
+//!
+//! let protocol = Protocol::new("http://localhost:2020/v1", "");
+//! let request = protocol.request(&Call::Query {
+//!     image_url: "data:image/png;base64,AAAA",
+//!     question: "What is this?",
+//! })?;
+//! assert_eq!(request.uri(), "http://localhost:2020/v1/query");
+//!
+//! // Send `request` with any HTTP client, then decode its answer.
+//! let response = http::Response::new(r#"{"answer": "A cat"}"#);
+//! let (answer, _flags): (QueryResponse, _) = protocol.response(&response)?;
+//! assert_eq!(answer.answer, "A cat");
+//! # Ok::<(), moondream::Error>(())
+//! ```
+
+use crate::template::{RequestTemplate, TemplateCell};
+use crate::{Auth, CaptionLength, Error, MoonDream, ResultFlags, decode, wire};
+use bytes::Bytes;
+use http::StatusCode;
+use http::header::{CONTENT_TYPE, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+/// A call to one of the inference endpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Call<'a> {
+    /// `/caption`: describe the image.
+    Caption {
+        /// Image URL or `data:` URI.
+        image_url: &'a str,
+        /// Length of the caption.
+        length: CaptionLength,
+    },
+    /// `/query`: answer a question about the image.
+    Query {
+        /// Image URL or `data:` URI.
+        image_url: &'a str,
+        /// Question to answer.
+        question: &'a str,
+    },
+    /// `/point`: centre points of the matching objects.
+    Point {
+        /// Image URL or `data:` URI.
+        image_url: &'a str,
+        /// Object to find.
+        object: &'a str,
+    },
+    /// `/detect`: bounding boxes of the matching objects.
+    Detect {
+        /// Image URL or `data:` URI.
+        image_url: &'a str,
+        /// Object to find.
+        object: &'a str,
+    },
+    /// `/segment`: outlines of the matching objects.
+    Segment {
+        /// Image URL or `data:` URI.
+        image_url: &'a str,
+        /// Object to find.
+        object: &'a str,
+    },
+}
+
+impl Call<'_> {
+    /// Path of the endpoint, relative to the API root.
+    pub fn path(&self) -> &'static str {
+        match self {
+            Call::Caption { .. } => "caption",
+            Call::Query { .. } => "query",
+            Call::Point { .. } => "point",
+            Call::Detect { .. } => "detect",
+            Call::Segment { .. } => "segment",
+        }
+    }
+
+    /// JSON body of the call, see [`wire`].
+    pub fn body(&self) -> Value {
+        match *self {
+            Call::Caption { image_url, length } => json!(wire::CaptionRequest {
+                image_url: image_url.into(),
+                length: Some(length),
+            }),
+            Call::Query {
+                image_url,
+                question,
+            } => json!(wire::QueryRequest {
+                image_url: image_url.into(),
+                question: question.into(),
+            }),
+            Call::Point { image_url, object }
+            | Call::Detect { image_url, object }
+            | Call::Segment { image_url, object } => object_body(image_url, object, false),
+        }
+    }
+}
+
+/// Body of a `/point`, `/detect` or `/segment` request.
+pub(crate) fn object_body(image_url: &str, object: &str, confidence: bool) -> Value {
+    json!(wire::ObjectRequest {
+        image_url: image_url.into(),
+        object: object.into(),
+        confidence: confidence.then_some(true),
+    })
+}
+
+/// Endpoint, credentials and decoding settings of the protocol.
+#[derive(Debug, Clone)]
+pub struct Protocol {
+    endpoint: String,
+    auth: Auth,
+    token: String,
+    headers: Vec<(String, String)>,
+    lenient_decode: bool,
+    template: TemplateCell,
+}
+
+impl Protocol {
+    /// Build requests for the API at `endpoint`, authenticated with `token`.
+    pub fn new(endpoint: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            auth: Auth::default(),
+            token: token.into(),
+            headers: Vec::new(),
+            lenient_decode: false,
+            template: TemplateCell::default(),
+        }
+    }
+
+    /// Send the token as `auth` describes, see [`Auth`].
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self.template = TemplateCell::default();
+        self
+    }
+
+    /// Add a header to every request.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self.template = TemplateCell::default();
+        self
+    }
+
+    /// Coerce numbers sent as strings when a response does not decode, see
+    /// [`MoonDream::with_lenient_decode`].
+    pub fn with_lenient_decode(mut self, lenient_decode: bool) -> Self {
+        self.lenient_decode = lenient_decode;
+        self
+    }
+
+    fn template(&self) -> Result<&RequestTemplate, Error> {
+        self.template
+            .get_or_build(&self.endpoint, &[], &self.auth, &self.token, &self.headers)
+    }
+
+    /// HTTP request performing `call`.
+    pub fn request(&self, call: &Call<'_>) -> Result<http::Request<Bytes>, Error> {
+        let template = self.template()?;
+        let body = Bytes::from(serde_json::to_vec(&call.body())?);
+        build_request(
+            template,
+            &template.with_query(&template.url(call.path())?),
+            body,
+        )
+    }
+
+    /// Decode the HTTP response to a call, with the [`ResultFlags`] set by
+    /// the decoding.
+    ///
+    /// Error statuses are returned as [`Error::Status`], or as
+    /// [`Error::ModelLoading`] when a `502`, `503` or `504` body says the
+    /// model is not ready.
+    pub fn response<T: DeserializeOwned>(
+        &self,
+        response: &http::Response<impl AsRef<[u8]>>,
+    ) -> Result<(T, ResultFlags), Error> {
+        let body = response.body().as_ref();
+        check_status(response.status(), body)?;
+        Ok(decode::decode(body, self.lenient_decode)?)
+    }
+}
+
+impl MoonDream {
+    /// [`Protocol`] with the endpoint, credentials, headers and decoding
+    /// settings of the client, to send requests without its HTTP stack.
+    pub fn protocol(&self) -> Protocol {
+        Protocol {
+            endpoint: self.endpoint.clone(),
+            auth: self.auth.clone(),
+            token: self.token.clone(),
+            headers: self.headers.clone(),
+            lenient_decode: self.lenient_decode,
+            template: TemplateCell::default(),
+        }
+    }
+}
+
+/// POST request of the JSON `body` to `uri`, with the headers of
+/// `template`.
+pub(crate) fn build_request(
+    template: &RequestTemplate,
+    uri: &str,
+    body: Bytes,
+) -> Result<http::Request<Bytes>, Error> {
+    let mut request = http::Request::post(uri)
+        .body(body)
+        .map_err(|e| Error::InvalidConfig(format!("request: {e}")))?;
+    *request.headers_mut() = template.headers().clone();
+    request
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(request)
+}
+
+/// Fail on an error status, with [`Error::ModelLoading`] when the body says
+/// the model is not ready and [`Error::Status`] otherwise. Both
+/// [`Protocol::response`] and [`MoonDream`] classify responses here.
+pub(crate) fn check_status(status: StatusCode, body: &[u8]) -> Result<(), Error> {
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(body).into_owned();
+    Err(if is_loading_response(status, &message) {
+        Error::ModelLoading { status, message }
+    } else {
+        Error::Status { status, message }
+    })
+}
+
+/// Return `true` if an error response says the model is not ready yet: a
+/// `502`, `503` or `504` status with a body such as "model is loading".
+fn is_loading_response(status: StatusCode, message: &str) -> bool {
+    if !matches!(status.as_u16(), 502..=504) {
+        return false;
+    }
+    let message = message.to_lowercase();
+    [
+        "model is loading",
+        "model is still loading",
+        "model loading",
+        "loading model",
+        "model not loaded",
+        "model is not loaded",
+        "model is not ready",
+        "model not ready",
+        "warming up",
+    ]
+    .iter()
+    .any(|phrase| message.contains(phrase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DetectResponse, QueryResponse};
+
+    #[test]
+    fn test_request() {
+        let protocol = Protocol::new("http://localhost:2020/v1/", "secret")
+            .with_auth(Auth::Query("sig".into()))
+            .with_header("X-Team", "vision");
+        let request = protocol
+            .request(&Call::Detect {
+                image_url: "data:image/png;base64,AAAA",
+                object: "cat",
+            })
+            .unwrap();
+        assert_eq!(request.method(), http::Method::POST);
+        assert_eq!(request.uri(), "http://localhost:2020/v1/detect?sig=secret");
+        assert_eq!(request.headers()["x-team"], "vision");
+        assert_eq!(request.headers()[CONTENT_TYPE], "application/json");
+        let body: Value = serde_json::from_slice(request.body()).unwrap();
+        assert_eq!(body["object"], "cat");
+    }
+
+    #[test]
+    fn test_response() {
+        let protocol = Protocol::new("http://localhost:2020/v1", "").with_lenient_decode(true);
+        let ok = http::Response::new(
+            r#"{"objects": [{"x_min": "0.1", "y_min": 0, "x_max": 1, "y_max": 1}]}"#,
+        );
+        let (detected, flags): (DetectResponse, _) = protocol.response(&ok).unwrap();
+        assert_eq!(detected.objects[0].x_min, 0.1);
+        assert!(flags.contains(ResultFlags::LENIENT_PARSE));
+
+        let mut limited = http::Response::new("slow down");
+        *limited.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+        let error = protocol.response::<QueryResponse>(&limited).unwrap_err();
+        assert!(matches!(error, Error::Status { status, .. } if status == 429));
+        assert!(error.is_retryable());
+
+        let mut loading = http::Response::new("Model is loading");
+        *loading.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        let error = protocol.response::<QueryResponse>(&loading).unwrap_err();
+        assert!(matches!(error, Error::ModelLoading { .. }));
+
+        let mut bad_image = http::Response::new("error loading image");
+        *bad_image.status_mut() = StatusCode::BAD_REQUEST;
+        let error = protocol.response::<QueryResponse>(&bad_image).unwrap_err();
+        assert!(matches!(error, Error::Status { .. }));
+        assert!(!error.is_retryable());
+    }
+}
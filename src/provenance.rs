@@ -0,0 +1,260 @@
+//! Provenance of the images sent, for audited annotation workflows.
+//!
+//! With [`MoonDream::with_provenance`], the client records a [`Provenance`]
+//! for every image it prepares for sending: the SHA-256 hash of the source
+//! image, its path or URL and the time it was recorded. The hash is that of
+//! the source, before any [decoder](crate::decoder) or
+//! [preprocessing](crate::preprocess) runs, so a stored result can later be
+//! checked against the original file with [`Provenance::matches`].
+//!
+//! The provenance of the images of a call is also attached to its
+//! [`ResponseMeta::provenance`](crate::ResponseMeta::provenance), so each
+//! result is linked to its image even when calls run concurrently.
+//!
+//! Remote URLs are not downloaded: their hash is the hash of the URL. Files
+//! are read once, for the hash and for the request, unless they are streamed
+//! by the multipart transport.
+//!
+//! ```no_run
+//! use moondream::provenance::ProvenanceLog;
+//! use moondream::{ImageInput, MoonDream};
+//!
+//! # async fn run() -> Result<(), moondream::Error> {
+//! let log = ProvenanceLog::new();
+//! let md = MoonDream::remote("token").with_provenance(log.clone());
+//! let cars = md.detect_with_meta(ImageInput::file("scans/0001.png"), "car").await?;
+//! let provenance = &cars.meta.provenance[0];
+//! // Store `cars` with `provenance`, then, during the audit:
+//! assert!(provenance.matches(ImageInput::from_path("scans/0001.png")?)?);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::input::decode_data_uri;
+use crate::{Error, ImageInput, MoonDream};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use web_time::SystemTime;
+
+/// Hash, source and time of an image sent.
+///
+/// Serialized with `recorded_at` in milliseconds since the Unix epoch, to be
+/// stored next to the results.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Lowercase hex SHA-256 hash of the source image.
+    pub sha256: String,
+    /// Path of file inputs or remote URL; `None` for in-memory bytes and
+    /// `data:` URIs.
+    pub source: Option<String>,
+    /// Time at which the image was prepared for sending.
+    #[serde(with = "unix_millis")]
+    pub recorded_at: SystemTime,
+}
+
+impl Provenance {
+    /// Compute the provenance of `image`, reading files.
+    pub fn of(image: &ImageInput) -> Result<Self, Error> {
+        let (sha256, source) = match image {
+            ImageInput::Url(url) => match decode_data_uri(url) {
+                Some(decoded) => (hash(&decoded?.1), None),
+                None => (hash(url.as_bytes()), Some(url.clone())),
+            },
+            ImageInput::Bytes { data, .. } => (hash(data), None),
+            ImageInput::File { path, .. } => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+                (hex(&hasher.finalize()), Some(path.display().to_string()))
+            }
+            #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+            ImageInput::Mapped { file, .. } => {
+                (hash(file), Some(file.path().display().to_string()))
+            }
+        };
+        Ok(Self {
+            sha256,
+            source,
+            recorded_at: SystemTime::now(),
+        })
+    }
+
+    /// Provenance of the file at `path`, already read into `data`.
+    pub(crate) fn of_file(data: &[u8], path: &Path) -> Self {
+        Self {
+            sha256: hash(data),
+            source: Some(path.display().to_string()),
+            recorded_at: SystemTime::now(),
+        }
+    }
+
+    /// Return `true` if `image` has the hash recorded.
+    pub fn matches(&self, image: impl Into<ImageInput>) -> Result<bool, Error> {
+        Ok(Provenance::of(&image.into())?.sha256 == self.sha256)
+    }
+}
+
+fn hash(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .fold(String::with_capacity(64), |mut hash, byte| {
+            let _ = write!(hash, "{byte:02x}");
+            hash
+        })
+}
+
+/// Destination of the [`Provenance`] of the images sent.
+pub trait ProvenanceSink: std::fmt::Debug + Send + Sync {
+    /// Store `provenance`.
+    fn record(&self, provenance: &Provenance);
+}
+
+/// In-memory [`ProvenanceSink`], cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct ProvenanceLog(Arc<Mutex<Vec<Provenance>>>);
+
+impl ProvenanceLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Provenance recorded so far, oldest first.
+    pub fn entries(&self) -> Vec<Provenance> {
+        self.lock().clone()
+    }
+
+    /// Latest provenance recorded for the image with hash `sha256`.
+    pub fn find(&self, sha256: &str) -> Option<Provenance> {
+        self.lock()
+            .iter()
+            .rev()
+            .find(|provenance| provenance.sha256 == sha256)
+            .cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Provenance>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ProvenanceSink for ProvenanceLog {
+    fn record(&self, provenance: &Provenance) {
+        self.lock().push(provenance.clone());
+    }
+}
+
+impl MoonDream {
+    /// Record the [`Provenance`] of every image sent to `sink`, see the
+    /// [module documentation](crate::provenance).
+    pub fn with_provenance(mut self, sink: impl ProvenanceSink + 'static) -> Self {
+        self.provenance = Some(Arc::new(sink));
+        self
+    }
+}
+
+/// (De)serialize a time as milliseconds since the Unix epoch.
+mod unix_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+    use web_time::{SystemTime, UNIX_EPOCH};
+
+    pub(super) fn serialize<S: Serializer>(
+        time: &SystemTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        serializer.serialize_u64(millis)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SystemTime, D::Error> {
+        Ok(UNIX_EPOCH + Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_provenance_of_inputs() {
+        let bytes = Provenance::of(&ImageInput::bytes(b"abc".to_vec(), "image/png")).unwrap();
+        assert_eq!(
+            bytes.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(bytes.source, None);
+
+        let data_uri = Provenance::of(&"data:image/png;base64,YWJj".into()).unwrap();
+        assert_eq!(data_uri.sha256, bytes.sha256);
+
+        let remote = Provenance::of(&"https://example.com/a.png".into()).unwrap();
+        assert_eq!(remote.source.as_deref(), Some("https://example.com/a.png"));
+        assert!(
+            !remote
+                .matches(ImageInput::bytes(b"abc".to_vec(), "image/png"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_provenance_round_trip() {
+        let provenance = Provenance {
+            recorded_at: web_time::UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123),
+            ..Provenance::of(&"https://example.com/a.png".into()).unwrap()
+        };
+        let json = serde_json::to_value(&provenance).unwrap();
+        assert_eq!(json["recorded_at"], 1_700_000_000_123u64);
+        assert_eq!(json["source"], "https://example.com/a.png");
+        assert_eq!(
+            serde_json::from_value::<Provenance>(json).unwrap(),
+            provenance
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provenance_recorded_for_file() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "a scan",
+            })))
+            .mount(&server)
+            .await;
+
+        let file = std::env::temp_dir().join(format!("moondream-{}.png", uuid::Uuid::new_v4()));
+        std::fs::write(&file, b"scan").unwrap();
+
+        let log = ProvenanceLog::new();
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_provenance(log.clone());
+        let response = md
+            .caption_with_meta(ImageInput::file(&file), None)
+            .await
+            .unwrap();
+
+        let provenance = log.entries().pop().unwrap();
+        assert_eq!(response.meta.provenance, std::slice::from_ref(&provenance));
+        assert_eq!(provenance.source, Some(file.display().to_string()));
+        assert_eq!(log.find(&provenance.sha256), Some(provenance.clone()));
+        assert!(provenance.matches(ImageInput::file(&file)).unwrap());
+
+        std::fs::write(&file, b"edited").unwrap();
+        assert!(!provenance.matches(ImageInput::file(&file)).unwrap());
+        std::fs::remove_file(&file).unwrap();
+    }
+}
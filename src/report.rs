@@ -0,0 +1,365 @@
+//! Visual reports of results (feature `image`).
+//!
+//! [`contact_sheet`] lays out thumbnails in a grid, with their detected
+//! boxes and points drawn on top and a caption below each, so reviewers can
+//! check hundreds of results at a glance instead of opening every file.
+//! Captions use a small built-in bitmap font covering ASCII letters, digits
+//! and common punctuation; lowercase letters are shown in uppercase.
+//!
+//! ```no_run
+//! use moondream::report::{SheetItem, contact_sheet};
+//!
+//! # fn run(results: Vec<(std::path::PathBuf, moondream::DetectResponse)>) -> Result<(), Box<dyn std::error::Error>> {
+//! let items = results
+//!     .into_iter()
+//!     .map(|(path, response)| {
+//!         let caption = format!("{}: {} cars", path.display(), response.objects.len());
+//!         Ok(SheetItem::load(&path)?
+//!             .with_caption(caption)
+//!             .with_boxes(response.objects))
+//!     })
+//!     .collect::<Result<Vec<_>, moondream::Error>>()?;
+//! contact_sheet(&items, 6).save("review.png")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{DetectionObject, Error, Point};
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgb, RgbImage};
+use std::path::Path;
+
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const TEXT: Rgb<u8> = Rgb([240, 240, 240]);
+const POINT: Rgb<u8> = Rgb([255, 214, 0]);
+/// Box colors, cycled through so neighbouring boxes stay distinguishable.
+const BOXES: [Rgb<u8>; 4] = [
+    Rgb([230, 40, 40]),
+    Rgb([40, 200, 80]),
+    Rgb([50, 130, 240]),
+    Rgb([230, 90, 220]),
+];
+/// Margin around each thumbnail, in pixels.
+const PADDING: u32 = 8;
+/// Magnification of the 3x5 font.
+const FONT_SCALE: u32 = 2;
+/// Advance of one character, including spacing.
+const CHAR_WIDTH: u32 = 4 * FONT_SCALE;
+/// Height of one caption line, including spacing.
+const LINE_HEIGHT: u32 = 7 * FONT_SCALE;
+const CAPTION_LINES: u32 = 2;
+
+/// One cell of a contact sheet: an image and the results to draw on it.
+#[derive(Debug, Clone)]
+pub struct SheetItem {
+    /// Image shown as a thumbnail.
+    pub image: DynamicImage,
+    /// Text written below the thumbnail.
+    pub caption: Option<String>,
+    /// Boxes drawn on the thumbnail.
+    pub boxes: Vec<DetectionObject>,
+    /// Points drawn on the thumbnail.
+    pub points: Vec<Point>,
+}
+
+impl SheetItem {
+    /// Create an item without annotations.
+    pub fn new(image: DynamicImage) -> Self {
+        Self {
+            image,
+            caption: None,
+            boxes: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+
+    /// Open the image file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let image = image::open(path).map_err(|e| Error::InvalidImage(e.to_string()))?;
+        Ok(Self::new(image))
+    }
+
+    /// Write `caption` below the thumbnail.
+    pub fn with_caption(mut self, caption: impl Into<String>) -> Self {
+        self.caption = Some(caption.into());
+        self
+    }
+
+    /// Draw `boxes` on the thumbnail.
+    pub fn with_boxes(mut self, boxes: impl IntoIterator<Item = DetectionObject>) -> Self {
+        self.boxes.extend(boxes);
+        self
+    }
+
+    /// Draw `points` on the thumbnail.
+    pub fn with_points(mut self, points: impl IntoIterator<Item = Point>) -> Self {
+        self.points.extend(points);
+        self
+    }
+}
+
+/// Layout of a contact sheet.
+///
+/// Thumbnails fit in 256 x 256 pixels by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContactSheet {
+    columns: u32,
+    thumbnail_size: u32,
+}
+
+impl ContactSheet {
+    /// Lay thumbnails out in `columns` columns.
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns: columns.clamp(1, u32::MAX as usize) as u32,
+            thumbnail_size: 256,
+        }
+    }
+
+    /// Fit thumbnails in `size` x `size` pixels.
+    pub fn with_thumbnail_size(mut self, size: u32) -> Self {
+        self.thumbnail_size = size.max(16);
+        self
+    }
+
+    /// Draw the sheet.
+    pub fn render(&self, items: &[SheetItem]) -> RgbImage {
+        let cell_width = self.thumbnail_size + 2 * PADDING;
+        let cell_height = cell_width + CAPTION_LINES * LINE_HEIGHT;
+        let rows = (items.len() as u32).div_ceil(self.columns);
+        let mut sheet =
+            RgbImage::from_pixel(self.columns * cell_width, rows * cell_height, BACKGROUND);
+
+        for (index, item) in items.iter().enumerate() {
+            let index = index as u32;
+            let left = (index % self.columns) * cell_width;
+            let top = (index / self.columns) * cell_height;
+            self.draw_thumbnail(&mut sheet, item, left + PADDING, top + PADDING);
+            if let Some(caption) = &item.caption {
+                let max_chars = (self.thumbnail_size / CHAR_WIDTH) as usize;
+                let caption_top = top + cell_width;
+                for (line, text) in wrap(caption, max_chars).iter().enumerate() {
+                    let y = caption_top + line as u32 * LINE_HEIGHT;
+                    draw_text(&mut sheet, text, left + PADDING, y);
+                }
+            }
+        }
+        sheet
+    }
+
+    fn draw_thumbnail(&self, sheet: &mut RgbImage, item: &SheetItem, left: u32, top: u32) {
+        let size = self.thumbnail_size;
+        let thumbnail = item
+            .image
+            .resize(size, size, FilterType::Triangle)
+            .to_rgb8();
+        let (width, height) = thumbnail.dimensions();
+        let left = left + (size - width.min(size)) / 2;
+        let top = top + (size - height.min(size)) / 2;
+        image::imageops::replace(sheet, &thumbnail, i64::from(left), i64::from(top));
+
+        let x = |value: f64| left + (value.clamp(0.0, 1.0) * f64::from(width - 1)).round() as u32;
+        let y = |value: f64| top + (value.clamp(0.0, 1.0) * f64::from(height - 1)).round() as u32;
+        for (bbox, color) in item.boxes.iter().zip(BOXES.iter().cycle()) {
+            let (x0, y0) = (x(bbox.x_min), y(bbox.y_min));
+            let (x1, y1) = (x(bbox.x_max), y(bbox.y_max));
+            for offset in 0..2 {
+                draw_rect(
+                    sheet,
+                    x0 + offset,
+                    y0 + offset,
+                    x1.saturating_sub(offset),
+                    y1.saturating_sub(offset),
+                    *color,
+                );
+            }
+        }
+        for point in &item.points {
+            let (cx, cy) = (x(point.x), y(point.y));
+            fill(
+                sheet,
+                cx.saturating_sub(2),
+                cy.saturating_sub(2),
+                5,
+                5,
+                POINT,
+            );
+        }
+    }
+}
+
+/// Lay `items` out in a grid of `columns` columns with 256 pixel
+/// thumbnails, see [`ContactSheet`] for other sizes.
+pub fn contact_sheet(items: &[SheetItem], columns: usize) -> RgbImage {
+    ContactSheet::new(columns).render(items)
+}
+
+/// Split `text` into at most [`CAPTION_LINES`] lines of `max_chars`,
+/// ending with ".." when it does not fit.
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(3);
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    let mut words = text.split_whitespace().peekable();
+    while let Some(word) = words.peek() {
+        let len = line.chars().count();
+        let word_len = word.chars().count();
+        if len > 0 && len + 1 + word_len > max_chars {
+            lines.push(std::mem::take(&mut line));
+            if lines.len() as u32 == CAPTION_LINES {
+                break;
+            }
+            continue;
+        }
+        if len > 0 {
+            line.push(' ');
+        }
+        let room = max_chars - line.chars().count();
+        line.extend(word.chars().take(room));
+        words.next();
+    }
+    if !line.is_empty() && (lines.len() as u32) < CAPTION_LINES {
+        lines.push(line);
+    }
+    if words.peek().is_some()
+        && let Some(last) = lines.last_mut()
+    {
+        let keep = max_chars - 2;
+        *last = last.chars().take(keep).collect::<String>() + "..";
+    }
+    lines
+}
+
+fn draw_text(image: &mut RgbImage, text: &str, left: u32, top: u32) {
+    for (index, c) in text.chars().enumerate() {
+        let x = left + index as u32 * CHAR_WIDTH;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let px = x + column * FONT_SCALE;
+                    let py = top + row as u32 * FONT_SCALE;
+                    fill(image, px, py, FONT_SCALE, FONT_SCALE, TEXT);
+                }
+            }
+        }
+    }
+}
+
+fn draw_rect(image: &mut RgbImage, x0: u32, y0: u32, x1: u32, y1: u32, color: Rgb<u8>) {
+    if x1 < x0 || y1 < y0 {
+        return;
+    }
+    fill(image, x0, y0, x1 - x0 + 1, 1, color);
+    fill(image, x0, y1, x1 - x0 + 1, 1, color);
+    fill(image, x0, y0, 1, y1 - y0 + 1, color);
+    fill(image, x1, y0, 1, y1 - y0 + 1, color);
+}
+
+fn fill(image: &mut RgbImage, left: u32, top: u32, width: u32, height: u32, color: Rgb<u8>) {
+    let (max_x, max_y) = image.dimensions();
+    for y in top..(top + height).min(max_y) {
+        for x in left..(left + width).min(max_x) {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+/// Rows of the 3x5 glyph of `c`, the high bit being the left column.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_caption() {
+        assert_eq!(wrap("two red cars", 8), ["two red", "cars"]);
+        assert_eq!(
+            wrap("a very long caption that does not fit", 10),
+            ["a very", "long.."]
+        );
+        assert_eq!(wrap("supercalifragilistic", 8), ["supercal"]);
+    }
+
+    #[test]
+    fn test_contact_sheet_layout() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 50, Rgb([0, 0, 0])));
+        let bbox = DetectionObject {
+            x_min: 0.0,
+            y_min: 0.0,
+            x_max: 0.5,
+            y_max: 1.0,
+            confidence: None,
+        };
+        let items = [
+            SheetItem::new(image.clone())
+                .with_caption("car")
+                .with_boxes([bbox]),
+            SheetItem::new(image.clone()),
+            SheetItem::new(image),
+        ];
+
+        let sheet = ContactSheet::new(2).with_thumbnail_size(64).render(&items);
+        let cell = 64 + 2 * PADDING;
+        assert_eq!(sheet.dimensions(), (2 * cell, 2 * (cell + 28)));
+
+        // The 64x32 thumbnail is centered vertically, its box starts at the
+        // top left corner.
+        assert_eq!(*sheet.get_pixel(PADDING, PADDING + 16), BOXES[0]);
+        assert_eq!(*sheet.get_pixel(PADDING + 40, PADDING + 30), Rgb([0, 0, 0]));
+        // The "C" of the caption.
+        assert_eq!(*sheet.get_pixel(PADDING + 2, cell), TEXT);
+    }
+}
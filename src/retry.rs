@@ -0,0 +1,104 @@
+//! Retry policy for transient failures.
+
+use std::time::Duration;
+
+/// Controls how failed requests are retried.
+///
+/// Timeouts, connection errors, `429 Too Many Requests` and `5xx` responses
+/// are retried with exponential backoff; other errors are returned
+/// immediately. The default policy does not retry.
+///
+/// ```
+/// use moondream::{MoonDream, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let md = MoonDream::remote("token")
+///     .with_retry(RetryPolicy::new(3).with_initial_backoff(Duration::from_millis(500)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, starting with a 200 ms backoff that
+    /// doubles after each attempt, capped at 10 s.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+        }
+    }
+
+    /// Never retry.
+    pub fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Set the delay before the first retry.
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the upper bound of the delay between retries.
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor applied to the delay after each retry.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Maximum number of retries after the first attempt.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Delay before the given retry, starting at 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.saturating_sub(1) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * factor;
+        if secs >= self.max_backoff.as_secs_f64() {
+            self.max_backoff
+        } else {
+            Duration::from_secs_f64(secs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy::new(5)
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(350));
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+        assert_eq!(policy.backoff(10), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_default_does_not_retry() {
+        assert_eq!(RetryPolicy::default().max_retries(), 0);
+    }
+}
@@ -0,0 +1,54 @@
+//! Runtime helpers that differ between native targets and WebAssembly.
+//!
+//! `std::time::Instant` and `SystemTime` panic on `wasm32-unknown-unknown`,
+//! so the crate uses the `web-time` types, which are the `std` ones on every
+//! other target. Timers and background tasks use tokio natively and the
+//! browser event loop on wasm. Native clients share one HTTP connection
+//! pool; in the browser pooling is handled by `fetch`.
+
+use std::time::Duration;
+
+pub(crate) use web_time::{Instant, SystemTime};
+
+/// Wait for `duration`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+/// Wait for `duration`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    gloo_timers::future::sleep(duration).await;
+}
+
+/// Run `future` in the background.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    tokio::spawn(future);
+}
+
+/// Run `future` in the background.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn(future: impl Future<Output = ()> + 'static) {
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// HTTP client used by clients that were not given their own.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn shared_client() -> reqwest::Client {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            crate::HttpConfig::tuned()
+                .build()
+                .expect("failed to build the HTTP client")
+        })
+        .clone()
+}
+
+/// HTTP client used by clients that were not given their own.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn shared_client() -> reqwest::Client {
+    reqwest::Client::new()
+}
@@ -0,0 +1,292 @@
+//! Several operations on one image, run concurrently.
+//!
+//! [`MoonDream::scope`] decodes, preprocesses and encodes an image once,
+//! into an [`EncodedImage`], and returns a [`Scope`] whose operations all
+//! send it. [`Scope::join`], [`Scope::join3`] and [`Scope::join_all`] run
+//! operations concurrently and return their typed results. By default the
+//! first failure cancels the pending siblings and is returned; with
+//! [`Scope::with_fail_fast`] set to `false`, siblings run to completion
+//! (filling the [`Cache`](crate::Cache), for example) before the first
+//! failure is returned.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//!
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let s = md.scope("https://example.com/street.jpg")?;
+//! let (cars, caption) = s.join(s.detect("car"), s.caption(None)).await?;
+//! let people = s
+//!     .join_all(["adult", "child"].map(|object| s.point(object)))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    ApiResponse, CaptionLength, CaptionResponse, DetectResponse, Error, ImageInput, MoonDream,
+    PointsResponse, QueryResponse, SegmentResponse,
+};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// An image prepared for sending, cheap to clone.
+///
+/// Converts back into an [`ImageInput::Url`] holding the URL or `data:` URI
+/// sent to the API.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EncodedImage(Arc<str>);
+
+impl EncodedImage {
+    /// Value sent as `image_url`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for EncodedImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<EncodedImage> for ImageInput {
+    fn from(image: EncodedImage) -> Self {
+        ImageInput::Url(image.0.to_string())
+    }
+}
+
+/// Operations sharing one [`EncodedImage`], see the
+/// [module documentation](crate::scope).
+#[derive(Debug, Clone)]
+pub struct Scope {
+    md: MoonDream,
+    image: EncodedImage,
+    cancellation: CancellationToken,
+    fail_fast: bool,
+}
+
+impl MoonDream {
+    /// Prepare `image` once for several calls, running the decoders and the
+    /// preprocessor and enforcing the maximum image size.
+    pub fn encode(&self, image: impl Into<ImageInput>) -> Result<EncodedImage, Error> {
+        self.prepare_image(image)
+            .map(|image| EncodedImage(image.into()))
+    }
+
+    /// Start a [`Scope`] of operations on `image`.
+    ///
+    /// Cancelling the token of the client [`RequestOptions`](crate::RequestOptions)
+    /// cancels the operations of the scope too.
+    pub fn scope(&self, image: impl Into<ImageInput>) -> Result<Scope, Error> {
+        let image = self.encode(image)?;
+        let cancellation = self
+            .options
+            .cancellation()
+            .map_or_else(CancellationToken::new, CancellationToken::child_token);
+        let options = self.options.clone().with_cancellation(cancellation.clone());
+        Ok(Scope {
+            md: self.clone().with_options(options),
+            image,
+            cancellation,
+            fail_fast: true,
+        })
+    }
+}
+
+impl Scope {
+    /// Cancel the pending operations on the first failure (default), or let
+    /// them complete. Once cancelled, later operations of the scope fail with
+    /// [`Error::Cancelled`].
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Image sent by the operations.
+    pub fn image(&self) -> &EncodedImage {
+        &self.image
+    }
+
+    /// See [`MoonDream::caption`].
+    pub async fn caption(&self, length: Option<CaptionLength>) -> Result<CaptionResponse, Error> {
+        self.md
+            .caption_prepared(self.image.as_str(), length)
+            .await
+            .map(ApiResponse::into_inner)
+    }
+
+    /// See [`MoonDream::query`].
+    pub async fn query(&self, question: impl Into<String>) -> Result<QueryResponse, Error> {
+        self.md
+            .query_prepared(self.image.as_str(), question.into())
+            .await
+            .map(ApiResponse::into_inner)
+    }
+
+    /// See [`MoonDream::detect`].
+    pub async fn detect(&self, object: impl Into<String>) -> Result<DetectResponse, Error> {
+        self.object_call("detect", object.into()).await
+    }
+
+    /// See [`MoonDream::points`].
+    pub async fn point(&self, object: impl Into<String>) -> Result<PointsResponse, Error> {
+        self.object_call("point", object.into()).await
+    }
+
+    /// See [`MoonDream::segment`].
+    pub async fn segment(&self, object: impl Into<String>) -> Result<SegmentResponse, Error> {
+        self.object_call("segment", object.into()).await
+    }
+
+    async fn object_call<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        object: String,
+    ) -> Result<T, Error> {
+        let body = self.md.object_request(self.image.to_string(), object);
+        self.md.send(path, body).await.map(ApiResponse::into_inner)
+    }
+
+    /// Run `future`, cancelling the siblings if it fails and the scope
+    /// fails fast.
+    async fn run<T>(&self, future: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        let result = future.await;
+        if result.is_err() && self.fail_fast {
+            self.cancellation.cancel();
+        }
+        result
+    }
+
+    /// Run two operations concurrently.
+    pub async fn join<A, B>(
+        &self,
+        a: impl Future<Output = Result<A, Error>>,
+        b: impl Future<Output = Result<B, Error>>,
+    ) -> Result<(A, B), Error> {
+        match futures::future::join(self.run(a), self.run(b)).await {
+            (Ok(a), Ok(b)) => Ok((a, b)),
+            (a, b) => Err(first_failure([a.err(), b.err()])),
+        }
+    }
+
+    /// Run three operations concurrently.
+    pub async fn join3<A, B, C>(
+        &self,
+        a: impl Future<Output = Result<A, Error>>,
+        b: impl Future<Output = Result<B, Error>>,
+        c: impl Future<Output = Result<C, Error>>,
+    ) -> Result<(A, B, C), Error> {
+        match futures::future::join3(self.run(a), self.run(b), self.run(c)).await {
+            (Ok(a), Ok(b), Ok(c)) => Ok((a, b, c)),
+            (a, b, c) => Err(first_failure([a.err(), b.err(), c.err()])),
+        }
+    }
+
+    /// Run operations of the same type concurrently, returning their results
+    /// in order.
+    pub async fn join_all<T, F>(
+        &self,
+        operations: impl IntoIterator<Item = F>,
+    ) -> Result<Vec<T>, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let results =
+            futures::future::join_all(operations.into_iter().map(|future| self.run(future))).await;
+        let mut values = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(error) => errors.push(Some(error)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(values)
+        } else {
+            Err(first_failure(errors))
+        }
+    }
+}
+
+/// The error that caused the failure, rather than the cancellations it
+/// triggered.
+fn first_failure(errors: impl IntoIterator<Item = Option<Error>>) -> Error {
+    let mut cancelled = None;
+    for error in errors.into_iter().flatten() {
+        match error {
+            Error::Cancelled => cancelled = Some(error),
+            error => return error,
+        }
+    }
+    cancelled.unwrap_or(Error::Cancelled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_scope_runs_operations_on_one_image() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objects": [{"x_min": 0.1, "y_min": 0.1, "x_max": 0.2, "y_max": 0.2}],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "a street",
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let s = md.scope("data:image/png;base64,AAAA").unwrap();
+        let (cars, caption) = s.join(s.detect("car"), s.caption(None)).await.unwrap();
+        assert_eq!(cars.objects.len(), 1);
+        assert_eq!(caption.caption, "a street");
+
+        let all = s
+            .join_all(["car", "bus"].map(|o| s.detect(o)))
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scope_cancels_siblings_on_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "caption": "slow" }))
+                    .set_delay(Duration::from_secs(3)),
+            )
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token")
+            .with_endpoint(server.uri())
+            .with_timeout(Duration::from_secs(10));
+        let s = md.scope("img").unwrap();
+        let start = std::time::Instant::now();
+        let error = s.join(s.caption(None), s.detect("car")).await.unwrap_err();
+        assert!(
+            matches!(&error, Error::Status { status, .. } if *status == reqwest::StatusCode::BAD_REQUEST)
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,283 @@
+//! Segmentation: outlines of objects instead of bounding boxes.
+//!
+//! [`MoonDream::segment`] calls the `/segment` endpoint of deployments that
+//! support it, see [`Capabilities::segmentation`](crate::Capabilities::segmentation).
+//! The server answers with polygons whose vertices are normalized to the
+//! image dimensions, like detection boxes:
+//!
+//! ```json
+//! {"request_id": "abc", "polygons": [{"vertices": [[0.1, 0.2], [0.4, 0.2], [0.3, 0.6]]}]}
+//! ```
+//!
+//! [`Polygon::rasterize`] and [`SegmentResponse::mask`] turn the polygons
+//! into a boolean [`Mask`] at any resolution, for example the size of the
+//! source image.
+
+use crate::{ApiResponse, DetectionObject, Error, ImageInput, MoonDream};
+use serde::{Deserialize, Serialize};
+
+/// Response returned by the `/segment` endpoint.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct SegmentResponse {
+    /// Unique request identifier returned by the API.
+    pub request_id: Option<String>,
+    /// Outline of each segmented object.
+    pub polygons: Vec<Polygon>,
+}
+
+impl SegmentResponse {
+    /// Rasterize every polygon into one mask of `width` x `height` pixels.
+    pub fn mask(&self, width: u32, height: u32) -> Mask {
+        let mut mask = Mask::new(width, height);
+        for polygon in &self.polygons {
+            polygon.fill(&mut mask);
+        }
+        mask
+    }
+}
+
+/// A closed polygon with normalized vertices.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct Polygon {
+    /// `(x, y)` vertices, normalized to the image dimensions (0-1). The
+    /// last vertex connects back to the first one.
+    pub vertices: Vec<(f64, f64)>,
+}
+
+impl Polygon {
+    /// Iterate over the edges of the polygon, including the closing one.
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        let next = self.vertices.iter().cycle().skip(1);
+        self.vertices.iter().copied().zip(next.copied())
+    }
+
+    /// Area of the polygon, in normalized units.
+    pub fn area(&self) -> f64 {
+        let twice: f64 = self
+            .edges()
+            .map(|((x0, y0), (x1, y1))| x0 * y1 - x1 * y0)
+            .sum();
+        twice.abs() / 2.0
+    }
+
+    /// Return `true` if the normalized point `(x, y)` is inside the polygon.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        crossings(self, y).filter(|&crossing| crossing > x).count() % 2 == 1
+    }
+
+    /// Smallest box enclosing the polygon, or `None` without vertices.
+    pub fn bounding_box(&self) -> Option<DetectionObject> {
+        let (&(x, y), rest) = self.vertices.split_first()?;
+        let init = DetectionObject {
+            x_min: x,
+            y_min: y,
+            x_max: x,
+            y_max: y,
+            confidence: None,
+        };
+        Some(rest.iter().fold(init, |bbox, &(x, y)| DetectionObject {
+            x_min: bbox.x_min.min(x),
+            y_min: bbox.y_min.min(y),
+            x_max: bbox.x_max.max(x),
+            y_max: bbox.y_max.max(y),
+            confidence: None,
+        }))
+    }
+
+    /// Rasterize the polygon into a mask of `width` x `height` pixels.
+    ///
+    /// A pixel is set when its centre is inside the polygon.
+    pub fn rasterize(&self, width: u32, height: u32) -> Mask {
+        let mut mask = Mask::new(width, height);
+        self.fill(&mut mask);
+        mask
+    }
+
+    /// Set the pixels of `mask` inside the polygon, one row at a time.
+    fn fill(&self, mask: &mut Mask) {
+        let (width, height) = (mask.width as f64, mask.height as f64);
+        for row in 0..mask.height {
+            let y = (row as f64 + 0.5) / height;
+            let mut xs: Vec<f64> = crossings(self, y).collect();
+            xs.sort_by(f64::total_cmp);
+            for span in xs.chunks_exact(2) {
+                // First and past-the-end columns whose centres are in the span.
+                let start = (span[0] * width - 0.5).ceil().clamp(0.0, width) as u32;
+                let end = (span[1] * width - 0.5).ceil().clamp(0.0, width) as u32;
+                for column in start..end {
+                    mask.set(column, row, true);
+                }
+            }
+        }
+    }
+}
+
+/// Abscissas where the edges of `polygon` cross the horizontal line at `y`.
+fn crossings(polygon: &Polygon, y: f64) -> impl Iterator<Item = f64> + '_ {
+    polygon
+        .edges()
+        .filter(move |&((_, y0), (_, y1))| (y0 <= y) != (y1 <= y))
+        .map(move |((x0, y0), (x1, y1))| x0 + (y - y0) * (x1 - x0) / (y1 - y0))
+}
+
+/// A boolean mask, stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mask {
+    width: u32,
+    height: u32,
+    pixels: Vec<bool>,
+}
+
+impl Mask {
+    /// Create an empty mask of `width` x `height` pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![false; width as usize * height as usize],
+        }
+    }
+
+    /// Width of the mask, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the mask, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Value of the pixel at `(x, y)`; `false` outside the mask.
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.pixels[self.index(x, y)]
+    }
+
+    /// Set the pixel at `(x, y)`, which must be inside the mask.
+    pub fn set(&mut self, x: u32, y: u32, value: bool) {
+        let index = self.index(x, y);
+        self.pixels[index] = value;
+    }
+
+    /// Pixels row by row, from the top left corner.
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+
+    /// Number of pixels set.
+    pub fn count(&self) -> usize {
+        self.pixels.iter().filter(|&&pixel| pixel).count()
+    }
+
+    /// Share of the pixels set, from 0 to 1.
+    pub fn coverage(&self) -> f64 {
+        if self.pixels.is_empty() {
+            0.0
+        } else {
+            self.count() as f64 / self.pixels.len() as f64
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel outside the mask");
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+impl MoonDream {
+    /// Segment the objects matching `object`, see the
+    /// [module documentation](crate::segment).
+    pub async fn segment(
+        &self,
+        image: impl Into<ImageInput>,
+        object: impl Into<String>,
+    ) -> Result<SegmentResponse, Error> {
+        self.segment_with_meta(image, object)
+            .await
+            .map(ApiResponse::into_inner)
+    }
+
+    /// Same as [`MoonDream::segment`], also returning the
+    /// [`ResponseMeta`](crate::ResponseMeta).
+    pub async fn segment_with_meta(
+        &self,
+        image: impl Into<ImageInput>,
+        object: impl Into<String>,
+    ) -> Result<ApiResponse<SegmentResponse>, Error> {
+        let (image, provenance) = self.prepare_traced(image.into())?;
+        self.send::<SegmentResponse>("segment", self.object_request(image, object.into()))
+            .await
+            .map(|response| response.with_provenance(provenance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn square(min: f64, max: f64) -> Polygon {
+        Polygon {
+            vertices: vec![(min, min), (max, min), (max, max), (min, max)],
+        }
+    }
+
+    #[test]
+    fn test_polygon_geometry() {
+        let triangle = Polygon {
+            vertices: vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        };
+        assert_eq!(triangle.area(), 0.5);
+        assert!(triangle.contains(0.2, 0.2));
+        assert!(!triangle.contains(0.6, 0.6));
+
+        let bbox = square(0.25, 0.75).bounding_box().unwrap();
+        assert_eq!((bbox.x_min, bbox.y_max), (0.25, 0.75));
+        assert_eq!(Polygon { vertices: vec![] }.bounding_box(), None);
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let mask = square(0.25, 0.75).rasterize(4, 4);
+        let expected = [
+            "....", //
+            ".##.", //
+            ".##.", //
+            "....",
+        ];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                assert_eq!(mask.get(x as u32, y as u32), pixel == '#', "({x}, {y})");
+            }
+        }
+        assert_eq!(mask.coverage(), 0.25);
+
+        let response = SegmentResponse {
+            request_id: None,
+            polygons: vec![square(0.0, 0.5), square(0.25, 0.75)],
+        };
+        assert_eq!(response.mask(4, 4).count(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_segment() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/segment"))
+            .and(body_partial_json(serde_json::json!({ "object": "cat" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "seg1",
+                "polygons": [{"vertices": [[0.1, 0.2], [0.4, 0.2], [0.3, 0.6]]}],
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let response = md.segment("img", "cat").await.unwrap();
+        assert_eq!(response.request_id.as_deref(), Some("seg1"));
+        assert_eq!(response.polygons[0].vertices[2], (0.3, 0.6));
+    }
+}
@@ -0,0 +1,182 @@
+//! Request instrumentation (feature `tracing`).
+//!
+//! Every request runs inside a `moondream.request` span at `INFO` level with
+//! the fields `endpoint`, `payload_size`, `client_request_id`, `request_id`,
+//! `status`, `attempt`, `cached` and `elapsed_ms`. Retries and failures are
+//! reported as `DEBUG` events inside the span. Without the feature every
+//! helper is a no-op.
+//!
+//! With [`MoonDream::with_log_bodies`](crate::MoonDream::with_log_bodies),
+//! request and response bodies are also logged as `DEBUG` events. Inline
+//! images are replaced by the start of their SHA-256 hash and their size, so
+//! logs stay small and free of image content.
+
+use crate::Error;
+use std::time::Duration;
+
+/// Fields holding images in request bodies.
+const IMAGE_FIELDS: [&str; 2] = ["image_url", "image_urls"];
+
+/// Render `body` for logging, with inline images redacted.
+pub(crate) fn redact_body(body: &[u8]) -> String {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            if let Some(fields) = value.as_object_mut() {
+                for (name, field) in fields {
+                    if IMAGE_FIELDS.contains(&name.as_str()) {
+                        redact_images(field);
+                    }
+                }
+            }
+            value.to_string()
+        }
+        Err(_) => format!("<{} bytes of non-JSON data>", body.len()),
+    }
+}
+
+/// Replace every data URI in `value` by a short description.
+fn redact_images(value: &mut serde_json::Value) {
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+
+    match value {
+        Value::String(image) if image.starts_with("data:") => {
+            let hash: String = Sha256::digest(image.as_bytes())
+                .iter()
+                .take(6)
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            *image = format!("<image sha256={hash}... bytes={}>", image.len());
+        }
+        Value::Array(images) => images.iter_mut().for_each(redact_images),
+        _ => {}
+    }
+}
+
+/// Span covering one logical request, including its retries.
+pub(crate) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl RequestSpan {
+    pub(crate) fn new(url: &str, payload_size: usize, client_request_id: &str) -> Self {
+        use tracing::field::Empty;
+
+        Self {
+            span: tracing::info_span!(
+                "moondream.request",
+                endpoint = url,
+                payload_size,
+                client_request_id,
+                request_id = Empty,
+                status = Empty,
+                attempt = Empty,
+                cached = Empty,
+                elapsed_ms = Empty
+            ),
+        }
+    }
+
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        tracing::Instrument::instrument(future, self.span.clone())
+    }
+
+    pub(crate) fn cache_hit(&self, elapsed: Duration) {
+        self.span.record("cached", true);
+        self.span.record("elapsed_ms", elapsed.as_millis() as u64);
+    }
+
+    pub(crate) fn retry(&self, attempt: u32, error: &Error, backoff: Duration) {
+        tracing::debug!(
+            parent: &self.span,
+            attempt,
+            error = %error,
+            backoff_ms = backoff.as_millis() as u64,
+            "retrying request"
+        );
+    }
+
+    pub(crate) fn failure(&self, attempt: u32, error: &Error, elapsed: Duration) {
+        let status = match error {
+            Error::PointError(error) => error.status(),
+            Error::Status { status, .. } | Error::ModelLoading { status, .. } => Some(*status),
+            _ => None,
+        };
+        if let Some(status) = status {
+            self.span.record("status", status.as_u16());
+        }
+        self.span.record("attempt", attempt);
+        self.span.record("elapsed_ms", elapsed.as_millis() as u64);
+        tracing::debug!(parent: &self.span, attempt, error = %error, "request failed");
+    }
+
+    pub(crate) fn request_body(&self, body: &[u8]) {
+        tracing::debug!(parent: &self.span, body = %redact_body(body), "request body");
+    }
+
+    pub(crate) fn response_body(&self, body: &[u8]) {
+        tracing::debug!(parent: &self.span, body = %redact_body(body), "response body");
+    }
+
+    pub(crate) fn success(&self, status: u16, attempt: u32, body: &[u8], elapsed: Duration) {
+        #[derive(serde::Deserialize)]
+        struct RequestId {
+            request_id: Option<String>,
+        }
+
+        if let Ok(RequestId {
+            request_id: Some(request_id),
+        }) = serde_json::from_slice(body)
+        {
+            self.span.record("request_id", request_id.as_str());
+        }
+        self.span.record("status", status);
+        self.span.record("attempt", attempt);
+        self.span.record("cached", false);
+        self.span.record("elapsed_ms", elapsed.as_millis() as u64);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl RequestSpan {
+    pub(crate) fn new(_url: &str, _payload_size: usize, _client_request_id: &str) -> Self {
+        Self {}
+    }
+
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> F {
+        future
+    }
+
+    pub(crate) fn cache_hit(&self, _elapsed: Duration) {}
+
+    pub(crate) fn retry(&self, _attempt: u32, _error: &Error, _backoff: Duration) {}
+
+    pub(crate) fn failure(&self, _attempt: u32, _error: &Error, _elapsed: Duration) {}
+
+    pub(crate) fn request_body(&self, _body: &[u8]) {}
+
+    pub(crate) fn response_body(&self, _body: &[u8]) {}
+
+    pub(crate) fn success(&self, _status: u16, _attempt: u32, _body: &[u8], _elapsed: Duration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_body() {
+        let body = br#"{"image_url": "data:image/png;base64,AAAA", "question": "What?"}"#;
+        let redacted = redact_body(body);
+
+        assert!(redacted.contains("<image sha256="));
+        assert!(redacted.contains("bytes=26>"));
+        assert!(redacted.contains("\"question\":\"What?\""));
+        assert!(!redacted.contains("AAAA"));
+
+        let body = br#"{"image_url": "https://example.com/a.png"}"#;
+        assert!(redact_body(body).contains("https://example.com/a.png"));
+    }
+}
@@ -0,0 +1,187 @@
+//! Request template shared by the clones of a client.
+//!
+//! The authentication and custom headers, the authentication query parameter, and the URL of every
+//! endpoint, are validated and built on first use instead of on every call.
+//! URLs are kept for the primary endpoint and each failover endpoint.
+//! Setters changing the endpoint or the headers start a new template.
+
+use crate::{Auth, Error};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{RequestBuilder, Url};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Lazily built [`RequestTemplate`], shared between clones.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TemplateCell(Arc<OnceLock<RequestTemplate>>);
+
+impl TemplateCell {
+    /// Return the template, building it from the client settings on first
+    /// use.
+    pub(crate) fn get_or_build(
+        &self,
+        endpoint: &str,
+        fallbacks: &[String],
+        auth: &Auth,
+        token: &str,
+        headers: &[(String, String)],
+    ) -> Result<&RequestTemplate, Error> {
+        if let Some(template) = self.0.get() {
+            return Ok(template);
+        }
+        let template = RequestTemplate::new(endpoint, fallbacks, auth, token, headers)?;
+        Ok(self.0.get_or_init(|| template))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RequestTemplate {
+    headers: HeaderMap,
+    query: Option<(String, String)>,
+    endpoints: Vec<EndpointUrls>,
+}
+
+/// URLs built for one endpoint.
+#[derive(Debug)]
+struct EndpointUrls {
+    base: String,
+    urls: Mutex<HashMap<String, Url>>,
+}
+
+impl RequestTemplate {
+    fn new(
+        endpoint: &str,
+        fallbacks: &[String],
+        auth: &Auth,
+        token: &str,
+        extra: &[(String, String)],
+    ) -> Result<Self, Error> {
+        let mut headers = HeaderMap::new();
+        let query = auth.apply(token, &mut headers)?;
+        for (name, value) in extra {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::InvalidConfig(format!("header name {name:?}: {e}")))?;
+            headers.append(name, header_value(value)?);
+        }
+
+        let endpoints = std::iter::once(endpoint)
+            .chain(fallbacks.iter().map(String::as_str))
+            .map(|endpoint| {
+                let base = endpoint.trim_end_matches('/').to_string();
+                Url::parse(&base)
+                    .map_err(|e| Error::InvalidConfig(format!("endpoint {base:?}: {e}")))?;
+                Ok(EndpointUrls {
+                    base,
+                    urls: Mutex::new(HashMap::new()),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            headers,
+            query,
+            endpoints,
+        })
+    }
+
+    /// Add the headers and query parameter sent with every request to
+    /// `request`. The content type depends on the body and is left out.
+    pub(crate) fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.headers(self.headers.clone());
+        match &self.query {
+            Some(query) => request.query(&[query]),
+            None => request,
+        }
+    }
+
+    /// Headers sent with every request.
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// `url` with the query parameter sent with every request, if any.
+    pub(crate) fn with_query(&self, url: &Url) -> String {
+        match &self.query {
+            Some((name, value)) => {
+                let mut url = url.clone();
+                url.query_pairs_mut().append_pair(name, value);
+                url.into()
+            }
+            None => url.to_string(),
+        }
+    }
+
+    /// URL of `{endpoint}/{path}` on the primary endpoint.
+    pub(crate) fn url(&self, path: &str) -> Result<Url, Error> {
+        self.url_at(0, path)
+    }
+
+    /// URL of `{endpoint}/{path}` on endpoint `index`, the primary one
+    /// being 0.
+    pub(crate) fn url_at(&self, index: usize, path: &str) -> Result<Url, Error> {
+        let endpoint = &self.endpoints[index];
+        let mut urls = endpoint.urls.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(url) = urls.get(path) {
+            return Ok(url.clone());
+        }
+        let url = Url::parse(&format!("{}/{path}", endpoint.base))
+            .map_err(|e| Error::InvalidConfig(format!("endpoint path {path:?}: {e}")))?;
+        urls.insert(path.to_string(), url.clone());
+        Ok(url)
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, Error> {
+    HeaderValue::from_str(value).map_err(|e| Error::InvalidConfig(format!("header value: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_is_built_once() {
+        let cell = TemplateCell::default();
+        let headers = [("X-Team".to_string(), "vision".to_string())];
+        let template = cell
+            .get_or_build(
+                "http://localhost:2020/v1/",
+                &["http://backup:2020/v1".to_string()],
+                &Auth::MoondreamHeader,
+                "token",
+                &headers,
+            )
+            .unwrap();
+        assert_eq!(
+            template.url("query").unwrap().as_str(),
+            "http://localhost:2020/v1/query"
+        );
+        assert_eq!(
+            template.url_at(1, "query").unwrap().as_str(),
+            "http://backup:2020/v1/query"
+        );
+        let request = template
+            .authorize(reqwest::Client::new().get(template.url("query").unwrap()))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["x-moondream-auth"], "token");
+        assert_eq!(request.headers()["x-team"], "vision");
+
+        let shared = cell.clone();
+        let again = shared
+            .get_or_build("ignored", &[], &Auth::None, "ignored", &[])
+            .unwrap();
+        assert!(std::ptr::eq(template, again));
+
+        let bad = [("X-Team".to_string(), "line\nbreak".to_string())];
+        assert!(matches!(
+            TemplateCell::default().get_or_build(
+                "http://localhost",
+                &[],
+                &Auth::MoondreamHeader,
+                "token",
+                &bad
+            ),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,310 @@
+//! Per-tenant clients for multi-tenant backends.
+//!
+//! A [`MoonDreamFactory`] mints a [`MoonDream`] client per tenant from a base
+//! client. Tenant clients share the connection pool and the settings of the
+//! base client, but each sends its own token and has its own
+//! [`TenantLimits`] and [usage](crate::usage) counters, so one tenant cannot
+//! exhaust the quota of another. Clients minted for the same tenant ID share
+//! its limits and counters.
+//!
+//! Tenant clients do not use the [`Cache`](crate::Cache) of the base client,
+//! which would let a tenant see what others sent; give them their own with
+//! [`MoonDream::with_cache`].
+//!
+//! ```
+//! use moondream::tenant::{MoonDreamFactory, TenantLimits};
+//! use moondream::MoonDream;
+//! use std::time::Duration;
+//!
+//! let factory = MoonDreamFactory::new(MoonDream::remote(""))
+//!     .with_limits(TenantLimits::new().with_rate_limit(10, Duration::from_secs(1)));
+//! let acme = factory.tenant("acme", "acme-token");
+//! let globex = factory.tenant_with_limits(
+//!     "globex",
+//!     "globex-token",
+//!     TenantLimits::new().with_budget(10_000),
+//! );
+//! ```
+
+use crate::rt::{self, Instant};
+use crate::template::TemplateCell;
+use crate::{Error, MoonDream, UsageSnapshot, UsageTracker};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Rate limit and budget of a tenant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantLimits {
+    rate: Option<(u32, Duration)>,
+    budget: Option<u64>,
+}
+
+impl TenantLimits {
+    /// No limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send at most `requests` requests per `period`, waiting for the next
+    /// slot when the limit is reached. Bursts of up to `requests` requests
+    /// are allowed.
+    pub fn with_rate_limit(mut self, requests: u32, period: Duration) -> Self {
+        self.rate = (requests > 0 && !period.is_zero()).then_some((requests, period));
+        self
+    }
+
+    /// Send at most `requests` requests in total, retries included. Once it
+    /// is spent, calls fail with [`Error::BudgetExhausted`].
+    pub fn with_budget(mut self, requests: u64) -> Self {
+        self.budget = Some(requests);
+        self
+    }
+}
+
+/// Rate limiter and budget shared by the clients of a tenant.
+#[derive(Debug, Clone)]
+pub(crate) struct Quota(Arc<Mutex<QuotaState>>);
+
+#[derive(Debug)]
+struct QuotaState {
+    limits: TenantLimits,
+    tokens: f64,
+    refilled_at: Instant,
+    spent: u64,
+}
+
+impl Quota {
+    fn new(limits: TenantLimits) -> Self {
+        Self(Arc::new(Mutex::new(QuotaState {
+            limits,
+            tokens: limits.rate.map_or(0.0, |(requests, _)| requests as f64),
+            refilled_at: Instant::now(),
+            spent: 0,
+        })))
+    }
+
+    /// Replace the limits and reset the budget and rate limit, for every
+    /// client sharing the quota.
+    fn set_limits(&self, limits: TenantLimits) {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        state.limits = limits;
+        state.tokens = limits.rate.map_or(0.0, |(requests, _)| requests as f64);
+        state.refilled_at = Instant::now();
+        state.spent = 0;
+    }
+
+    /// Wait for the rate limit and take one request from the budget.
+    pub(crate) async fn acquire(&self) -> Result<(), Error> {
+        loop {
+            let wait = {
+                let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(budget) = state.limits.budget
+                    && state.spent >= budget
+                {
+                    return Err(Error::BudgetExhausted { budget });
+                }
+                match state.limits.rate {
+                    None => Duration::ZERO,
+                    Some((requests, period)) => {
+                        let per_second = requests as f64 / period.as_secs_f64();
+                        let now = Instant::now();
+                        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+                        state.tokens = (state.tokens + elapsed * per_second).min(requests as f64);
+                        state.refilled_at = now;
+                        if state.tokens >= 1.0 {
+                            state.tokens -= 1.0;
+                            Duration::ZERO
+                        } else {
+                            Duration::from_secs_f64((1.0 - state.tokens) / per_second)
+                        }
+                    }
+                }
+            };
+            if wait.is_zero() {
+                let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+                state.spent += 1;
+                return Ok(());
+            }
+            rt::sleep(wait).await;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Tenant {
+    quota: Quota,
+    usage: UsageTracker,
+}
+
+/// Mints isolated per-tenant clients from a base client, see the
+/// [module documentation](crate::tenant).
+#[derive(Debug, Clone)]
+pub struct MoonDreamFactory {
+    base: MoonDream,
+    limits: TenantLimits,
+    tenants: Arc<Mutex<HashMap<String, Tenant>>>,
+}
+
+impl MoonDreamFactory {
+    /// Mint clients configured like `base`.
+    pub fn new(base: MoonDream) -> Self {
+        Self {
+            base,
+            limits: TenantLimits::default(),
+            tenants: Arc::default(),
+        }
+    }
+
+    /// Limits of the tenants that do not have their own.
+    pub fn with_limits(mut self, limits: TenantLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Client of tenant `id`, authenticated with `token`.
+    pub fn tenant(&self, id: &str, token: impl Into<String>) -> MoonDream {
+        let tenant = self
+            .lock()
+            .entry(id.to_string())
+            .or_insert_with(|| Tenant {
+                quota: Quota::new(self.limits),
+                usage: UsageTracker::new(),
+            })
+            .clone();
+        self.mint(tenant, token.into())
+    }
+
+    /// Client of tenant `id` with its own `limits`, replacing the limits
+    /// and resetting the budget of its existing clients.
+    pub fn tenant_with_limits(
+        &self,
+        id: &str,
+        token: impl Into<String>,
+        limits: TenantLimits,
+    ) -> MoonDream {
+        let tenant = match self.lock().entry(id.to_string()) {
+            Entry::Occupied(entry) => {
+                entry.get().quota.set_limits(limits);
+                entry.get().clone()
+            }
+            Entry::Vacant(entry) => entry
+                .insert(Tenant {
+                    quota: Quota::new(limits),
+                    usage: UsageTracker::new(),
+                })
+                .clone(),
+        };
+        self.mint(tenant, token.into())
+    }
+
+    /// Usage recorded by the clients of tenant `id`.
+    pub fn usage(&self, id: &str) -> Option<UsageSnapshot> {
+        self.lock().get(id).map(|tenant| tenant.usage.snapshot())
+    }
+
+    /// Forget tenant `id`; its existing clients keep their limits.
+    pub fn remove(&self, id: &str) -> bool {
+        self.lock().remove(id).is_some()
+    }
+
+    fn mint(&self, tenant: Tenant, token: String) -> MoonDream {
+        let mut md = self.base.clone();
+        md.token = token;
+        md.template = TemplateCell::default();
+        md.cache = None;
+        md.usage = tenant.usage;
+        md.quota = Some(tenant.quota);
+        md.recent_detections = Default::default();
+        md
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Tenant>> {
+        self.tenants.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_tenants_are_isolated() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("x-moondream-auth", "acme-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "acme",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header("x-moondream-auth", "globex-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "globex",
+            })))
+            .mount(&server)
+            .await;
+
+        let base = MoonDream::remote("").with_endpoint(server.uri());
+        let factory = MoonDreamFactory::new(base).with_limits(TenantLimits::new().with_budget(1));
+        let acme = factory.tenant("acme", "acme-token");
+        let globex = factory.tenant("globex", "globex-token");
+
+        assert_eq!(acme.caption("img", None).await.unwrap().caption, "acme");
+        let error = factory
+            .tenant("acme", "acme-token")
+            .caption("img", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::BudgetExhausted { budget: 1 }));
+        assert_eq!(globex.caption("img", None).await.unwrap().caption, "globex");
+
+        assert_eq!(factory.usage("acme").unwrap().total().requests, 1);
+        assert_eq!(factory.usage("acme").unwrap().total().errors, 1);
+        assert_eq!(factory.usage("globex").unwrap().total().requests, 1);
+        assert!(factory.usage("initech").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_new_limits_apply_to_existing_clients() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "caption": "acme",
+            })))
+            .mount(&server)
+            .await;
+
+        let base = MoonDream::remote("").with_endpoint(server.uri());
+        let factory = MoonDreamFactory::new(base).with_limits(TenantLimits::new().with_budget(1));
+        let acme = factory.tenant("acme", "acme-token");
+        acme.caption("img", None).await.unwrap();
+        assert!(matches!(
+            acme.caption("img", None).await,
+            Err(Error::BudgetExhausted { budget: 1 })
+        ));
+
+        factory.tenant_with_limits("acme", "acme-token", TenantLimits::new().with_budget(2));
+        acme.caption("img", None).await.unwrap();
+        acme.caption("img", None).await.unwrap();
+        assert!(matches!(
+            acme.caption("img", None).await,
+            Err(Error::BudgetExhausted { budget: 2 })
+        ));
+        assert_eq!(factory.usage("acme").unwrap().total().requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_waits() {
+        let quota = Quota::new(TenantLimits::new().with_rate_limit(2, Duration::from_millis(200)));
+        let start = std::time::Instant::now();
+        for _ in 0..3 {
+            quota.acquire().await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
@@ -0,0 +1,261 @@
+//! Tiled and pyramidal TIFF images (feature `tiff`).
+//!
+//! Whole-slide scans and map rasters are stored as (Big)TIFF files holding
+//! the image at several resolutions, one per directory. [`TiffSource`] lists
+//! these levels and reads the tiles (or strips) of one level as PNG
+//! [`Tile`]s for [`MoonDream::detect_tiled`](crate::MoonDream::detect_tiled),
+//! decoding one tile at a time. Pick a coarse level to find regions of
+//! interest quickly and a fine one to find small objects.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::tiff::TiffSource;
+//!
+//! # async fn run(md: MoonDream) -> Result<(), moondream::Error> {
+//! let mut slide = TiffSource::open("slide.tiff")?;
+//! let level = slide.level_for_width(8192);
+//! let cells = md.detect_tiled(slide.tiles(level)?, "mitotic cell").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tiles::Tile;
+use crate::{Error, ImageInput};
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage, RgbaImage};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+use tiff::ColorType;
+use tiff::decoder::{Decoder, DecodingResult};
+
+/// One resolution of a TIFF image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    /// Index of the TIFF directory holding the level.
+    pub index: usize,
+    /// Width of the level, in pixels.
+    pub width: u32,
+    /// Height of the level, in pixels.
+    pub height: u32,
+    /// Width of the tiles, in pixels; the level width for strips.
+    pub tile_width: u32,
+    /// Height of the tiles or strips, in pixels.
+    pub tile_height: u32,
+}
+
+impl Level {
+    /// Number of tiles, or strips, of the level.
+    pub fn tile_count(&self) -> u32 {
+        self.tiles_across() * self.height.div_ceil(self.tile_height.max(1))
+    }
+
+    fn tiles_across(&self) -> u32 {
+        self.width.div_ceil(self.tile_width.max(1))
+    }
+}
+
+/// Reader of the levels of a TIFF or BigTIFF image.
+pub struct TiffSource<R: Read + Seek> {
+    decoder: Decoder<R>,
+    levels: Vec<Level>,
+}
+
+impl<R: Read + Seek> std::fmt::Debug for TiffSource<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TiffSource")
+            .field("levels", &self.levels)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TiffSource<BufReader<File>> {
+    /// Open the TIFF file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl TiffSource<Cursor<crate::MappedFile>> {
+    /// Read a memory-mapped TIFF file, see
+    /// [`ImageInput::from_mmap`](crate::ImageInput::from_mmap).
+    pub fn from_mmap(file: crate::MappedFile) -> Result<Self, Error> {
+        Self::new(Cursor::new(file))
+    }
+}
+
+impl<R: Read + Seek> TiffSource<R> {
+    /// Read the directories of the TIFF image in `reader`.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut decoder = Decoder::new(reader).map_err(invalid)?;
+        let mut levels = Vec::new();
+        loop {
+            let (width, height) = decoder.dimensions().map_err(invalid)?;
+            let (tile_width, tile_height) = decoder.chunk_dimensions();
+            levels.push(Level {
+                index: levels.len(),
+                width,
+                height,
+                tile_width,
+                tile_height,
+            });
+            if !decoder.more_images() {
+                break;
+            }
+            decoder.next_image().map_err(invalid)?;
+        }
+        Ok(Self { decoder, levels })
+    }
+
+    /// Levels of the image, in file order: usually the full resolution
+    /// first, then smaller ones, and possibly thumbnails or label images.
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    /// Index of the largest level at most `width` pixels wide, or of the
+    /// smallest level if all are wider.
+    pub fn level_for_width(&self, width: u32) -> usize {
+        let fitting = self
+            .levels
+            .iter()
+            .filter(|level| level.width <= width)
+            .max_by_key(|level| level.width);
+        let smallest = self.levels.iter().min_by_key(|level| level.width);
+        fitting.or(smallest).map_or(0, |level| level.index)
+    }
+
+    /// Iterate over the tiles of level `index`, row by row.
+    pub fn tiles(&mut self, index: usize) -> Result<Tiles<'_, R>, Error> {
+        let level = *self.levels.get(index).ok_or_else(|| {
+            Error::InvalidImage(format!(
+                "level {index} out of range, the image has {} levels",
+                self.levels.len()
+            ))
+        })?;
+        self.decoder.seek_to_image(index).map_err(invalid)?;
+        Ok(Tiles {
+            decoder: &mut self.decoder,
+            level,
+            next: 0,
+        })
+    }
+}
+
+/// Iterator over the tiles of a [`Level`], decoding one tile per step.
+pub struct Tiles<'a, R: Read + Seek> {
+    decoder: &'a mut Decoder<R>,
+    level: Level,
+    next: u32,
+}
+
+impl<R: Read + Seek> std::fmt::Debug for Tiles<'_, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tiles")
+            .field("level", &self.level)
+            .field("next", &self.next)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read + Seek> Iterator for Tiles<'_, R> {
+    type Item = Result<Tile, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.level.tile_count() {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        Some(self.read(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.level.tile_count().saturating_sub(self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<R: Read + Seek> Tiles<'_, R> {
+    fn read(&mut self, index: u32) -> Result<Tile, Error> {
+        let level = self.level;
+        let (width, height) = self.decoder.chunk_data_dimensions(index);
+        let color = self.decoder.colortype().map_err(invalid)?;
+        let DecodingResult::U8(data) = self.decoder.read_chunk(index).map_err(invalid)? else {
+            return Err(unsupported(color));
+        };
+        let image = match color {
+            ColorType::Gray(8) => GrayImage::from_raw(width, height, data).map(DynamicImage::from),
+            ColorType::RGB(8) => RgbImage::from_raw(width, height, data).map(DynamicImage::from),
+            ColorType::RGBA(8) => RgbaImage::from_raw(width, height, data).map(DynamicImage::from),
+            color => return Err(unsupported(color)),
+        }
+        .ok_or_else(|| Error::InvalidImage(format!("tile {index} has an unexpected size")))?;
+
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(invalid)?;
+        Ok(Tile {
+            x: index % level.tiles_across() * level.tile_width,
+            y: index / level.tiles_across() * level.tile_height,
+            width,
+            height,
+            image_width: level.width,
+            image_height: level.height,
+            image: ImageInput::bytes(png, "image/png"),
+        })
+    }
+}
+
+fn unsupported(color: ColorType) -> Error {
+    Error::InvalidImage(format!("unsupported TIFF color type {color:?}"))
+}
+
+fn invalid(error: impl std::fmt::Display) -> Error {
+    Error::InvalidImage(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiff::encoder::{TiffEncoder, colortype};
+
+    /// A two-level striped RGB TIFF, 64x32 then 32x16.
+    fn pyramid() -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        for (width, height) in [(64, 32), (32, 16)] {
+            let pixels = vec![200u8; (width * height * 3) as usize];
+            encoder
+                .write_image::<colortype::RGB8>(width, height, &pixels)
+                .unwrap();
+        }
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_levels_and_tiles() {
+        let mut source = TiffSource::new(Cursor::new(pyramid())).unwrap();
+        let sizes: Vec<_> = source
+            .levels()
+            .iter()
+            .map(|l| (l.width, l.height))
+            .collect();
+        assert_eq!(sizes, [(64, 32), (32, 16)]);
+        assert_eq!(source.level_for_width(40), 1);
+        assert_eq!(source.level_for_width(10), 1);
+        assert_eq!(source.level_for_width(1000), 0);
+
+        let tiles: Vec<Tile> = source.tiles(1).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(tiles.iter().map(|t| t.height).sum::<u32>(), 16);
+        assert!(tiles.iter().all(|t| t.width == 32 && t.image_width == 32));
+        let ImageInput::Bytes { data, .. } = &tiles[0].image else {
+            panic!("tiles are encoded bytes");
+        };
+        let decoded = image::load_from_memory(data).unwrap();
+        assert_eq!(decoded.width(), 32);
+
+        assert!(matches!(source.tiles(2), Err(Error::InvalidImage(_))));
+    }
+}
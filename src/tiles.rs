@@ -0,0 +1,110 @@
+//! Detection on images too large to send whole.
+//!
+//! A tile source, such as `tiff::TiffSource` (feature
+//! `tiff`), yields [`Tile`]s: encoded pieces of a large image with their
+//! position. [`MoonDream::detect_tiled`] detects objects tile by tile and
+//! returns boxes normalized to the whole image, holding a single tile in
+//! memory at a time. An object crossing tile borders is returned as one box
+//! per tile it covers.
+
+use crate::{DetectionObject, Error, ImageInput, MoonDream};
+
+/// A piece of a large image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    /// Left edge of the tile in the image, in pixels.
+    pub x: u32,
+    /// Top edge of the tile in the image, in pixels.
+    pub y: u32,
+    /// Width of the tile, in pixels.
+    pub width: u32,
+    /// Height of the tile, in pixels.
+    pub height: u32,
+    /// Width of the whole image, in pixels.
+    pub image_width: u32,
+    /// Height of the whole image, in pixels.
+    pub image_height: u32,
+    /// Encoded tile.
+    pub image: ImageInput,
+}
+
+impl Tile {
+    /// Convert a box normalized to the tile into a box normalized to the
+    /// whole image.
+    pub fn to_image(&self, object: &DetectionObject) -> DetectionObject {
+        let x = |value: f64| (self.x as f64 + value * self.width as f64) / self.image_width as f64;
+        let y =
+            |value: f64| (self.y as f64 + value * self.height as f64) / self.image_height as f64;
+        DetectionObject {
+            x_min: x(object.x_min),
+            y_min: y(object.y_min),
+            x_max: x(object.x_max),
+            y_max: y(object.y_max),
+            confidence: object.confidence,
+        }
+    }
+}
+
+impl MoonDream {
+    /// Detect `object` on every tile, returning the boxes normalized to the
+    /// whole image, see the [module documentation](crate::tiles).
+    ///
+    /// Tiles are sent one after the other; the first error is returned.
+    pub async fn detect_tiled<I>(
+        &self,
+        tiles: I,
+        object: impl Into<String>,
+    ) -> Result<Vec<DetectionObject>, Error>
+    where
+        I: IntoIterator<Item = Result<Tile, Error>>,
+    {
+        let object = object.into();
+        let mut objects = Vec::new();
+        for tile in tiles {
+            let tile = tile?;
+            let detected = self.detect(tile.image.clone(), object.clone()).await?;
+            objects.extend(detected.objects.iter().map(|found| tile.to_image(found)));
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn tile(x: u32, y: u32) -> Tile {
+        Tile {
+            x,
+            y,
+            width: 100,
+            height: 100,
+            image_width: 200,
+            image_height: 100,
+            image: ImageInput::bytes(vec![0], "image/png"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detect_tiled_maps_boxes_to_image() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/detect"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objects": [{"x_min": 0.5, "y_min": 0.0, "x_max": 1.0, "y_max": 0.5}],
+            })))
+            .mount(&server)
+            .await;
+
+        let md = MoonDream::remote("token").with_endpoint(server.uri());
+        let objects = md
+            .detect_tiled([Ok(tile(0, 0)), Ok(tile(100, 0))], "cell")
+            .await
+            .unwrap();
+        let xs: Vec<_> = objects.iter().map(|o| (o.x_min, o.x_max)).collect();
+        assert_eq!(xs, [(0.25, 0.5), (0.75, 1.0)]);
+        assert_eq!(objects[0].y_max, 0.5);
+    }
+}
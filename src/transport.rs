@@ -0,0 +1,109 @@
+//! How images are uploaded.
+//!
+//! By default images travel inside the JSON body as base64 `data:` URIs,
+//! which inflates them by a third. With [`ImageTransport::Multipart`]
+//! requests carrying an `image_url` are sent as `multipart/form-data`
+//! instead: the image is a binary `image` part and the other fields are text
+//! parts. Files passed as [`ImageInput::file`](crate::ImageInput::file) are
+//! streamed from disk without being read into memory, as long as no decoder
+//! or preprocessor has to look at their content. The server must accept
+//! multipart uploads.
+
+use crate::Error;
+use crate::input::{decode_data_uri, mime_from_path};
+use reqwest::Body;
+use reqwest::multipart::{Form, Part};
+use serde_json::Value;
+use std::path::Path;
+use tokio_util::io::ReaderStream;
+
+/// Scheme marking `image_url` values referring to a local file.
+const FILE_SCHEME: &str = "file://";
+
+/// How images are sent to the API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageTransport {
+    /// Base64 `data:` URIs in the JSON body.
+    #[default]
+    DataUri,
+    /// Binary parts of a `multipart/form-data` body, streaming files from
+    /// disk.
+    Multipart,
+}
+
+/// `image_url` value referring to the local file at `path`.
+pub(crate) fn file_url(path: &Path) -> String {
+    format!("{FILE_SCHEME}{}", path.display())
+}
+
+/// Return `true` if the `image_url` of `fields` refers to a local file.
+pub(crate) fn uploads_file(fields: &Value) -> bool {
+    fields
+        .get("image_url")
+        .and_then(Value::as_str)
+        .is_some_and(|image| image.starts_with(FILE_SCHEME))
+}
+
+/// Build the multipart form of a JSON object body.
+///
+/// The form is built again for every attempt, so retried uploads reopen the
+/// file.
+pub(crate) async fn form(fields: &Value) -> Result<Form, Error> {
+    let mut form = Form::new();
+    let Value::Object(fields) = fields else {
+        return Ok(form);
+    };
+    for (name, value) in fields {
+        form = match (name.as_str(), value) {
+            ("image_url", Value::String(image)) => match image_part(image).await? {
+                Some(part) => form.part("image", part),
+                None => form.text(name.clone(), image.clone()),
+            },
+            (_, Value::String(text)) => form.text(name.clone(), text.clone()),
+            _ => form.text(name.clone(), value.to_string()),
+        };
+    }
+    Ok(form)
+}
+
+/// Binary part of a local file or a `data:` URI, `None` for remote URLs.
+async fn image_part(image: &str) -> Result<Option<Part>, Error> {
+    if let Some(path) = image.strip_prefix(FILE_SCHEME) {
+        let path = Path::new(path);
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "image".to_string());
+        let part = Part::stream_with_length(Body::wrap_stream(ReaderStream::new(file)), length)
+            .file_name(name)
+            .mime_str(mime_from_path(path))?;
+        return Ok(Some(part));
+    }
+    match decode_data_uri(image) {
+        Some(decoded) => {
+            let (mime, bytes) = decoded?;
+            let part = Part::bytes(bytes).file_name("image").mime_str(mime)?;
+            Ok(Some(part))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_uploads_file() {
+        let url = file_url(Path::new("/tmp/cat.jpg"));
+        assert_eq!(url, "file:///tmp/cat.jpg");
+        assert!(uploads_file(&json!({ "image_url": url })));
+        assert!(!uploads_file(
+            &json!({ "image_url": "data:image/png;base64,AAE=" })
+        ));
+        assert!(!uploads_file(&json!({})));
+    }
+}
@@ -0,0 +1,191 @@
+//! Per-client usage accounting.
+//!
+//! Every [`MoonDream`](crate::MoonDream) records its calls in a
+//! [`UsageTracker`], read with
+//! [`MoonDream::usage`](crate::MoonDream::usage). Clones of a client share
+//! the tracker; give each tenant its own with
+//! [`MoonDream::with_usage_tracker`](crate::MoonDream::with_usage_tracker).
+//! Token counts are recorded when the response carries a `usage` object with
+//! `input_tokens` and `output_tokens`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Shared counters of the calls made by a client.
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    inner: Arc<Mutex<UsageSnapshot>>,
+}
+
+/// Usage recorded for every endpoint, keyed by endpoint name such as
+/// `query`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageSnapshot {
+    /// Usage of each endpoint.
+    pub endpoints: BTreeMap<String, EndpointUsage>,
+}
+
+/// Usage of a single endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointUsage {
+    /// HTTP requests sent, retries included.
+    pub requests: u64,
+    /// Calls that failed after their last attempt.
+    pub errors: u64,
+    /// Calls served from the cache, without any request.
+    pub cache_hits: u64,
+    /// Request body bytes sent, retries included.
+    pub bytes_uploaded: u64,
+    /// Response body bytes received by successful calls.
+    pub bytes_downloaded: u64,
+    /// Sum of the latencies of successful calls, in milliseconds.
+    pub total_latency_ms: u64,
+    /// Highest latency of a successful call, in milliseconds.
+    pub max_latency_ms: u64,
+    /// Input tokens reported by the API.
+    pub input_tokens: u64,
+    /// Output tokens reported by the API.
+    pub output_tokens: u64,
+    /// Retries performed by the calls that failed.
+    #[serde(default)]
+    pub failed_retries: u64,
+    /// Time the calls that failed spent waiting between attempts, in
+    /// milliseconds.
+    #[serde(default)]
+    pub failed_backoff_ms: u64,
+}
+
+impl EndpointUsage {
+    fn add(&mut self, other: &EndpointUsage) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.cache_hits += other.cache_hits;
+        self.bytes_uploaded += other.bytes_uploaded;
+        self.bytes_downloaded += other.bytes_downloaded;
+        self.total_latency_ms += other.total_latency_ms;
+        self.max_latency_ms = self.max_latency_ms.max(other.max_latency_ms);
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.failed_retries += other.failed_retries;
+        self.failed_backoff_ms += other.failed_backoff_ms;
+    }
+}
+
+impl UsageSnapshot {
+    /// Usage summed over every endpoint.
+    pub fn total(&self) -> EndpointUsage {
+        let mut total = EndpointUsage::default();
+        for usage in self.endpoints.values() {
+            total.add(usage);
+        }
+        total
+    }
+}
+
+impl UsageTracker {
+    /// Create a tracker without any recorded usage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a copy of the recorded usage.
+    pub fn snapshot(&self) -> UsageSnapshot {
+        self.lock().clone()
+    }
+
+    /// Return the recorded usage and start counting from zero, for example
+    /// at the end of a billing window.
+    pub fn reset(&self) -> UsageSnapshot {
+        std::mem::take(&mut *self.lock())
+    }
+
+    pub(crate) fn record_request(&self, endpoint: &str, bytes: usize) {
+        self.update(endpoint, |usage| {
+            usage.requests += 1;
+            usage.bytes_uploaded += bytes as u64;
+        });
+    }
+
+    pub(crate) fn record_cache_hit(&self, endpoint: &str) {
+        self.update(endpoint, |usage| usage.cache_hits += 1);
+    }
+
+    pub(crate) fn record_error(&self, endpoint: &str, attempts: u32, backoff: Duration) {
+        self.update(endpoint, |usage| {
+            usage.errors += 1;
+            usage.failed_retries += u64::from(attempts.saturating_sub(1));
+            usage.failed_backoff_ms += backoff.as_millis() as u64;
+        });
+    }
+
+    pub(crate) fn record_success(&self, endpoint: &str, body: &[u8], latency: Duration) {
+        #[derive(Deserialize)]
+        struct Body {
+            usage: Option<Tokens>,
+        }
+        #[derive(Deserialize)]
+        struct Tokens {
+            #[serde(default)]
+            input_tokens: u64,
+            #[serde(default)]
+            output_tokens: u64,
+        }
+
+        let tokens = serde_json::from_slice::<Body>(body)
+            .ok()
+            .and_then(|body| body.usage);
+        let latency_ms = latency.as_millis() as u64;
+        self.update(endpoint, |usage| {
+            usage.bytes_downloaded += body.len() as u64;
+            usage.total_latency_ms += latency_ms;
+            usage.max_latency_ms = usage.max_latency_ms.max(latency_ms);
+            if let Some(tokens) = &tokens {
+                usage.input_tokens += tokens.input_tokens;
+                usage.output_tokens += tokens.output_tokens;
+            }
+        });
+    }
+
+    fn update(&self, endpoint: &str, f: impl FnOnce(&mut EndpointUsage)) {
+        let mut snapshot = self.lock();
+        f(snapshot.endpoints.entry(endpoint.to_string()).or_default());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UsageSnapshot> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_and_resets() {
+        let tracker = UsageTracker::new();
+        tracker.record_request("query", 100);
+        tracker.record_request("query", 100);
+        tracker.record_success(
+            "query",
+            br#"{"answer": "yes", "usage": {"input_tokens": 12, "output_tokens": 3}}"#,
+            Duration::from_millis(40),
+        );
+        tracker.record_request("caption", 50);
+        tracker.record_error("caption", 3, Duration::from_millis(30));
+
+        let usage = tracker.reset();
+        let query = usage.endpoints["query"];
+        assert_eq!(query.requests, 2);
+        assert_eq!(query.bytes_uploaded, 200);
+        assert_eq!(query.max_latency_ms, 40);
+        assert_eq!((query.input_tokens, query.output_tokens), (12, 3));
+        assert_eq!(usage.total().errors, 1);
+        assert_eq!(usage.endpoints["caption"].failed_retries, 2);
+        assert_eq!(usage.endpoints["caption"].failed_backoff_ms, 30);
+        assert_eq!(usage.total().requests, 3);
+
+        assert!(tracker.snapshot().endpoints.is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+//! Record and replay HTTP interactions for hermetic tests (feature `vcr`).
+//!
+//! A [`Cassette`] set with [`MoonDream::with_cassette`] sits between the
+//! client and the network:
+//!
+//! - in [`VcrMode::Record`] requests go to the server and every response is
+//!   written to a JSON fixture file, next to the request it answers;
+//! - in [`VcrMode::Replay`] responses are served from the fixture file and
+//!   nothing is sent. A request without a recorded answer fails with
+//!   [`Error::Vcr`].
+//!
+//! Fixtures never contain credentials: request headers and query
+//! parameters are not recorded, and `data:` images are replaced by their
+//! SHA-256 hash. Requests are matched on their method, path and JSON body;
+//! identical requests are answered in the recorded order, the last answer
+//! being repeated. Multipart uploads are matched on method and path only.
+//! Retries, interceptors and response metadata work as with live responses.
+//!
+//! ```no_run
+//! use moondream::MoonDream;
+//! use moondream::vcr::Cassette;
+//!
+//! # async fn run() -> Result<(), moondream::Error> {
+//! // Records on the first run, replays on the next ones.
+//! let md = MoonDream::remote(std::env::var("MOONDREAM_TOKEN").unwrap_or_default())
+//!     .with_cassette(Cassette::open("tests/fixtures/caption.json")?);
+//! let caption = md.caption("tests/cat.jpg", None).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, MoonDream};
+use reqwest::header::SET_COOKIE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Whether a [`Cassette`] talks to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VcrMode {
+    /// Send requests and record the responses.
+    Record,
+    /// Serve recorded responses without network access.
+    Replay,
+}
+
+/// A request and the response it received.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    path: String,
+    /// JSON body with images hashed, `null` for multipart uploads.
+    request: Value,
+    status: u16,
+    headers: BTreeMap<String, String>,
+    /// Response body, as JSON when it parses and as a string otherwise.
+    response: Value,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    #[serde(skip)]
+    played: Vec<bool>,
+}
+
+/// Fixture file of recorded interactions, shared between clones.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: VcrMode,
+    tape: Arc<Mutex<Tape>>,
+}
+
+impl Cassette {
+    /// Record to `path`, replacing its interactions.
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            mode: VcrMode::Record,
+            tape: Arc::default(),
+        }
+    }
+
+    /// Replay the interactions recorded in `path`.
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let contents = std::fs::read(&path)
+            .map_err(|e| Error::Vcr(format!("cannot read {}: {e}", path.display())))?;
+        let mut tape: Tape = serde_json::from_slice(&contents)?;
+        tape.played = vec![false; tape.interactions.len()];
+        Ok(Self {
+            path,
+            mode: VcrMode::Replay,
+            tape: Arc::new(Mutex::new(tape)),
+        })
+    }
+
+    /// Replay `path` if it exists and record to it otherwise.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        if path.exists() {
+            Self::replay(path)
+        } else {
+            Ok(Self::record(path))
+        }
+    }
+
+    /// Mode of the cassette.
+    pub fn mode(&self) -> VcrMode {
+        self.mode
+    }
+
+    /// Path of the fixture file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of recorded interactions.
+    pub fn len(&self) -> usize {
+        self.tape().interactions.len()
+    }
+
+    /// Return `true` if no interaction is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn tape(&self) -> std::sync::MutexGuard<'_, Tape> {
+        self.tape.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Send `request`, or answer it from the tape.
+    pub(crate) async fn execute(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, Error> {
+        let method = request.method().to_string();
+        let path = request.url().path().to_string();
+        let body = request_body(&request);
+        match self.mode {
+            VcrMode::Replay => {
+                let interaction = self.play(&method, &path, &body).ok_or_else(|| {
+                    Error::Vcr(format!(
+                        "no interaction recorded in {} for {method} {path}",
+                        self.path.display()
+                    ))
+                })?;
+                into_response(&interaction)
+            }
+            VcrMode::Record => {
+                let response = client.execute(request).await?;
+                let status = response.status().as_u16();
+                let headers = response
+                    .headers()
+                    .iter()
+                    .filter(|(name, _)| *name != SET_COOKIE)
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect();
+                let bytes = response.bytes().await?;
+                let interaction = Interaction {
+                    method,
+                    path,
+                    request: body,
+                    status,
+                    headers,
+                    response: serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+                        Value::String(String::from_utf8_lossy(&bytes).into_owned())
+                    }),
+                };
+                let response = into_response(&interaction)?;
+                self.save(interaction)?;
+                Ok(response)
+            }
+        }
+    }
+
+    /// Next recorded answer to the request, the last one once all were
+    /// played.
+    fn play(&self, method: &str, path: &str, body: &Value) -> Option<Interaction> {
+        let mut tape = self.tape();
+        let matching: Vec<usize> = (0..tape.interactions.len())
+            .filter(|&index| {
+                let interaction = &tape.interactions[index];
+                interaction.method == method
+                    && interaction.path == path
+                    && (body.is_null() || interaction.request == *body)
+            })
+            .collect();
+        let index = matching
+            .iter()
+            .copied()
+            .find(|&index| !tape.played[index])
+            .or(matching.last().copied())?;
+        tape.played[index] = true;
+        Some(tape.interactions[index].clone())
+    }
+
+    /// Append `interaction` to the tape and rewrite the fixture file.
+    fn save(&self, interaction: Interaction) -> Result<(), Error> {
+        let mut tape = self.tape();
+        tape.interactions.push(interaction);
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| Error::Vcr(format!("cannot create {}: {e}", parent.display())))?;
+        }
+        let contents = serde_json::to_vec_pretty(&*tape)?;
+        std::fs::write(&self.path, contents)
+            .map_err(|e| Error::Vcr(format!("cannot write {}: {e}", self.path.display())))
+    }
+}
+
+/// JSON body of `request` with images hashed, `null` if it is not JSON.
+fn request_body(request: &reqwest::Request) -> Value {
+    let Some(mut body) = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .and_then(|bytes| serde_json::from_slice::<Value>(bytes).ok())
+    else {
+        return Value::Null;
+    };
+    hash_images(&mut body);
+    body
+}
+
+/// Replace every `data:` URI in `value` by its SHA-256 hash.
+fn hash_images(value: &mut Value) {
+    match value {
+        Value::String(text) if text.starts_with("data:") => {
+            let hash: String = Sha256::digest(text.as_bytes())
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            *text = format!("sha256:{hash}");
+        }
+        Value::Array(values) => values.iter_mut().for_each(hash_images),
+        Value::Object(fields) => fields.values_mut().for_each(hash_images),
+        _ => {}
+    }
+}
+
+/// Rebuild the HTTP response of `interaction`.
+fn into_response(interaction: &Interaction) -> Result<reqwest::Response, Error> {
+    let body = match &interaction.response {
+        Value::String(text) => text.clone().into_bytes(),
+        value => serde_json::to_vec(value)?,
+    };
+    let mut builder = http::Response::builder().status(interaction.status);
+    for (name, value) in &interaction.headers {
+        // The body may be re-encoded, so its recorded length no longer
+        // applies.
+        if name != "content-length" {
+            builder = builder.header(name, value);
+        }
+    }
+    let response = builder
+        .body(body)
+        .map_err(|e| Error::Vcr(format!("invalid recorded response: {e}")))?;
+    Ok(reqwest::Response::from(response))
+}
+
+impl MoonDream {
+    /// Record or replay the HTTP interactions of the client with
+    /// `cassette`, see the [module documentation](crate::vcr).
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let fixture = std::env::temp_dir()
+            .join(format!("moondream-vcr-{}", uuid::Uuid::new_v4()))
+            .join("query.json");
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "request_id": "rec1",
+                "answer": "A cat",
+            })))
+            .mount(&server)
+            .await;
+
+        let image = "data:image/png;base64,AAAA";
+        let recorder = MoonDream::remote("secret-token")
+            .with_endpoint(server.uri())
+            .with_cassette(Cassette::open(&fixture).unwrap());
+        assert_eq!(
+            recorder.query(image, "What?").await.unwrap().answer,
+            "A cat"
+        );
+
+        let contents = std::fs::read_to_string(&fixture).unwrap();
+        assert!(!contents.contains("secret-token"));
+        assert!(!contents.contains("AAAA"));
+
+        // The server is gone: the answer comes from the fixture.
+        drop(server);
+        let cassette = Cassette::open(&fixture).unwrap();
+        assert_eq!(cassette.mode(), VcrMode::Replay);
+        let replayer = MoonDream::remote("other-token")
+            .with_endpoint("http://127.0.0.1:9")
+            .with_cassette(cassette);
+        let response = replayer.query_with_meta(image, "What?").await.unwrap();
+        assert_eq!(response.answer, "A cat");
+        assert_eq!(response.request_id.as_deref(), Some("rec1"));
+
+        let missing = replayer.query(image, "Something else?").await;
+        assert!(matches!(missing, Err(Error::Vcr(_))));
+        std::fs::remove_dir_all(fixture.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//! Second-opinion verification of detections (feature `image`).
+//!
+//! [`MoonDream::verify_detection`] crops a bounding box out of the image and
+//! asks the model whether the crop shows the detected object. Each crop of a
+//! [`Verifier`] is one vote; the share of "yes" answers is the confidence of
+//! the [`Verification`]. [`MoonDream::verify_all`] drops the objects of a
+//! [`DetectResponse`] that fail verification, trading extra queries for
+//! fewer false positives.
+
+use crate::input::decode_data_uri;
+use crate::prompts::{Task, TaskOutput};
+use crate::{DetectResponse, DetectionObject, Error, ImageInput, MoonDream};
+use futures::{StreamExt, TryStreamExt};
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use std::io::Cursor;
+
+/// How boxes are verified.
+///
+/// By default one crop, padded by 10% of the box size on each side, is
+/// checked and a "yes" is required.
+///
+/// ```
+/// use moondream::verify::Verifier;
+///
+/// // Three views with more and more context, at least two must agree.
+/// let verifier = Verifier::new()
+///     .with_paddings([0.0, 0.25, 0.5])
+///     .with_threshold(0.6);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verifier {
+    paddings: Vec<f64>,
+    threshold: f64,
+    concurrency: usize,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Verifier {
+    /// Check one crop padded by 10%, requiring a "yes".
+    pub fn new() -> Self {
+        Self {
+            paddings: vec![0.1],
+            threshold: 0.5,
+            concurrency: 4,
+        }
+    }
+
+    /// Check one crop per padding, each a fraction of the box size added on
+    /// every side.
+    pub fn with_paddings(mut self, paddings: impl IntoIterator<Item = f64>) -> Self {
+        self.paddings = paddings
+            .into_iter()
+            .map(|padding| padding.max(0.0))
+            .collect();
+        if self.paddings.is_empty() {
+            self.paddings.push(0.0);
+        }
+        self
+    }
+
+    /// Minimum share of "yes" answers for a box to be verified.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Maximum number of verification queries in flight in
+    /// [`MoonDream::verify_all`] (default 4).
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+}
+
+/// Outcome of verifying one box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    /// `true` if the share of "yes" answers reached the threshold.
+    pub verified: bool,
+    /// Share of "yes" answers (0-1).
+    pub confidence: f64,
+    /// Raw answer to each crop.
+    pub answers: Vec<String>,
+}
+
+impl MoonDream {
+    /// Ask the model whether `bbox` shows `object`, see the
+    /// [module documentation](crate::verify).
+    pub async fn verify_detection(
+        &self,
+        image: impl Into<ImageInput>,
+        object: &str,
+        bbox: &DetectionObject,
+        verifier: &Verifier,
+    ) -> Result<Verification, Error> {
+        let image = self.load_for_crop(image).await?;
+        self.verify_box(&image, object, bbox, verifier).await
+    }
+
+    /// Keep the objects of `response` that pass
+    /// [`MoonDream::verify_detection`].
+    ///
+    /// The image is decoded once and the boxes are checked concurrently.
+    pub async fn verify_all(
+        &self,
+        image: impl Into<ImageInput>,
+        object: &str,
+        mut response: DetectResponse,
+        verifier: &Verifier,
+    ) -> Result<DetectResponse, Error> {
+        let image = self.load_for_crop(image).await?;
+        let verified: Vec<bool> = futures::stream::iter(&response.objects)
+            .map(|bbox| self.verify_box(&image, object, bbox, verifier))
+            .buffered(verifier.concurrency)
+            .map_ok(|verification| verification.verified)
+            .try_collect()
+            .await?;
+
+        let mut verified = verified.into_iter();
+        response
+            .objects
+            .retain(|_| verified.next().unwrap_or(false));
+        Ok(response)
+    }
+
+    async fn verify_box(
+        &self,
+        image: &DynamicImage,
+        object: &str,
+        bbox: &DetectionObject,
+        verifier: &Verifier,
+    ) -> Result<Verification, Error> {
+        let task = Task::YesNo(format!("Is this a {object}?"));
+        let mut answers = Vec::with_capacity(verifier.paddings.len());
+        let mut yes = 0;
+        for &padding in &verifier.paddings {
+            let crop = crop(image, bbox, padding)?;
+            let response = self.query(crop, task.prompt()).await?;
+            if let Ok(TaskOutput::YesNo(true)) = task.parse(&response.answer) {
+                yes += 1;
+            }
+            answers.push(response.answer);
+        }
+
+        let confidence = f64::from(yes) / answers.len() as f64;
+        Ok(Verification {
+            verified: confidence >= verifier.threshold,
+            confidence,
+            answers,
+        })
+    }
+
+    /// Decode `image` after the configured decoders and preprocessing,
+    /// downloading remote URLs.
+    async fn load_for_crop(&self, image: impl Into<ImageInput>) -> Result<DynamicImage, Error> {
+        let image = self.prepare_image(image)?;
+        let bytes = match decode_data_uri(&image) {
+            Some(decoded) => decoded?.1,
+            None => self
+                .client
+                .get(&image)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec(),
+        };
+        image::load_from_memory(&bytes).map_err(|e| Error::InvalidImage(e.to_string()))
+    }
+}
+
+/// Crop `bbox`, padded by `padding` times its size on each side, and encode
+/// it as PNG.
+fn crop(image: &DynamicImage, bbox: &DetectionObject, padding: f64) -> Result<ImageInput, Error> {
+    let (width, height) = image.dimensions();
+    let pad_x = (bbox.x_max - bbox.x_min) * padding;
+    let pad_y = (bbox.y_max - bbox.y_min) * padding;
+    let pixel = |value: f64, size: u32| (value.clamp(0.0, 1.0) * f64::from(size)).round() as u32;
+
+    let left = pixel(bbox.x_min - pad_x, width).min(width.saturating_sub(1));
+    let top = pixel(bbox.y_min - pad_y, height).min(height.saturating_sub(1));
+    let right = pixel(bbox.x_max + pad_x, width).max(left + 1);
+    let bottom = pixel(bbox.y_max + pad_y, height).max(top + 1);
+
+    let mut png = Vec::new();
+    image
+        .crop_imm(left, top, right - left, bottom - top)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| Error::InvalidImage(e.to_string()))?;
+    Ok(ImageInput::bytes(png, "image/png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn bbox(x_min: f64, x_max: f64) -> DetectionObject {
+        DetectionObject {
+            x_min,
+            y_min: 0.0,
+            x_max,
+            y_max: 0.5,
+            confidence: None,
+        }
+    }
+
+    fn crop_size(input: ImageInput) -> (u32, u32) {
+        let ImageInput::Bytes { data, .. } = input else {
+            panic!("crops are encoded bytes");
+        };
+        image::load_from_memory(&data).unwrap().dimensions()
+    }
+
+    #[test]
+    fn test_crop_pads_and_clamps() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(100, 100));
+
+        assert_eq!(
+            crop_size(crop(&image, &bbox(0.25, 0.75), 0.0).unwrap()),
+            (50, 50)
+        );
+        // The padding above the box is clamped to the top edge.
+        assert_eq!(
+            crop_size(crop(&image, &bbox(0.25, 0.75), 0.1).unwrap()),
+            (60, 55)
+        );
+        assert_eq!(
+            crop_size(crop(&image, &bbox(0.0, 0.5), 0.5).unwrap()),
+            (75, 75)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_all_prunes_rejected_boxes() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "answer": "No.",
+            })))
+            .mount(&server)
+            .await;
+
+        let mut png = Vec::new();
+        RgbImage::new(16, 16)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let response = DetectResponse {
+            request_id: None,
+            objects: vec![bbox(0.0, 0.5), bbox(0.5, 1.0)],
+        };
+
+        let md = MoonDream::local(server.uri());
+        let verified = md
+            .verify_all(
+                ImageInput::bytes(png, "image/png"),
+                "car",
+                response,
+                &Verifier::new(),
+            )
+            .await
+            .unwrap();
+        assert!(verified.objects.is_empty());
+    }
+}
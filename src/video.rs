@@ -0,0 +1,240 @@
+//! Frame-sequence analysis (feature `video`).
+//!
+//! [`FrameAnalyzer`] samples every Nth frame from a stream or iterator of
+//! [`Frame`]s, runs a [`FrameOperation`] on each sampled frame with bounded
+//! concurrency and yields timestamped [`FrameResult`]s in frame order. Frames
+//! can come from any decoder (ffmpeg, GStreamer, a camera SDK, ...) as long as
+//! they are encoded into an image value accepted by the client, usually a
+//! base64 `data:` URI.
+
+use crate::{
+    CaptionLength, CaptionResponse, DetectResponse, Error, PointsResponse, QueryResponse,
+    VisionClient,
+};
+use derive_new::new;
+use derive_setters::Setters;
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A single video frame.
+#[derive(Debug, Clone, PartialEq, new)]
+pub struct Frame {
+    /// Position of the frame in the source video.
+    pub index: u64,
+    /// Presentation timestamp of the frame.
+    pub timestamp: Duration,
+    /// Encoded image sent to the client.
+    pub image: String,
+}
+
+/// Operation run on each sampled frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameOperation {
+    /// Call [`VisionClient::points`] for the given object.
+    Points(String),
+    /// Call [`VisionClient::detect`] for the given object.
+    Detect(String),
+    /// Call [`VisionClient::caption`] with the given length.
+    Caption(Option<CaptionLength>),
+    /// Call [`VisionClient::query`] with the given question.
+    Query(String),
+}
+
+/// Response produced by a [`FrameOperation`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FrameOutput {
+    /// Response of [`FrameOperation::Points`].
+    Points(PointsResponse),
+    /// Response of [`FrameOperation::Detect`].
+    Detect(DetectResponse),
+    /// Response of [`FrameOperation::Caption`].
+    Caption(CaptionResponse),
+    /// Response of [`FrameOperation::Query`].
+    Query(QueryResponse),
+}
+
+/// Result of analyzing one frame.
+#[derive(Debug)]
+pub struct FrameResult {
+    /// Position of the frame in the source video.
+    pub index: u64,
+    /// Presentation timestamp of the frame.
+    pub timestamp: Duration,
+    /// Output of the operation, or the error returned by the client.
+    pub result: Result<FrameOutput, Error>,
+}
+
+/// Samples frames and analyzes them with a [`VisionClient`].
+///
+/// By default every frame is analyzed with up to 4 requests in flight.
+///
+/// ```no_run
+/// # async fn run(frames: Vec<moondream::video::Frame>) {
+/// use futures::StreamExt;
+/// use moondream::MoonDream;
+/// use moondream::video::{FrameAnalyzer, FrameOperation};
+///
+/// let analyzer = FrameAnalyzer::new(
+///     MoonDream::remote("token"),
+///     FrameOperation::Detect("person".into()),
+/// )
+/// .with_every_nth(25)
+/// .with_concurrency(8);
+///
+/// let mut results = analyzer.analyze_iter(frames);
+/// while let Some(frame) = results.next().await {
+///     println!("{:?}: {:?}", frame.timestamp, frame.result);
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, new, Setters)]
+#[setters(prefix = "with_")]
+pub struct FrameAnalyzer<C> {
+    #[setters(skip)]
+    client: C,
+
+    #[setters(skip)]
+    operation: FrameOperation,
+
+    /// Analyze one frame out of `every_nth` (the first frame is always kept).
+    #[new(value = "1")]
+    every_nth: usize,
+
+    /// Maximum number of requests in flight.
+    #[new(value = "4")]
+    concurrency: usize,
+}
+
+impl<C: VisionClient> FrameAnalyzer<C> {
+    /// Analyze a stream of frames.
+    ///
+    /// Results are yielded in the order of the input frames.
+    pub fn analyze<'a, S>(&'a self, frames: S) -> impl Stream<Item = FrameResult> + 'a
+    where
+        S: Stream<Item = Frame> + 'a,
+    {
+        let every_nth = self.every_nth.max(1);
+
+        frames
+            .enumerate()
+            .filter(move |(position, _)| std::future::ready(position % every_nth == 0))
+            .map(move |(_, frame)| self.analyze_frame(frame))
+            .buffered(self.concurrency.max(1))
+    }
+
+    /// Analyze frames produced by an iterator.
+    pub fn analyze_iter<'a, I>(&'a self, frames: I) -> impl Stream<Item = FrameResult> + 'a
+    where
+        I: IntoIterator<Item = Frame>,
+        I::IntoIter: 'a,
+    {
+        self.analyze(stream::iter(frames))
+    }
+
+    async fn analyze_frame(&self, frame: Frame) -> FrameResult {
+        let result = match &self.operation {
+            FrameOperation::Points(object) => self
+                .client
+                .points(frame.image, object.clone())
+                .await
+                .map(FrameOutput::Points),
+            FrameOperation::Detect(object) => self
+                .client
+                .detect(frame.image, object.clone())
+                .await
+                .map(FrameOutput::Detect),
+            FrameOperation::Caption(length) => self
+                .client
+                .caption(frame.image, *length)
+                .await
+                .map(FrameOutput::Caption),
+            FrameOperation::Query(question) => self
+                .client
+                .query(frame.image, question.clone())
+                .await
+                .map(FrameOutput::Query),
+        };
+
+        FrameResult {
+            index: frame.index,
+            timestamp: frame.timestamp,
+            result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct EchoClient;
+
+    #[async_trait]
+    impl VisionClient for EchoClient {
+        async fn points(&self, _: String, _: String) -> Result<PointsResponse, Error> {
+            Err(Error::Unsupported("points"))
+        }
+
+        async fn detect(&self, _: String, _: String) -> Result<DetectResponse, Error> {
+            Err(Error::Unsupported("detect"))
+        }
+
+        async fn caption(
+            &self,
+            image: String,
+            _: Option<CaptionLength>,
+        ) -> Result<CaptionResponse, Error> {
+            Ok(CaptionResponse {
+                request_id: None,
+                caption: image,
+                flags: Default::default(),
+                language: None,
+            })
+        }
+
+        async fn query(&self, _: String, _: String) -> Result<QueryResponse, Error> {
+            Err(Error::Unsupported("query"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_analyze_samples_every_nth_frame_in_order() {
+        let frames = (0..5).map(|i| Frame::new(i, Duration::from_millis(i * 40), format!("f{i}")));
+        let analyzer = FrameAnalyzer::new(EchoClient, FrameOperation::Caption(None))
+            .with_every_nth(2)
+            .with_concurrency(3);
+
+        let results: Vec<_> = analyzer.analyze_iter(frames).collect().await;
+
+        let captions: Vec<_> = results
+            .iter()
+            .map(|r| match &r.result {
+                Ok(FrameOutput::Caption(c)) => (r.index, r.timestamp, c.caption.clone()),
+                other => panic!("unexpected result: {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            captions,
+            vec![
+                (0, Duration::from_millis(0), "f0".to_string()),
+                (2, Duration::from_millis(80), "f2".to_string()),
+                (4, Duration::from_millis(160), "f4".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_analyze_reports_errors_per_frame() {
+        let frames = vec![Frame::new(0, Duration::ZERO, "f0".into())];
+        let analyzer = FrameAnalyzer::new(EchoClient, FrameOperation::Detect("cat".into()));
+
+        let results: Vec<_> = analyzer.analyze_iter(frames).collect().await;
+
+        assert!(matches!(
+            results[0].result,
+            Err(Error::Unsupported("detect"))
+        ));
+    }
+}
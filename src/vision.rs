@@ -0,0 +1,306 @@
+//! Backend-agnostic interface over the Moondream operations.
+//!
+//! [`VisionClient`] lets application code depend on the four vision
+//! operations without tying it to the HTTP client. [`MoonDream`] implements
+//! it out of the box and, with the `test-util` feature enabled,
+//! `MockVisionClient` provides a scriptable fake for unit tests.
+
+use crate::{
+    CaptionLength, CaptionResponse, DetectResponse, Error, MoonDream, PointsResponse, QueryResponse,
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Common interface implemented by every Moondream backend.
+///
+/// The trait is object safe, so it can be stored as `Arc<dyn VisionClient>`
+/// and swapped for a fake or an alternative backend at runtime.
+#[async_trait]
+pub trait VisionClient: Send + Sync {
+    /// Return the centre points of every `object` found in `image`.
+    async fn points(&self, image: String, object: String) -> Result<PointsResponse, Error>;
+
+    /// Return the bounding boxes of every `object` found in `image`.
+    async fn detect(&self, image: String, object: String) -> Result<DetectResponse, Error>;
+
+    /// Generate a caption for `image`.
+    async fn caption(
+        &self,
+        image: String,
+        length: Option<CaptionLength>,
+    ) -> Result<CaptionResponse, Error>;
+
+    /// Answer `question` about `image`.
+    async fn query(&self, image: String, question: String) -> Result<QueryResponse, Error>;
+}
+
+#[async_trait]
+impl VisionClient for MoonDream {
+    async fn points(&self, image: String, object: String) -> Result<PointsResponse, Error> {
+        MoonDream::points(self, image, object).await
+    }
+
+    async fn detect(&self, image: String, object: String) -> Result<DetectResponse, Error> {
+        MoonDream::detect(self, image, object).await
+    }
+
+    async fn caption(
+        &self,
+        image: String,
+        length: Option<CaptionLength>,
+    ) -> Result<CaptionResponse, Error> {
+        MoonDream::caption(self, image, length).await
+    }
+
+    async fn query(&self, image: String, question: String) -> Result<QueryResponse, Error> {
+        MoonDream::query(self, image, question).await
+    }
+}
+
+#[async_trait]
+impl<T: VisionClient + ?Sized> VisionClient for Arc<T> {
+    async fn points(&self, image: String, object: String) -> Result<PointsResponse, Error> {
+        (**self).points(image, object).await
+    }
+
+    async fn detect(&self, image: String, object: String) -> Result<DetectResponse, Error> {
+        (**self).detect(image, object).await
+    }
+
+    async fn caption(
+        &self,
+        image: String,
+        length: Option<CaptionLength>,
+    ) -> Result<CaptionResponse, Error> {
+        (**self).caption(image, length).await
+    }
+
+    async fn query(&self, image: String, question: String) -> Result<QueryResponse, Error> {
+        (**self).query(image, question).await
+    }
+}
+
+#[cfg(feature = "test-util")]
+pub use mock::{MockCall, MockVisionClient};
+
+#[cfg(feature = "test-util")]
+mod mock {
+    use super::*;
+    use std::sync::Mutex;
+
+    type Handler<R> = Box<dyn Fn(&str, &str) -> Result<R, Error> + Send + Sync>;
+    type CaptionHandler =
+        Box<dyn Fn(&str, Option<CaptionLength>) -> Result<CaptionResponse, Error> + Send + Sync>;
+
+    /// A call recorded by [`MockVisionClient`].
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum MockCall {
+        /// A call to [`VisionClient::points`].
+        Points { image: String, object: String },
+        /// A call to [`VisionClient::detect`].
+        Detect { image: String, object: String },
+        /// A call to [`VisionClient::caption`].
+        Caption {
+            image: String,
+            length: Option<CaptionLength>,
+        },
+        /// A call to [`VisionClient::query`].
+        Query { image: String, question: String },
+    }
+
+    /// Scriptable [`VisionClient`] for unit tests.
+    ///
+    /// Each operation is answered by the handler registered with the matching
+    /// `on_*` method. Calling an operation without a handler panics, so
+    /// unexpected calls surface as test failures. Every call is recorded and
+    /// can be inspected with [`MockVisionClient::calls`].
+    #[derive(Default)]
+    pub struct MockVisionClient {
+        points: Option<Handler<PointsResponse>>,
+        detect: Option<Handler<DetectResponse>>,
+        caption: Option<CaptionHandler>,
+        query: Option<Handler<QueryResponse>>,
+        calls: Mutex<Vec<MockCall>>,
+    }
+
+    impl MockVisionClient {
+        /// Create a mock without any handler registered.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answer [`VisionClient::points`] calls with `handler(image, object)`.
+        pub fn on_points<F>(mut self, handler: F) -> Self
+        where
+            F: Fn(&str, &str) -> Result<PointsResponse, Error> + Send + Sync + 'static,
+        {
+            self.points = Some(Box::new(handler));
+            self
+        }
+
+        /// Answer [`VisionClient::detect`] calls with `handler(image, object)`.
+        pub fn on_detect<F>(mut self, handler: F) -> Self
+        where
+            F: Fn(&str, &str) -> Result<DetectResponse, Error> + Send + Sync + 'static,
+        {
+            self.detect = Some(Box::new(handler));
+            self
+        }
+
+        /// Answer [`VisionClient::caption`] calls with `handler(image, length)`.
+        pub fn on_caption<F>(mut self, handler: F) -> Self
+        where
+            F: Fn(&str, Option<CaptionLength>) -> Result<CaptionResponse, Error>
+                + Send
+                + Sync
+                + 'static,
+        {
+            self.caption = Some(Box::new(handler));
+            self
+        }
+
+        /// Answer [`VisionClient::query`] calls with `handler(image, question)`.
+        pub fn on_query<F>(mut self, handler: F) -> Self
+        where
+            F: Fn(&str, &str) -> Result<QueryResponse, Error> + Send + Sync + 'static,
+        {
+            self.query = Some(Box::new(handler));
+            self
+        }
+
+        /// Return every call received so far, in order.
+        pub fn calls(&self) -> Vec<MockCall> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn record(&self, call: MockCall) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl std::fmt::Debug for MockVisionClient {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("MockVisionClient")
+                .field("calls", &self.calls())
+                .finish_non_exhaustive()
+        }
+    }
+
+    fn missing(operation: &str) -> ! {
+        panic!("MockVisionClient: no handler registered for `{operation}`")
+    }
+
+    #[async_trait]
+    impl VisionClient for MockVisionClient {
+        async fn points(&self, image: String, object: String) -> Result<PointsResponse, Error> {
+            self.record(MockCall::Points {
+                image: image.clone(),
+                object: object.clone(),
+            });
+            let handler = self.points.as_ref().unwrap_or_else(|| missing("points"));
+            handler(&image, &object)
+        }
+
+        async fn detect(&self, image: String, object: String) -> Result<DetectResponse, Error> {
+            self.record(MockCall::Detect {
+                image: image.clone(),
+                object: object.clone(),
+            });
+            let handler = self.detect.as_ref().unwrap_or_else(|| missing("detect"));
+            handler(&image, &object)
+        }
+
+        async fn caption(
+            &self,
+            image: String,
+            length: Option<CaptionLength>,
+        ) -> Result<CaptionResponse, Error> {
+            self.record(MockCall::Caption {
+                image: image.clone(),
+                length,
+            });
+            let handler = self.caption.as_ref().unwrap_or_else(|| missing("caption"));
+            handler(&image, length)
+        }
+
+        async fn query(&self, image: String, question: String) -> Result<QueryResponse, Error> {
+            self.record(MockCall::Query {
+                image: image.clone(),
+                question: question.clone(),
+            });
+            let handler = self.query.as_ref().unwrap_or_else(|| missing("query"));
+            handler(&image, &question)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_moondream_as_dyn_vision_client() {
+        let server = MockServer::start().await;
+
+        let body = serde_json::json!({
+            "request_id": "abc",
+            "points": [{"x": 0.5, "y": 0.5}],
+            "count": 1
+        });
+
+        Mock::given(method("POST"))
+            .and(path("/point"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&body))
+            .mount(&server)
+            .await;
+
+        let client: Arc<dyn VisionClient> =
+            Arc::new(MoonDream::remote("token").with_endpoint(server.uri()));
+
+        let resp = client
+            .points("data:image/png;base64,AAA".into(), "object".into())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            resp.points,
+            vec![Point {
+                x: 0.5,
+                y: 0.5,
+                confidence: None
+            }]
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_mock_vision_client() {
+        use crate::ResultFlags;
+
+        let mock = MockVisionClient::new().on_query(|_, question| {
+            Ok(QueryResponse {
+                request_id: None,
+                answer: format!("echo: {question}"),
+                flags: ResultFlags::empty(),
+                language: None,
+            })
+        });
+
+        let resp = mock
+            .query("image".into(), "What is this?".into())
+            .await
+            .unwrap();
+
+        assert_eq!(resp.answer, "echo: What is this?");
+        assert_eq!(
+            mock.calls(),
+            vec![MockCall::Query {
+                image: "image".into(),
+                question: "What is this?".into(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! JSON types of the Moondream API, as sent on the wire.
+//!
+//! Proxies, gateways and mock servers can (de)serialize requests and
+//! responses with these types without depending on the behavior of
+//! [`MoonDream`](crate::MoonDream). The client builds its request bodies
+//! from them.
+//!
+//! This module follows semver on its own: new fields are only added as
+//! `Option`s that are skipped when `None`, so JSON written by an older
+//! version still reads with a newer one. Renaming or removing a field, or
+//! changing its type, is a breaking change noted in the changelog. The
+//! response types of the high-level client may carry extra client-side
+//! fields, such as [`ResultFlags`](crate::ResultFlags); the types here do
+//! not.
+//!
+//! Request types borrow from the JSON they are read from, to avoid copying
+//! inline images:
+//!
+//! ```
+//! use moondream::wire::QueryRequest;
+//!
+//! let body = br#"{"image_url": "data:image/png;base64,AAAA", "question": "What is this?"}"#;
+//! let request: QueryRequest = serde_json::from_slice(body)?;
+//! assert_eq!(request.question, "What is this?");
+//! # Ok::<(), serde_json::Error>(())
+//! ```
+
+pub use crate::segment::{Polygon, SegmentResponse};
+pub use crate::{CaptionLength, DetectResponse, DetectionObject, Point, PointsResponse};
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Body of a `/caption` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptionRequest<'a> {
+    /// Image URL or `data:` URI.
+    #[serde(borrow)]
+    pub image_url: Cow<'a, str>,
+    /// Length of the caption, `normal` when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<CaptionLength>,
+}
+
+/// Body of a single-image `/query` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryRequest<'a> {
+    /// Image URL or `data:` URI.
+    #[serde(borrow)]
+    pub image_url: Cow<'a, str>,
+    /// Question about the image.
+    #[serde(borrow)]
+    pub question: Cow<'a, str>,
+}
+
+/// Body of a multi-image `/query` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiQueryRequest<'a> {
+    /// Image URLs or `data:` URIs.
+    #[serde(borrow)]
+    pub image_urls: Vec<Cow<'a, str>>,
+    /// Question about the images.
+    #[serde(borrow)]
+    pub question: Cow<'a, str>,
+}
+
+/// Body of a `/point`, `/detect` or `/segment` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectRequest<'a> {
+    /// Image URL or `data:` URI.
+    #[serde(borrow)]
+    pub image_url: Cow<'a, str>,
+    /// Object to find.
+    #[serde(borrow)]
+    pub object: Cow<'a, str>,
+    /// Ask for confidence scores.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<bool>,
+}
+
+/// Response of the `/caption` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct CaptionResponse {
+    /// Unique request identifier.
+    pub request_id: Option<String>,
+    /// Caption of the image.
+    pub caption: String,
+}
+
+/// Response of the `/query` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct QueryResponse {
+    /// Unique request identifier.
+    pub request_id: Option<String>,
+    /// Answer to the question.
+    pub answer: String,
+}
+
+impl From<CaptionResponse> for crate::CaptionResponse {
+    fn from(response: CaptionResponse) -> Self {
+        Self {
+            request_id: response.request_id,
+            caption: response.caption,
+            flags: crate::ResultFlags::empty(),
+            language: None,
+        }
+    }
+}
+
+impl From<QueryResponse> for crate::QueryResponse {
+    fn from(response: QueryResponse) -> Self {
+        Self {
+            request_id: response.request_id,
+            answer: response.answer,
+            flags: crate::ResultFlags::empty(),
+            language: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Call;
+    use serde_json::json;
+
+    #[test]
+    fn test_client_bodies_match_wire_types() {
+        let body = Call::Caption {
+            image_url: "img",
+            length: CaptionLength::Short,
+        }
+        .body();
+        assert_eq!(body, json!({"image_url": "img", "length": "short"}));
+        let body = body.to_string();
+        let request: CaptionRequest = serde_json::from_str(&body).unwrap();
+        assert_eq!(request.length, Some(CaptionLength::Short));
+
+        let body = Call::Detect {
+            image_url: "img",
+            object: "cat",
+        }
+        .body();
+        assert_eq!(body, json!({"image_url": "img", "object": "cat"}));
+    }
+
+    #[test]
+    fn test_requests_borrow_from_input() {
+        let body =
+            br#"{"image_url": "data:image/png;base64,AAAA", "object": "cat", "confidence": true}"#;
+        let request: ObjectRequest = serde_json::from_slice(body).unwrap();
+        assert!(matches!(request.image_url, Cow::Borrowed(_)));
+        assert_eq!(request.confidence, Some(true));
+    }
+
+    #[test]
+    fn test_client_response_from_wire() {
+        let wire: QueryResponse =
+            serde_json::from_str(r#"{"request_id": "q", "answer": "yes"}"#).unwrap();
+        let response = crate::QueryResponse::from(wire);
+        assert_eq!(response.answer, "yes");
+        assert!(response.flags.is_empty());
+    }
+}